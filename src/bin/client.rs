@@ -2,6 +2,7 @@ use bevy::window::close_on_esc;
 use bevy_debug_text_overlay::OverlayPlugin;
 use bevy_discord_presence::config::{RPCConfig, RPCPlugin};
 use bevy_embedded_assets::EmbeddedAssetPlugin;
+use clap::Parser;
 use spacegame::*;
 
 use bevy::render::texture::ImageSettings;
@@ -9,14 +10,22 @@ use bevy_inspector_egui::WorldInspectorPlugin;
 use bevy_prototype_debug_lines::DebugLinesPlugin;
 use bevy_rapier3d::prelude::*;
 use client::controller::{Controlled, ControllerPlugin};
+use iyes_loopless::prelude::IntoConditionalSystem;
 use resources::keybindings::Keybindings;
 use spacegame::binding::BindingPlugin;
+use spacegame::chunk_collider::ChunkColliderPlugin;
+use spacegame::client::chat::{ChatFocus, ChatInputLabel, ChatPlugin};
+use spacegame::client::chunk_mesh::ChunkMeshPlugin;
+use spacegame::client::config::{ClientArgs, ClientSettings};
+use spacegame::client::faction::FactionPlugin;
 use spacegame::client::highlight::HighlightPlugin;
 use spacegame::client::model::character::Character;
 use spacegame::client::networking::ClientNetworkingPlugin;
 use spacegame::client::player::PlayerPlugin;
 use spacegame::client::sync::SyncPlugin;
-use spacegame::model::block::BlockType;
+use spacegame::model::block_definitions::{
+    BlockCollider, BlockDefinitions, BlockDefinitionsPlugin,
+};
 use spacegame::shared::entities::player::PlayerBundle;
 
 use crate::resources::block_registry::BlockRegistry;
@@ -28,7 +37,16 @@ use crate::client::controller::LookingAt;
 use spacegame::client::*;
 
 fn main() {
+    let settings = match ClientSettings::load(ClientArgs::parse()) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
     App::new()
+        .insert_resource(settings)
         .insert_resource(ImageSettings::default_nearest())
         .insert_resource(RapierConfiguration {
             gravity: Vect::ZERO,
@@ -44,7 +62,12 @@ fn main() {
         .add_plugins_with(DefaultPlugins, |group| {
             group.add_before::<bevy::asset::AssetPlugin, _>(EmbeddedAssetPlugin)
         })
-        .add_system(close_on_esc)
+        // Escape closes the chat box when it is open, so only close the window otherwise
+        .add_system(
+            close_on_esc
+                .run_if(|chat_focus: Res<ChatFocus>| !chat_focus.0)
+                .before(ChatInputLabel),
+        )
         .add_plugin(MaterialPlugin::<CubemapMaterial>::default())
         .add_plugin(OverlayPlugin::default())
         .add_plugin(WorldInspectorPlugin::new())
@@ -58,12 +81,18 @@ fn main() {
         .add_plugin(ClientNetworkingPlugin)
         .add_plugin(SyncPlugin)
         // Insert game
+        .add_plugin(BlockDefinitionsPlugin)
         .add_startup_system(client_setup)
+        .add_system(register_block_assets)
+        .add_plugin(ChunkMeshPlugin)
+        .add_plugin(ChunkColliderPlugin)
         .add_plugin(ControllerPlugin)
         .add_plugin(HighlightPlugin)
         .add_system(shared::ship::despawn_ship)
         .add_system(asset_loaded)
         .add_plugin(PlayerPlugin)
+        .add_plugin(ChatPlugin)
+        .add_plugin(FactionPlugin)
         .add_plugin(BindingPlugin)
         .add_plugin(RPCPlugin(RPCConfig {
             app_id: 1044938793129619517,
@@ -77,23 +106,10 @@ fn client_setup(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     asset_server: Res<AssetServer>,
-    mut block_registry: ResMut<BlockRegistry>,
 ) {
     // Spawn UI Camera
     // commands.spawn_bundle(Camera2dBundle::default());
 
-    let hull_handle = asset_server.load("hull.png");
-
-    // Register block types
-    let hull_material = StandardMaterial {
-        base_color_texture: Some(hull_handle.clone()),
-        ..default()
-    };
-    let hull_material_handle = materials.add(hull_material);
-    block_registry.register_material(BlockType::Hull, hull_material_handle);
-    let hull_mesh_handle = meshes.add(Mesh::from(shape::Cube { size: 1.0 }));
-    block_registry.register_mesh(BlockType::Hull, hull_mesh_handle);
-
     // Plane
     commands.spawn_bundle(PbrBundle {
         mesh: meshes.add(Mesh::from(shape::Plane { size: 100. })),
@@ -193,3 +209,37 @@ fn client_setup(
         image_handle: skybox_handle,
     });
 }
+
+/// Create the meshes and materials of the block types whenever their definitions are (re)loaded.
+fn register_block_assets(
+    definitions: Res<BlockDefinitions>,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut block_registry: ResMut<BlockRegistry>,
+) {
+    if !definitions.is_changed() {
+        return;
+    }
+
+    for definition in definitions.iter() {
+        let texture = asset_server.load(definition.texture.as_str());
+        block_registry.register_texture(definition.id, texture.clone());
+        let material = StandardMaterial {
+            base_color_texture: Some(texture),
+            ..default()
+        };
+        block_registry.register_material(definition.id, materials.add(material));
+
+        let mesh = match definition.collider {
+            BlockCollider::Cuboid {
+                half_extents: (x, y, z),
+            } => Mesh::from(shape::Box::new(x * 2., y * 2., z * 2.)),
+            BlockCollider::Ball { radius } => Mesh::from(shape::UVSphere {
+                radius,
+                ..default()
+            }),
+        };
+        block_registry.register_mesh(definition.id, meshes.add(mesh));
+    }
+}
@@ -1,93 +1,98 @@
-use std::thread::{self, Thread};
-use std::time::Duration;
-
+use bevy::app::ScheduleRunnerSettings;
+use bevy::asset::AssetPlugin;
 use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
+use bevy::hierarchy::HierarchyPlugin;
 use bevy::log::{LogPlugin, LogSettings};
-use bevy::render::settings::WgpuSettings;
+use bevy::transform::TransformPlugin;
+use clap::Parser;
 
-use bevy::winit::WinitWindows;
-use bevy_framepace::{FramepacePlugin, FramepaceSettings, Limiter};
 use spacegame::*;
 
 use bevy_rapier3d::prelude::*;
-use resources::keybindings::Keybindings;
 
 use spacegame::binding::BindingPlugin;
-use spacegame::model::block_map::BlockRotation;
+use spacegame::chunk_collider::ChunkColliderPlugin;
+use spacegame::model::block_definitions::BlockDefinitionsPlugin;
+use spacegame::server::access::AccessPlugin;
+use spacegame::server::chat::ChatPlugin;
+use spacegame::server::command::CommandPlugin;
+use spacegame::server::config::{ServerArgs, ServerSettings};
+use spacegame::server::console::ConsolePlugin;
+use spacegame::server::faction::FactionPlugin;
+use spacegame::server::lifecycle::LifecyclePlugin;
+use spacegame::server::merge::MergePlugin;
 use spacegame::server::networking::ServerNetworkingPlugin;
+use spacegame::server::ownership::OwnershipPlugin;
+use spacegame::server::physics::PhysicsPlugin;
+use spacegame::server::player::PlayerPlugin;
+use spacegame::server::propulsion::PropulsionPlugin;
+use spacegame::server::roles::RolesPlugin;
 use spacegame::server::ship::ShipPlugin;
 use spacegame::server::sync::SyncPlugin;
+use spacegame::server::tick::{TickPlugin, TickSettings};
+use spacegame::server::world::WorldPlugin;
 
-use crate::model::block::{BlockBundle, BlockType};
-use crate::model::block_map::{BlockMap, BlockPosition};
-use crate::model::ship::ShipBundle;
 use crate::resources::block_registry::BlockRegistry;
 
 use spacegame::server::*;
 
 fn main() {
+    let settings = match ServerSettings::load(ServerArgs::parse()) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let tick_settings = TickSettings::new(settings.tick_rate);
+
     App::new()
         .insert_resource(RapierConfiguration {
             gravity: Vect::ZERO,
-            ..default()
-        })
-        .insert_resource(Keybindings::default())
-        .insert_resource(WgpuSettings {
-            backends: None,
+            timestep_mode: TimestepMode::Fixed {
+                dt: tick_settings.delta_seconds(),
+                substeps: 1,
+            },
             ..default()
         })
         .insert_resource(LogSettings {
-            filter: "info,wgpu_core=warn,wgpu_hal=warn,spacegame=trace".into(),
+            filter: settings.log_filter.clone(),
             level: bevy::log::Level::TRACE,
         })
-        .add_plugins(DefaultPlugins)
-        .add_system_to_stage(CoreStage::Last, limit_ticks)
+        .insert_resource(ScheduleRunnerSettings::run_loop(tick_settings.budget()))
+        .insert_resource(tick_settings)
+        .insert_resource(settings)
+        .add_plugins(MinimalPlugins)
+        .add_plugin(LogPlugin)
+        .add_plugin(TransformPlugin)
+        .add_plugin(HierarchyPlugin)
+        .add_plugin(AssetPlugin)
+        // Rapier needs the mesh assets to exist to build colliders from meshes
+        .add_asset::<Mesh>()
+        .add_plugin(TickPlugin)
         .add_plugin(LogDiagnosticsPlugin::default())
         .add_plugin(FrameTimeDiagnosticsPlugin::default())
         .insert_resource(BlockRegistry::new())
+        .add_plugin(BlockDefinitionsPlugin)
         .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
-        .add_startup_system(server_setup)
         .add_system(shared::ship::despawn_ship)
         .add_plugin(ServerNetworkingPlugin)
+        .add_plugin(LifecyclePlugin)
+        .add_plugin(CommandPlugin)
+        .add_plugin(ConsolePlugin)
+        .add_plugin(AccessPlugin)
+        .add_plugin(RolesPlugin)
+        .add_plugin(FactionPlugin)
+        .add_plugin(ChatPlugin)
         .add_plugin(SyncPlugin)
         .add_plugin(ShipPlugin)
+        .add_plugin(PhysicsPlugin)
+        .add_plugin(ChunkColliderPlugin)
+        .add_plugin(PropulsionPlugin)
+        .add_plugin(MergePlugin)
+        .add_plugin(OwnershipPlugin)
+        .add_plugin(WorldPlugin)
+        .add_plugin(PlayerPlugin)
         .add_plugin(BindingPlugin)
         .run();
 }
-
-fn limit_ticks(time: Res<Time>, mut _windows: NonSendMut<WinitWindows>) {
-    let d = Duration::from_millis(1_000 / 64);
-	if d > time.delta() {
-		thread::sleep(d - time.delta());
-	}
-}
-
-fn server_setup(mut commands: Commands) {
-    // Spawn a single ship
-    let mut block_map = BlockMap::new();
-    commands
-        .spawn()
-        .with_children(|parent| {
-            let block_position = BlockPosition::splat(0);
-            let block_rotation = BlockRotation::default();
-
-            let block_entity = parent
-                .spawn_bundle(BlockBundle::new(
-                    BlockType::Hull,
-                    block_position,
-                    block_rotation,
-                ))
-                .id();
-
-            block_map.set(
-                block_entity,
-                BlockType::Hull,
-                block_position,
-                block_rotation,
-            );
-        })
-        .insert_bundle(ShipBundle {
-            block_map,
-            ..default()
-        });
-}
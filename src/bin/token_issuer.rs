@@ -0,0 +1,110 @@
+use std::{
+    net::{SocketAddr, TcpListener},
+    path::PathBuf,
+};
+
+use clap::Parser;
+
+use spacegame::shared::networking::{
+    auth::{write_token, PrivateKey, TokenIssuer},
+    identity::PlayerIdentity,
+    user_data::{validate_player_name, ConnectUserData},
+};
+use spacegame::{DEFAULT_PORT, DEFAULT_TOKEN_PORT, PROTOCOL_ID, PROTOCOL_VERSION};
+
+/// Hands out connect tokens for servers sharing its private key.
+///
+/// Runs as a service by default. With `--identity`, `--name` and `--out` it writes a single token
+/// to a file instead, which the client can load with `--token`.
+#[derive(Parser, Debug)]
+#[command(
+    name = "token_issuer",
+    about = "Connect token issuer for the space game"
+)]
+struct IssuerArgs {
+    /// Path of the key shared with the servers
+    #[arg(long, default_value = "server.key")]
+    private_key: PathBuf,
+    /// Servers the tokens are valid for, can be given multiple times
+    #[arg(long = "server")]
+    servers: Vec<SocketAddr>,
+    /// Seconds a token can be used to connect
+    #[arg(long, default_value_t = 300)]
+    expire_seconds: u64,
+    /// Address the service listens on
+    #[arg(long)]
+    listen: Option<SocketAddr>,
+    /// Identity to issue a single token for, in hex
+    #[arg(long, requires_all = ["name", "out"])]
+    identity: Option<String>,
+    #[arg(long)]
+    name: Option<String>,
+    /// Server password to put in the single token
+    #[arg(long)]
+    password: Option<String>,
+    /// File to write the single token to
+    #[arg(long)]
+    out: Option<PathBuf>,
+}
+
+fn main() {
+    let args = IssuerArgs::parse();
+
+    let private_key = PrivateKey::load_or_create(&args.private_key).unwrap_or_else(|e| {
+        exit(format!(
+            "Could not load private key from {}: {}",
+            args.private_key.display(),
+            e
+        ))
+    });
+    let server_addresses = if args.servers.is_empty() {
+        vec![SocketAddr::from(([127, 0, 0, 1], DEFAULT_PORT))]
+    } else {
+        args.servers
+    };
+
+    let issuer = TokenIssuer {
+        private_key,
+        protocol_id: PROTOCOL_ID,
+        server_addresses,
+        expire_seconds: args.expire_seconds,
+    };
+
+    if let (Some(identity), Some(name), Some(out)) = (args.identity, args.name, args.out) {
+        let identity = identity
+            .parse::<PlayerIdentity>()
+            .unwrap_or_else(|e| exit(format!("Invalid identity: {}", e)));
+        if let Err(e) = validate_player_name(&name) {
+            exit(format!("Invalid name: {}", e));
+        }
+
+        let token = issuer
+            .issue(&ConnectUserData {
+                version: PROTOCOL_VERSION,
+                identity,
+                name,
+                password: args.password,
+            })
+            .unwrap_or_else(|e| exit(e.to_string()));
+        write_token(&out, &token)
+            .unwrap_or_else(|e| exit(format!("Could not write {}: {}", out.display(), e)));
+        println!("Wrote connect token to {}", out.display());
+        return;
+    }
+
+    let listen_addr = args
+        .listen
+        .unwrap_or_else(|| SocketAddr::from(([127, 0, 0, 1], DEFAULT_TOKEN_PORT)));
+    let listener = TcpListener::bind(listen_addr)
+        .unwrap_or_else(|e| exit(format!("Could not listen on {}: {}", listen_addr, e)));
+    println!(
+        "Issuing connect tokens for {:?} on {}",
+        issuer.server_addresses, listen_addr
+    );
+    issuer.serve(listener);
+}
+
+fn exit(message: String) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
@@ -0,0 +1,215 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::{
+    default, AssetServer, Color, Commands, Component, EventReader, Input, KeyCode,
+    ParallelSystemDescriptorCoercion, Plugin, PositionType, Query, ReceivedCharacter, Res, ResMut,
+    Style, SystemLabel, Text, TextBundle, TextSection, TextStyle, UiRect, Val, With,
+};
+use iyes_loopless::prelude::IntoConditionalSystem;
+use spacegame_core::message::ClientMessageOutQueue;
+
+use crate::shared::events::chat::{AnnouncementEvent, ChatMessageEvent, MAX_CHAT_MESSAGE_LENGTH};
+
+use super::connection::ConnectionState;
+
+/// How many of the most recent lines are shown.
+const VISIBLE_LINES: usize = 10;
+/// How many lines are kept around in total.
+const MAX_LOG_LINES: usize = 100;
+
+/// Whether the player is typing in the chat box.
+///
+/// Other systems that read the keyboard should ignore it while this is set.
+#[derive(Default)]
+pub struct ChatFocus(pub bool);
+
+#[derive(SystemLabel)]
+pub struct ChatInputLabel;
+
+#[derive(Default)]
+struct ChatLog {
+    lines: VecDeque<String>,
+}
+
+impl ChatLog {
+    fn push(&mut self, line: String) {
+        if self.lines.len() == MAX_LOG_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+}
+
+#[derive(Default)]
+struct ChatInput {
+    text: String,
+    /// Messages sent earlier, for recalling them with the arrow keys.
+    sent: Vec<String>,
+    /// The sent message currently recalled, as an index into `sent`.
+    recalled: Option<usize>,
+}
+
+#[derive(Component)]
+struct ChatText;
+
+pub struct ChatPlugin;
+
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<ChatFocus>()
+            .init_resource::<ChatLog>()
+            .init_resource::<ChatInput>()
+            .add_startup_system(setup_chat)
+            .add_system(chat_input.label(ChatInputLabel))
+            .add_system(on_chat_message.run_on_event::<ChatMessageEvent>())
+            .add_system(on_announcement.run_on_event::<AnnouncementEvent>())
+            .add_system(update_chat_text.after(ChatInputLabel));
+    }
+
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+}
+
+fn setup_chat(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let style = TextStyle {
+        font: asset_server.load("fonts/DejaVuSansMono.ttf"),
+        font_size: 16.,
+        color: Color::WHITE,
+    };
+
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text::from_sections([
+                TextSection::new("", style.clone()),
+                TextSection::new("", style),
+            ]),
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(8.),
+                    bottom: Val::Px(8.),
+                    ..default()
+                },
+                ..default()
+            },
+            ..default()
+        })
+        .insert(ChatText);
+}
+
+fn chat_input(
+    state: Res<ConnectionState>,
+    keys: Res<Input<KeyCode>>,
+    mut focus: ResMut<ChatFocus>,
+    mut input: ResMut<ChatInput>,
+    mut characters: EventReader<ReceivedCharacter>,
+    mut chat_queue: ResMut<ClientMessageOutQueue<ChatMessageEvent>>,
+) {
+    if !state.is_connected() {
+        focus.0 = false;
+        return;
+    }
+
+    if !focus.0 {
+        if keys.just_pressed(KeyCode::T) || keys.just_pressed(KeyCode::Return) {
+            focus.0 = true;
+            // Don't let the key that opened the chat end up in it
+            characters.clear();
+        } else if keys.just_pressed(KeyCode::Slash) {
+            focus.0 = true;
+            characters.clear();
+            input.text = String::from("/");
+        }
+        return;
+    }
+
+    for event in characters.iter() {
+        if !event.char.is_control() && input.text.chars().count() < MAX_CHAT_MESSAGE_LENGTH {
+            input.text.push(event.char);
+        }
+    }
+    if keys.just_pressed(KeyCode::Back) {
+        input.text.pop();
+    }
+
+    if keys.just_pressed(KeyCode::Up) && !input.sent.is_empty() {
+        let recalled = match input.recalled {
+            Some(index) => index.saturating_sub(1),
+            None => input.sent.len() - 1,
+        };
+        input.text = input.sent[recalled].clone();
+        input.recalled = Some(recalled);
+    }
+    if keys.just_pressed(KeyCode::Down) {
+        match input.recalled {
+            Some(index) if index + 1 < input.sent.len() => {
+                input.text = input.sent[index + 1].clone();
+                input.recalled = Some(index + 1);
+            }
+            Some(_) => {
+                input.text.clear();
+                input.recalled = None;
+            }
+            None => {}
+        }
+    }
+
+    if keys.just_pressed(KeyCode::Escape) {
+        input.text.clear();
+        input.recalled = None;
+        focus.0 = false;
+    }
+    if keys.just_pressed(KeyCode::Return) {
+        let message = std::mem::take(&mut input.text);
+        if !message.trim().is_empty() {
+            chat_queue.send(ChatMessageEvent {
+                sender_name: String::new(),
+                message: message.clone(),
+                client_id: 0,
+            });
+            input.sent.push(message);
+        }
+        input.recalled = None;
+        focus.0 = false;
+    }
+}
+
+fn on_chat_message(mut log: ResMut<ChatLog>, mut events: EventReader<ChatMessageEvent>) {
+    for event in events.iter() {
+        log.push(format!("<{}> {}", event.sender_name, event.message));
+    }
+}
+
+fn on_announcement(mut log: ResMut<ChatLog>, mut events: EventReader<AnnouncementEvent>) {
+    for event in events.iter() {
+        for line in event.message.lines() {
+            log.push(format!("[Server] {}", line));
+        }
+    }
+}
+
+fn update_chat_text(
+    focus: Res<ChatFocus>,
+    log: Res<ChatLog>,
+    input: Res<ChatInput>,
+    mut query: Query<&mut Text, With<ChatText>>,
+) {
+    if !(focus.is_changed() || log.is_changed() || input.is_changed()) {
+        return;
+    }
+
+    let mut text = query.single_mut();
+    let skip = log.lines.len().saturating_sub(VISIBLE_LINES);
+    text.sections[0].value = log
+        .lines
+        .iter()
+        .skip(skip)
+        .map(|line| format!("{}\n", line))
+        .collect();
+    text.sections[1].value = if focus.0 {
+        format!("> {}_", input.text)
+    } else {
+        String::new()
+    };
+}
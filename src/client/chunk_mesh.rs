@@ -0,0 +1,399 @@
+use bevy::prelude::{
+    default, AssetEvent, Assets, BuildChildren, Commands, Component, CoreStage,
+    DespawnRecursiveExt, Entity, EventReader, Image, Mesh, Mut, ParallelSystemDescriptorCoercion,
+    PbrBundle, Plugin, Query, Res, ResMut, Transform, Vec3,
+};
+use bevy::render::{
+    mesh::Indices,
+    render_resource::{AddressMode, FilterMode, PrimitiveTopology, SamplerDescriptor},
+    texture::ImageSampler,
+};
+use bevy::utils::{HashMap, HashSet};
+use iyes_loopless::prelude::IntoConditionalSystem;
+
+use crate::{
+    chunk_collider::ChunkLabels,
+    model::{
+        block::{BlockBundle, BlockType},
+        block_definitions::{block_definitions_loaded, BlockDefinitions},
+        block_map::{BlockFace, BlockMap, BlockPosition, BlockRotation},
+        chunk::{ChunkPosition, CHUNK_SIZE},
+    },
+    resources::block_registry::BlockRegistry,
+};
+
+/// Draws ships with one mesh per block type in each of their chunks, instead of a mesh per block.
+pub struct ChunkMeshPlugin;
+
+impl Plugin for ChunkMeshPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        // Whether a block is meshed or gets an entity depends on its definition
+        app.add_system_to_stage(
+            CoreStage::PostUpdate,
+            update_chunk_meshes
+                .run_if(block_definitions_loaded)
+                .label(ChunkLabels::Rebuild),
+        )
+        .add_system(repeat_block_textures);
+    }
+
+    fn name(&self) -> &str {
+        "chunk_mesh_plugin"
+    }
+}
+
+/// The faces of all blocks of one type in a chunk, relative to the origin of the chunk.
+///
+/// Every face is a quad of 4 vertices and 2 triangles. Texture coordinates count blocks, so
+/// textures repeat once per block across faces that were merged.
+#[derive(Clone, Default, Debug)]
+pub struct ChunkMesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+}
+
+impl ChunkMesh {
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// How many quads there are, after merging.
+    pub fn face_count(&self) -> usize {
+        self.indices.len() / 6
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Add a quad on the side of the blocks facing `face`, `width` blocks along the first axis
+    /// after the face's own axis and `height` blocks along the second, starting at `start`.
+    fn push_quad(&mut self, face: BlockFace, start: [i32; 3], width: i32, height: i32) {
+        let (axis, positive) = face_axis(face);
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+
+        let mut corner = [start[0] as f32, start[1] as f32, start[2] as f32];
+        corner[axis] += if positive { 0.5 } else { -0.5 };
+        corner[u] -= 0.5;
+        corner[v] -= 0.5;
+
+        let mut corners = [(0, 0), (width, 0), (width, height), (0, height)];
+        // Counter-clockwise seen from the side the face looks at
+        if !positive {
+            corners.reverse();
+        }
+
+        let normal: Vec3 = face.normal().into();
+        let first = self.positions.len() as u32;
+        for (du, dv) in corners {
+            let mut position = corner;
+            position[u] += du as f32;
+            position[v] += dv as f32;
+            self.positions.push(position);
+            self.normals.push(normal.to_array());
+            self.uvs.push([du as f32, dv as f32]);
+        }
+        self.indices
+            .extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+}
+
+impl From<ChunkMesh> for Mesh {
+    fn from(chunk_mesh: ChunkMesh) -> Self {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, chunk_mesh.positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, chunk_mesh.normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, chunk_mesh.uvs);
+        mesh.set_indices(Some(Indices::U32(chunk_mesh.indices)));
+        mesh
+    }
+}
+
+/// The axis a face looks along, and whether it looks towards the positive side of it.
+fn face_axis(face: BlockFace) -> (usize, bool) {
+    let normal = face.normal();
+    match (normal.x, normal.y, normal.z) {
+        (x, 0, 0) => (0, x > 0),
+        (0, y, 0) => (1, y > 0),
+        (_, _, z) => (2, z > 0),
+    }
+}
+
+/// Build the meshes of a chunk of `block_map`, one per block type.
+///
+/// Only blocks for which `is_full_cube` holds are meshed, the others are drawn on their own. Faces
+/// between two full cubes are hidden, also across chunk borders, and the visible faces of blocks
+/// of the same type are merged into as few rectangles as possible.
+pub fn mesh_chunk(
+    block_map: &BlockMap,
+    chunk_position: ChunkPosition,
+    is_full_cube: impl Fn(BlockType) -> bool,
+) -> HashMap<BlockType, ChunkMesh> {
+    let mut meshes: HashMap<BlockType, ChunkMesh> = HashMap::new();
+    let chunk = match block_map.chunk(&chunk_position) {
+        Some(chunk) => chunk,
+        None => return meshes,
+    };
+    let origin = chunk_position.origin();
+    let size = CHUNK_SIZE as usize;
+
+    for face in BlockFace::ALL {
+        let (axis, _) = face_axis(face);
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+
+        for layer in 0..CHUNK_SIZE {
+            // Which block type shows a face here, for each cell of this layer
+            let mut mask: Vec<Option<BlockType>> = vec![None; size * size];
+            for b in 0..CHUNK_SIZE {
+                for a in 0..CHUNK_SIZE {
+                    let mut local = [0; 3];
+                    local[axis] = layer;
+                    local[u] = a;
+                    local[v] = b;
+                    let local = BlockPosition::new(local[0], local[1], local[2]);
+
+                    let block = match chunk.get(local) {
+                        Some(block) if is_full_cube(block.block_type) => block,
+                        _ => continue,
+                    };
+                    let neighbour = BlockPosition::new(
+                        origin.x + local.x,
+                        origin.y + local.y,
+                        origin.z + local.z,
+                    )
+                    .neighbour(face);
+                    let hidden = block_map
+                        .get(&neighbour)
+                        .map_or(false, |neighbour| is_full_cube(neighbour.block_type));
+                    if !hidden {
+                        mask[a as usize + b as usize * size] = Some(block.block_type);
+                    }
+                }
+            }
+
+            // Grow each face as wide as it goes, then as high as the whole row goes
+            for b in 0..size {
+                let mut a = 0;
+                while a < size {
+                    let block_type = match mask[a + b * size] {
+                        Some(block_type) => block_type,
+                        None => {
+                            a += 1;
+                            continue;
+                        }
+                    };
+
+                    let mut width = 1;
+                    while a + width < size && mask[a + width + b * size] == Some(block_type) {
+                        width += 1;
+                    }
+                    let mut height = 1;
+                    while b + height < size
+                        && (a..a + width).all(|a| mask[a + (b + height) * size] == Some(block_type))
+                    {
+                        height += 1;
+                    }
+                    for row in b..b + height {
+                        mask[a + row * size..a + width + row * size].fill(None);
+                    }
+
+                    let mut start = [0; 3];
+                    start[axis] = layer;
+                    start[u] = a as i32;
+                    start[v] = b as i32;
+                    meshes.entry(block_type).or_default().push_quad(
+                        face,
+                        start,
+                        width as i32,
+                        height as i32,
+                    );
+                    a += width;
+                }
+            }
+        }
+    }
+    meshes
+}
+
+/// The entities drawing the chunks of a ship, one for each block type in the chunk.
+#[derive(Component, Default, Debug)]
+pub struct ChunkMeshes(HashMap<ChunkPosition, Vec<Entity>>);
+
+/// Rebuild the meshes of the chunks that changed, or of every chunk when the block assets were
+/// reloaded, along with the entities of the blocks that are not full cubes.
+fn update_chunk_meshes(
+    mut commands: Commands,
+    definitions: Res<BlockDefinitions>,
+    block_registry: Res<BlockRegistry>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut ships: Query<(Entity, &mut BlockMap, Option<&mut ChunkMeshes>)>,
+) {
+    let reloaded = definitions.is_changed() || block_registry.is_changed();
+    for (ship_entity, mut block_map, chunk_meshes) in ships.iter_mut() {
+        let mut inserted = None;
+        let chunk_meshes = match chunk_meshes {
+            Some(chunk_meshes) => chunk_meshes.into_inner(),
+            None => inserted.insert(ChunkMeshes::default()),
+        };
+
+        let dirty: HashSet<ChunkPosition> = if reloaded {
+            block_map
+                .chunks()
+                .map(|(position, _)| position)
+                .chain(chunk_meshes.0.keys().copied())
+                .collect()
+        } else {
+            block_map.dirty_chunks().collect()
+        };
+
+        for chunk_position in dirty {
+            for entity in chunk_meshes.0.remove(&chunk_position).unwrap_or_default() {
+                commands.entity(entity).despawn_recursive();
+            }
+
+            let mut entities = Vec::new();
+            let chunk_mesh = mesh_chunk(&block_map, chunk_position, |block_type| {
+                definitions.is_full_cube(block_type)
+            });
+            for (block_type, chunk_mesh) in chunk_mesh {
+                let entity = commands
+                    .spawn_bundle(PbrBundle {
+                        mesh: meshes.add(chunk_mesh.into()),
+                        material: block_registry.get_material(block_type).unwrap_or_default(),
+                        transform: Transform::from_translation(chunk_position.origin().into()),
+                        ..default()
+                    })
+                    .id();
+                commands.entity(ship_entity).add_child(entity);
+                entities.push(entity);
+            }
+            if !entities.is_empty() {
+                chunk_meshes.0.insert(chunk_position, entities);
+            }
+
+            update_block_entities(
+                &mut commands,
+                &definitions,
+                &block_registry,
+                &mut block_map,
+                ship_entity,
+                chunk_position,
+                reloaded,
+            );
+        }
+
+        if let Some(chunk_meshes) = inserted {
+            commands.entity(ship_entity).insert(chunk_meshes);
+        }
+    }
+}
+
+/// Give the blocks of a chunk that are not full cubes an entity of their own, and take it away
+/// from the ones that are. With `respawn` every entity is spawned again, to pick up new meshes
+/// and materials.
+fn update_block_entities(
+    commands: &mut Commands,
+    definitions: &BlockDefinitions,
+    block_registry: &BlockRegistry,
+    block_map: &mut Mut<BlockMap>,
+    ship_entity: Entity,
+    chunk_position: ChunkPosition,
+    respawn: bool,
+) {
+    let chunk = match block_map.chunk(&chunk_position) {
+        Some(chunk) => chunk,
+        None => return,
+    };
+    let origin = chunk_position.origin();
+    let blocks: Vec<_> = chunk
+        .blocks()
+        .map(|(local, block)| {
+            let position =
+                BlockPosition::new(origin.x + local.x, origin.y + local.y, origin.z + local.z);
+            (position, block.block_type, block.block_rotation)
+        })
+        .collect();
+
+    for (position, block_type, block_rotation) in blocks {
+        let needs_entity = !definitions.is_full_cube(block_type);
+        // Only borrow the block map mutably when something changes, so change detection keeps
+        // working
+        match block_map.entity(&position) {
+            Some(_) if needs_entity && !respawn => continue,
+            Some(_) => {
+                if let Some(entity) = block_map.take_entity(&position) {
+                    commands.entity(entity).despawn_recursive();
+                }
+            }
+            None if !needs_entity => continue,
+            None => {}
+        }
+
+        if needs_entity {
+            let entity = spawn_block_entity(
+                commands,
+                block_registry,
+                position,
+                block_type,
+                block_rotation,
+            );
+            commands.entity(ship_entity).add_child(entity);
+            block_map.set_entity(position, entity);
+        }
+    }
+}
+
+fn spawn_block_entity(
+    commands: &mut Commands,
+    block_registry: &BlockRegistry,
+    block_position: BlockPosition,
+    block_type: BlockType,
+    block_rotation: BlockRotation,
+) -> Entity {
+    let bundle = BlockBundle::new(block_type, block_position, block_rotation);
+    commands
+        .spawn_bundle(BlockBundle {
+            pbr_bundle: PbrBundle {
+                material: block_registry.get_material(block_type).unwrap_or_default(),
+                mesh: block_registry.get_mesh(block_type).unwrap_or_default(),
+                ..bundle.pbr_bundle
+            },
+            ..bundle
+        })
+        .id()
+}
+
+/// Make block textures repeat, so one texture covers each block of a merged face.
+fn repeat_block_textures(
+    block_registry: Res<BlockRegistry>,
+    mut images: ResMut<Assets<Image>>,
+    mut events: EventReader<AssetEvent<Image>>,
+) {
+    for event in events.iter() {
+        let handle = match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => handle,
+            AssetEvent::Removed { .. } => continue,
+        };
+        if !block_registry.is_texture(handle) {
+            continue;
+        }
+        // Changing the image sends another event, which must leave it alone
+        let repeats = images.get(handle).map_or(true, |image| {
+            !matches!(image.sampler_descriptor, ImageSampler::Default)
+        });
+        if repeats {
+            continue;
+        }
+        if let Some(image) = images.get_mut(handle) {
+            image.sampler_descriptor = ImageSampler::Descriptor(SamplerDescriptor {
+                address_mode_u: AddressMode::Repeat,
+                address_mode_v: AddressMode::Repeat,
+                mag_filter: FilterMode::Nearest,
+                min_filter: FilterMode::Nearest,
+                ..default()
+            });
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    shared::{
+        config::ConfigError,
+        networking::user_data::{validate_player_name, MAX_PASSWORD_LENGTH},
+    },
+    DEFAULT_TOKEN_PORT,
+};
+
+/// Command line arguments of the client binary.
+///
+/// Any argument given here overrides the value from the config file.
+#[derive(Parser, Debug)]
+#[command(name = "client", about = "Rust Space Game")]
+pub struct ClientArgs {
+    /// Path to a TOML config file
+    #[arg(long, short)]
+    pub config: Option<PathBuf>,
+    /// Server to connect to on startup, as `host:port`
+    #[arg(long, short)]
+    pub server: Option<String>,
+    /// Name shown to other players
+    #[arg(long, short)]
+    pub name: Option<String>,
+    /// Password of the server
+    #[arg(long)]
+    pub password: Option<String>,
+    /// Connect token file to use instead of asking the token service
+    #[arg(long)]
+    pub token: Option<PathBuf>,
+    /// Token service to request connect tokens from, as `host:port`
+    #[arg(long)]
+    pub token_service: Option<String>,
+    /// Connect without a token, only works with servers in unsecure mode
+    #[arg(long)]
+    pub unsecure: bool,
+}
+
+/// Resource holding the resolved client configuration.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ClientSettings {
+    /// Server to connect to on startup, if any.
+    pub server: Option<String>,
+    /// Name shown to other players.
+    pub name: String,
+    /// File the persistent player identity is kept in.
+    pub identity_file: PathBuf,
+    /// Password for servers that require one.
+    pub password: Option<String>,
+    /// Connect token to use, as written by the token issuer.
+    pub token_file: Option<PathBuf>,
+    /// Token service to request connect tokens from.
+    ///
+    /// Defaults to the server's host on [`DEFAULT_TOKEN_PORT`].
+    pub token_service: Option<String>,
+    /// Connect without a token, for servers running in unsecure mode.
+    pub unsecure: bool,
+    /// Seconds to wait for a connection attempt before giving up on it.
+    pub connect_timeout: f32,
+    /// How many attempts are made before the connection is considered failed.
+    pub connect_attempts: u32,
+    /// Seconds to wait before the first retry, doubled on every following retry.
+    pub retry_backoff: f32,
+    /// Upper bound for the wait between retries.
+    pub max_retry_backoff: f32,
+}
+
+impl Default for ClientSettings {
+    fn default() -> Self {
+        Self {
+            server: None,
+            name: format!("Player{:04}", fastrand::u16(..10000)),
+            identity_file: PathBuf::from("identity"),
+            password: None,
+            token_file: None,
+            token_service: None,
+            unsecure: false,
+            connect_timeout: 5.,
+            connect_attempts: 5,
+            retry_backoff: 1.,
+            max_retry_backoff: 16.,
+        }
+    }
+}
+
+impl ClientSettings {
+    /// Resolve the settings from the config file given in `args`, or `client.toml` if it
+    /// exists, then apply the command line overrides on top.
+    pub fn load(args: ClientArgs) -> Result<Self, ConfigError> {
+        let mut settings = match &args.config {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new("client.toml").exists() => Self::from_file(Path::new("client.toml"))?,
+            None => Self::default(),
+        };
+
+        if let Some(server) = args.server {
+            settings.server = Some(server);
+        }
+        if let Some(name) = args.name {
+            settings.name = name;
+        }
+        if let Some(password) = args.password {
+            settings.password = Some(password);
+        }
+        if let Some(token) = args.token {
+            settings.token_file = Some(token);
+        }
+        if let Some(token_service) = args.token_service {
+            settings.token_service = Some(token_service);
+        }
+        if args.unsecure {
+            settings.unsecure = true;
+        }
+
+        if validate_player_name(&settings.name).is_err() {
+            return Err(ConfigError::Invalid(
+                "name must be 3 to 16 letters, digits, '_' or '-'",
+            ));
+        }
+        if settings
+            .password
+            .as_ref()
+            .map_or(false, |password| password.len() > MAX_PASSWORD_LENGTH)
+        {
+            return Err(ConfigError::Invalid(
+                "password must be at most 64 bytes long",
+            ));
+        }
+        if settings.connect_attempts == 0 {
+            return Err(ConfigError::Invalid("connect_attempts must be at least 1"));
+        }
+
+        Ok(settings)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let contents = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        toml::from_str(&contents).map_err(ConfigError::Parse)
+    }
+
+    /// The token service to ask for a token to connect to `server_addr`.
+    pub fn token_service_addr(&self, server_addr: SocketAddr) -> String {
+        match &self.token_service {
+            Some(token_service) => token_service.clone(),
+            None => SocketAddr::new(server_addr.ip(), DEFAULT_TOKEN_PORT).to_string(),
+        }
+    }
+
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_secs_f32(self.connect_timeout)
+    }
+
+    /// How long to wait before making attempt number `attempt`, counting from 0. The first
+    /// attempt is made right away, after that the wait doubles with every attempt.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        if attempt == 0 {
+            return Duration::ZERO;
+        }
+        let backoff = self.retry_backoff * 2f32.powi(attempt as i32 - 1);
+        Duration::from_secs_f32(backoff.min(self.max_retry_backoff))
+    }
+}
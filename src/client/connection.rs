@@ -0,0 +1,480 @@
+use std::{
+    net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket},
+    time::{Instant, SystemTime},
+};
+
+use bevy::{
+    prelude::{
+        warn, Commands, EventReader, EventWriter, Input, KeyCode, ParallelSystemDescriptorCoercion,
+        Plugin, ReceivedCharacter, Res, ResMut, SystemLabel,
+    },
+    tasks::{AsyncComputeTaskPool, Task},
+};
+use bevy_debug_text_overlay::screen_print;
+use bevy_renet::renet::{ClientAuthentication, ConnectToken, RenetClient, RenetConnectionConfig};
+use futures_lite::future;
+use iyes_loopless::prelude::IntoConditionalSystem;
+use spacegame_core::message::ClientId;
+
+use crate::{
+    shared::{
+        events::connection::{DisconnectNoticeEvent, DisconnectReason},
+        networking::{
+            auth::{load_token, request_token},
+            identity::PlayerIdentity,
+            user_data::ConnectUserData,
+        },
+    },
+    DEFAULT_PORT, PROTOCOL_ID, PROTOCOL_VERSION,
+};
+
+use super::config::ClientSettings;
+
+/// Ask the client to connect to a server, dropping any current connection.
+pub struct ConnectRequest {
+    pub server_addr: SocketAddr,
+}
+
+/// Ask the client to drop the current connection.
+pub struct DisconnectRequest;
+
+#[derive(Debug, Clone)]
+pub enum ConnectionState {
+    Disconnected,
+    /// Waiting for the token service to hand out a connect token for the attempt.
+    RequestingToken(ConnectAttempt),
+    Connecting(ConnectAttempt),
+    Connected(SocketAddr),
+    Failed {
+        server_addr: SocketAddr,
+        reason: String,
+    },
+}
+
+impl ConnectionState {
+    pub fn is_connected(&self) -> bool {
+        matches!(self, ConnectionState::Connected(_))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConnectAttempt {
+    pub server_addr: SocketAddr,
+    /// How many attempts have failed so far.
+    pub failed_attempts: u32,
+    /// When the attempt in progress was started, `None` while waiting to retry.
+    pub started_at: Option<Instant>,
+    pub next_attempt_at: Instant,
+}
+
+impl ConnectAttempt {
+    fn new(server_addr: SocketAddr) -> Self {
+        Self {
+            server_addr,
+            failed_attempts: 0,
+            started_at: None,
+            next_attempt_at: Instant::now(),
+        }
+    }
+}
+
+/// The client id used for every unsecure connection made during this session.
+pub struct SessionClientId(pub ClientId);
+
+/// The reason the server gave for dropping us, if it told us before doing so.
+struct DisconnectNotice(Option<(DisconnectReason, Option<String>)>);
+
+impl DisconnectNotice {
+    /// Describe why the connection ended, and whether we should try to reconnect.
+    fn take(&mut self, fallback: String) -> (String, bool) {
+        match self.0.take() {
+            Some((reason, Some(message))) => (
+                format!("{}: {}", reason, message),
+                reason.should_reconnect(),
+            ),
+            Some((reason, None)) => (reason.to_string(), reason.should_reconnect()),
+            None => (fallback, true),
+        }
+    }
+}
+
+/// The connect token being requested from the token service, off the main thread since it may
+/// take up to the connect timeout.
+#[derive(Default)]
+struct TokenRequest(Option<Task<Result<ConnectToken, String>>>);
+
+/// Connect and disconnect requests replace the state, so they run before `update_connection` to
+/// keep it from starting a client for an attempt that was just replaced.
+#[derive(SystemLabel)]
+enum ConnectionLabels {
+    Requests,
+}
+
+/// The server address typed into the connect prompt.
+struct AddressInput(String);
+
+pub struct ConnectionPlugin;
+
+impl Plugin for ConnectionPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        if !app.world.contains_resource::<ClientSettings>() {
+            app.insert_resource(ClientSettings::default());
+        }
+        let settings = app.world.resource::<ClientSettings>();
+        let address = settings.server.clone().unwrap_or_default();
+        let identity = match PlayerIdentity::load_or_create(&settings.identity_file) {
+            Ok(identity) => identity,
+            Err(e) => {
+                warn!(
+                    "Could not load identity from {}: {}, using a temporary one",
+                    settings.identity_file.display(),
+                    e
+                );
+                PlayerIdentity::random()
+            }
+        };
+
+        app.add_event::<ConnectRequest>()
+            .add_event::<DisconnectRequest>()
+            .insert_resource(ConnectionState::Disconnected)
+            .insert_resource(SessionClientId(fastrand::u64(1..u64::MAX)))
+            .insert_resource(DisconnectNotice(None))
+            .init_resource::<TokenRequest>()
+            .insert_resource(AddressInput(address))
+            .insert_resource(identity)
+            .add_startup_system(connect_on_startup)
+            .add_system(on_connect_request.label(ConnectionLabels::Requests))
+            .add_system(on_disconnect_request.label(ConnectionLabels::Requests))
+            .add_system(on_disconnect_notice.run_on_event::<DisconnectNoticeEvent>())
+            .add_system(update_connection.after(ConnectionLabels::Requests))
+            .add_system(connect_prompt);
+    }
+
+    fn name(&self) -> &str {
+        "connection_plugin"
+    }
+}
+
+/// Parse a server address, accepting both `host:port` and a plain ip without port.
+pub fn resolve_server_address(address: &str) -> Option<SocketAddr> {
+    let address = address.trim();
+    if let Ok(ip) = address.parse::<IpAddr>() {
+        return Some(SocketAddr::new(ip, DEFAULT_PORT));
+    }
+    address.to_socket_addrs().ok()?.next()
+}
+
+fn connect_on_startup(
+    settings: Res<ClientSettings>,
+    mut connect_requests: EventWriter<ConnectRequest>,
+) {
+    if let Some(address) = &settings.server {
+        match resolve_server_address(address) {
+            Some(server_addr) => connect_requests.send(ConnectRequest { server_addr }),
+            None => screen_print!("Could not resolve server address {}", address),
+        }
+    }
+}
+
+fn on_connect_request(
+    mut commands: Commands,
+    mut state: ResMut<ConnectionState>,
+    mut notice: ResMut<DisconnectNotice>,
+    mut token_request: ResMut<TokenRequest>,
+    client: Option<ResMut<RenetClient>>,
+    mut events: EventReader<ConnectRequest>,
+) {
+    if let Some(event) = events.iter().last() {
+        if let Some(mut client) = client {
+            client.disconnect();
+            commands.remove_resource::<RenetClient>();
+        }
+        notice.0 = None;
+        token_request.0 = None;
+        *state = ConnectionState::Connecting(ConnectAttempt::new(event.server_addr));
+    }
+}
+
+fn on_disconnect_notice(
+    mut notice: ResMut<DisconnectNotice>,
+    mut events: EventReader<DisconnectNoticeEvent>,
+) {
+    for event in events.iter() {
+        notice.0 = Some((event.reason, event.message.clone()));
+    }
+}
+
+fn on_disconnect_request(
+    mut commands: Commands,
+    mut state: ResMut<ConnectionState>,
+    mut token_request: ResMut<TokenRequest>,
+    client: Option<ResMut<RenetClient>>,
+    mut events: EventReader<DisconnectRequest>,
+) {
+    if events.iter().last().is_some() {
+        if let Some(mut client) = client {
+            client.disconnect();
+            commands.remove_resource::<RenetClient>();
+        }
+        token_request.0 = None;
+        *state = ConnectionState::Disconnected;
+    }
+}
+
+fn update_connection(
+    mut commands: Commands,
+    settings: Res<ClientSettings>,
+    session_client_id: Res<SessionClientId>,
+    identity: Res<PlayerIdentity>,
+    mut state: ResMut<ConnectionState>,
+    mut notice: ResMut<DisconnectNotice>,
+    mut token_request: ResMut<TokenRequest>,
+    client: Option<Res<RenetClient>>,
+) {
+    let now = Instant::now();
+    let next_state = match &mut *state {
+        ConnectionState::RequestingToken(attempt) => match &mut token_request.0 {
+            Some(task) => future::block_on(future::poll_once(task)).map(|token| {
+                token_request.0 = None;
+                let authentication = token.map(|connect_token| {
+                    (
+                        connect_token.client_id,
+                        ClientAuthentication::Secure { connect_token },
+                    )
+                });
+                let mut attempt = attempt.clone();
+                start_client(&mut commands, &settings, &mut attempt, authentication)
+                    .unwrap_or(ConnectionState::Connecting(attempt))
+            }),
+            // The request was dropped, so make it again
+            None => Some(ConnectionState::Connecting(attempt.clone())),
+        },
+        ConnectionState::Connecting(attempt) => match (&client, attempt.started_at) {
+            (None, None) if now >= attempt.next_attempt_at => {
+                let user_data = ConnectUserData {
+                    version: PROTOCOL_VERSION,
+                    identity: *identity,
+                    name: settings.name.clone(),
+                    password: settings.password.clone(),
+                };
+                if settings.unsecure || settings.token_file.is_some() {
+                    let authentication = client_authentication(
+                        &settings,
+                        attempt.server_addr,
+                        session_client_id.0,
+                        &user_data,
+                    );
+                    start_client(&mut commands, &settings, attempt, authentication)
+                } else {
+                    let service_addr = settings.token_service_addr(attempt.server_addr);
+                    let timeout = settings.connect_timeout();
+                    token_request.0 = Some(AsyncComputeTaskPool::get().spawn(async move {
+                        request_token(&*service_addr, &user_data, timeout).map_err(|e| {
+                            format!("could not get a token from {}: {}", service_addr, e)
+                        })
+                    }));
+                    Some(ConnectionState::RequestingToken(attempt.clone()))
+                }
+            }
+            (Some(client), Some(started_at)) => {
+                if client.is_connected() {
+                    screen_print!("Connected to {}", attempt.server_addr);
+                    Some(ConnectionState::Connected(attempt.server_addr))
+                } else if let Some(reason) = client.disconnected() {
+                    commands.remove_resource::<RenetClient>();
+                    match notice.take(reason.to_string()) {
+                        (reason, true) => Some(retry_or_fail(&settings, attempt, reason)),
+                        (reason, false) => Some(ConnectionState::Failed {
+                            server_addr: attempt.server_addr,
+                            reason,
+                        }),
+                    }
+                } else if now.duration_since(started_at) > settings.connect_timeout() {
+                    commands.remove_resource::<RenetClient>();
+                    Some(retry_or_fail(
+                        &settings,
+                        attempt,
+                        String::from("connection timed out"),
+                    ))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        },
+        ConnectionState::Connected(server_addr) => match &client {
+            Some(client) => client.disconnected().map(|reason| {
+                commands.remove_resource::<RenetClient>();
+                match notice.take(reason.to_string()) {
+                    (reason, true) => {
+                        screen_print!(
+                            "Lost connection to {}: {}, reconnecting",
+                            server_addr,
+                            reason
+                        );
+                        ConnectionState::Connecting(ConnectAttempt::new(*server_addr))
+                    }
+                    (reason, false) => ConnectionState::Failed {
+                        server_addr: *server_addr,
+                        reason,
+                    },
+                }
+            }),
+            None => Some(ConnectionState::Disconnected),
+        },
+        ConnectionState::Disconnected | ConnectionState::Failed { .. } => None,
+    };
+
+    if let Some(next_state) = next_state {
+        *state = next_state;
+    }
+}
+
+/// Create the client for an attempt, or schedule the next attempt if that fails.
+fn start_client(
+    commands: &mut Commands,
+    settings: &ClientSettings,
+    attempt: &mut ConnectAttempt,
+    authentication: Result<(ClientId, ClientAuthentication), String>,
+) -> Option<ConnectionState> {
+    match authentication
+        .and_then(|(client_id, authentication)| create_renet_client(client_id, authentication))
+    {
+        Ok(client) => {
+            commands.insert_resource(client);
+            attempt.started_at = Some(Instant::now());
+            None
+        }
+        Err(reason) => Some(retry_or_fail(settings, attempt, reason)),
+    }
+}
+
+/// Schedule the next attempt with exponential backoff, or give up if we ran out of attempts.
+fn retry_or_fail(
+    settings: &ClientSettings,
+    attempt: &ConnectAttempt,
+    reason: String,
+) -> ConnectionState {
+    let failed_attempts = attempt.failed_attempts + 1;
+    if failed_attempts >= settings.connect_attempts {
+        return ConnectionState::Failed {
+            server_addr: attempt.server_addr,
+            reason,
+        };
+    }
+
+    let backoff = settings.backoff(failed_attempts);
+    screen_print!(
+        "Connecting to {} failed: {}, retrying in {:.0}s",
+        attempt.server_addr,
+        reason,
+        backoff.as_secs_f32()
+    );
+
+    ConnectionState::Connecting(ConnectAttempt {
+        server_addr: attempt.server_addr,
+        failed_attempts,
+        started_at: None,
+        next_attempt_at: Instant::now() + backoff,
+    })
+}
+
+/// Lets the player type a server address whenever we are not connected.
+fn connect_prompt(
+    state: Res<ConnectionState>,
+    keys: Res<Input<KeyCode>>,
+    mut input: ResMut<AddressInput>,
+    mut characters: EventReader<ReceivedCharacter>,
+    mut connect_requests: EventWriter<ConnectRequest>,
+) {
+    match &*state {
+        ConnectionState::Connected(_) => {
+            characters.clear();
+            return;
+        }
+        ConnectionState::RequestingToken(attempt) | ConnectionState::Connecting(attempt) => {
+            characters.clear();
+            screen_print!("Connecting to {}...", attempt.server_addr);
+            return;
+        }
+        ConnectionState::Failed {
+            server_addr,
+            reason,
+        } => {
+            screen_print!("Could not connect to {}: {}", server_addr, reason);
+        }
+        ConnectionState::Disconnected => {}
+    }
+
+    for event in characters.iter() {
+        if !event.char.is_control() {
+            input.0.push(event.char);
+        }
+    }
+    if keys.just_pressed(KeyCode::Back) {
+        input.0.pop();
+    }
+
+    screen_print!("Server address: {}_ (press Enter to connect)", input.0);
+
+    if keys.just_pressed(KeyCode::Return) {
+        match resolve_server_address(&input.0) {
+            Some(server_addr) => connect_requests.send(ConnectRequest { server_addr }),
+            None => screen_print!("Invalid server address {}", input.0),
+        }
+    }
+}
+
+fn create_renet_client(
+    client_id: ClientId,
+    authentication: ClientAuthentication,
+) -> Result<RenetClient, String> {
+    let current_time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap();
+
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| e.to_string())?;
+
+    let connection_config = RenetConnectionConfig::default();
+
+    RenetClient::new(
+        current_time,
+        socket,
+        client_id,
+        connection_config,
+        authentication,
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Pick how to authenticate with the server, when no token has to be requested for it.
+///
+/// In secure mode the client id is the one the token was issued for.
+fn client_authentication(
+    settings: &ClientSettings,
+    server_addr: SocketAddr,
+    session_client_id: ClientId,
+    user_data: &ConnectUserData,
+) -> Result<(ClientId, ClientAuthentication), String> {
+    if settings.unsecure {
+        let authentication = ClientAuthentication::Unsecure {
+            protocol_id: PROTOCOL_ID,
+            client_id: session_client_id,
+            server_addr,
+            user_data: Some(user_data.to_bytes().map_err(|e| e.to_string())?),
+        };
+        return Ok((session_client_id, authentication));
+    }
+
+    let path = settings
+        .token_file
+        .as_ref()
+        .ok_or_else(|| String::from("no token file to connect with"))?;
+    let connect_token = load_token(path)
+        .map_err(|e| format!("could not load token from {}: {}", path.display(), e))?;
+
+    Ok((
+        connect_token.client_id,
+        ClientAuthentication::Secure { connect_token },
+    ))
+}
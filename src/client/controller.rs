@@ -17,7 +17,7 @@ use bevy_rapier3d::prelude::{ExternalForce, QueryFilter, RapierContext};
 use bevy_rapier3d::render::DebugRenderContext;
 use iyes_loopless::prelude::{AppLooplessStateExt, IntoConditionalSystem};
 use iyes_loopless::state::CurrentState;
-use leafwing_input_manager::prelude::{ActionState, InputManagerPlugin, InputMap};
+use leafwing_input_manager::prelude::{ActionState, InputManagerPlugin, InputMap, ToggleActions};
 use leafwing_input_manager::{action_state, Actionlike, InputManagerBundle};
 use spacegame_core::message::ClientMessageOutQueue;
 
@@ -25,22 +25,27 @@ use crate::events::ship::{
     BlockRemoveEvent, BlockUpdateEvent, EnteredShipEvent, LeftShipEvent, ShipMoveEvent,
     TryEnterShipEvent, TryLeaveShipEvent,
 };
-use crate::math::rotate_vec_by_quat;
-use crate::model::block_map::BlockRotation;
+use crate::model::block_definitions::BlockDefinitions;
+use crate::model::block_map::{BlockFace, BlockRotation};
 
+use crate::shared::chunk_collider::ChunkCollider;
 use crate::shared::events::player::PlayerMoveEvent;
 use crate::shared::model::block::BlockType;
 use crate::shared::model::block_map::BlockPosition;
-use crate::shared::model::ship::{Gimbal, Thrust};
 
 use crate::shared::resources::control_input::ControlInput;
 use crate::shared::resources::keybindings::Keybindings;
 
+use super::chat::{ChatFocus, ChatInputLabel};
 use super::model::character::Character;
 
 #[derive(Component)]
 pub struct Controlled;
 
+/// How much a pixel of mouse movement in a frame steers a ship, steering as hard as the ship can
+/// at 1.
+const STEERING_SENSITIVITY: f32 = 0.1;
+
 #[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
 pub enum ControlState {
     Ship,
@@ -50,8 +55,8 @@ pub enum ControlState {
 
 pub enum LookingAt {
     None,
-    // Block Entity, Block Ship Entity
-    Block(Entity, Entity, RayIntersection),
+    // Block Ship Entity, Block Position
+    Block(Entity, BlockPosition, RayIntersection),
 }
 
 pub enum ChangeControlEvent {
@@ -72,6 +77,21 @@ enum ControlledShip {
     None,
 }
 
+/// The block that gets placed next.
+struct SelectedBlock {
+    block_type: BlockType,
+    rotation: BlockRotation,
+}
+
+impl Default for SelectedBlock {
+    fn default() -> Self {
+        Self {
+            block_type: BlockType::HULL,
+            rotation: BlockRotation::IDENTITY,
+        }
+    }
+}
+
 pub struct ControllerPlugin;
 
 impl bevy::app::Plugin for ControllerPlugin {
@@ -79,6 +99,7 @@ impl bevy::app::Plugin for ControllerPlugin {
         app.add_event::<ChangeControlEvent>()
             .insert_resource(ControlInput::default())
             .insert_resource(ControlledShip::None)
+            .init_resource::<SelectedBlock>()
             .add_state(ControlState::Character)
             .add_plugin(InputManagerPlugin::<Action>::default())
             .add_startup_system(setup)
@@ -92,7 +113,8 @@ impl bevy::app::Plugin for ControllerPlugin {
                 SystemSet::on_update(ControlState::Character)
                     .after(Labels::Preprocess)
                     .with_system(character_movement)
-                    .with_system(character_controls),
+                    .with_system(character_controls)
+                    .with_system(select_block),
             )
             .add_system_set(
                 SystemSet::on_update(ControlState::Ship)
@@ -111,7 +133,8 @@ impl bevy::app::Plugin for ControllerPlugin {
             .add_system(control)
             .add_system(on_self_enter_ship)
             .add_system(on_self_leave_ship)
-            .add_system(toggle_debug);
+            .add_system(toggle_debug)
+            .add_system(suspend_actions_while_typing.after(ChatInputLabel));
     }
 
     fn name(&self) -> &str {
@@ -132,6 +155,8 @@ enum Action {
     Boost,
     PlaceBlock,
     RemoveBlock,
+    NextBlockType,
+    RotateBlock,
     EnterShip,
     ExitShip,
     ToggleDebugColliders,
@@ -159,6 +184,8 @@ fn setup(mut commands: Commands) {
         (KeyCode::I, Action::ToggleInspector),
         (KeyCode::L, Action::ToggleFreecam),
         (KeyCode::R, Action::ResetCamera),
+        (KeyCode::B, Action::NextBlockType),
+        (KeyCode::T, Action::RotateBlock),
     ]);
 
     input_map.insert_multiple([
@@ -172,30 +199,45 @@ fn setup(mut commands: Commands) {
     });
 }
 
+/// Keep the keys typed into the chat from moving the player around.
+fn suspend_actions_while_typing(
+    chat_focus: Res<ChatFocus>,
+    mut toggle_actions: ResMut<ToggleActions<Action>>,
+) {
+    if chat_focus.is_changed() {
+        toggle_actions.enabled = !chat_focus.0;
+    }
+}
+
 pub fn block_raycast(
     mut commands: Commands,
     character: Res<Character>,
     rapier_context: Res<RapierContext>,
     camera_query: Query<&GlobalTransform, With<Camera3d>>,
-    parent_query: Query<&Parent>,
+    collider_query: Query<(&ChunkCollider, &Parent)>,
+    ship_query: Query<&GlobalTransform>,
 ) {
     let camera_global_transform = camera_query.single();
 
-    if let Some((entity, intersect)) = rapier_context.cast_ray_and_get_normal(
-        camera_global_transform.translation(),
-        camera_global_transform.forward(),
-        Real::MAX,
-        true,
-        QueryFilter::new().exclude_collider(character.entity),
-    ) {
-        if let Ok(parent) = parent_query.get(entity) {
-            commands.insert_resource(LookingAt::Block(entity, **parent, intersect));
-        } else {
-            commands.insert_resource(LookingAt::None);
-        }
-    } else {
-        commands.insert_resource(LookingAt::None);
-    }
+    let looking_at = rapier_context
+        .cast_ray_and_get_normal(
+            camera_global_transform.translation(),
+            camera_global_transform.forward(),
+            Real::MAX,
+            true,
+            QueryFilter::new().exclude_collider(character.entity),
+        )
+        .and_then(|(entity, intersect)| {
+            // Chunk colliders know which of their blocks is where on the ship
+            let (chunk_collider, parent) = collider_query.get(entity).ok()?;
+            let to_ship = ship_query.get(**parent).ok()?.affine().inverse();
+            let block_position = chunk_collider.block_at(
+                to_ship.transform_point3(intersect.point),
+                to_ship.transform_vector3(intersect.normal),
+            )?;
+            Some(LookingAt::Block(**parent, block_position, intersect))
+        });
+    commands.insert_resource(looking_at.unwrap_or(LookingAt::None));
 }
 
 fn merge_inputs(
@@ -205,6 +247,7 @@ fn merge_inputs(
     mut windows: ResMut<Windows>,
     mut mouse_events: EventReader<MouseMotion>,
     mut control_input: ResMut<ControlInput>,
+    chat_focus: Res<ChatFocus>,
 ) {
     // Get aspect ratio of window
     let mut primary_window = windows.get_primary_mut().unwrap();
@@ -230,7 +273,12 @@ fn merge_inputs(
     }
 
     // Insert the control input resource
-    control_input.key_input = key_input.clone();
+    // Keys typed into the chat are not meant for the controls
+    control_input.key_input = if chat_focus.0 {
+        Input::default()
+    } else {
+        key_input.clone()
+    };
     control_input.mouse_input = mouse_input.clone();
     control_input.mouse_delta = mouse_delta_raw * aspect_ratio;
     control_input.mouse_delta_raw = mouse_delta_raw;
@@ -296,18 +344,55 @@ fn take_character_control(
     commands.entity(character.entity).add_child(camera_entity);
 }
 
+/// Cycle through the known block types and the rotations to place them with.
+fn select_block(
+    definitions: Res<BlockDefinitions>,
+    state_query: Query<&ActionState<Action>>,
+    mut selected: ResMut<SelectedBlock>,
+) {
+    let action_state = state_query.single();
+
+    if action_state.just_pressed(Action::NextBlockType) {
+        let mut block_types: Vec<BlockType> =
+            definitions.iter().map(|definition| definition.id).collect();
+        block_types.sort_by_key(|block_type| block_type.0);
+        selected.block_type = block_types
+            .iter()
+            .find(|block_type| block_type.0 > selected.block_type.0)
+            .or_else(|| block_types.first())
+            .copied()
+            .unwrap_or(BlockType::HULL);
+    }
+    if action_state.just_pressed(Action::RotateBlock) {
+        selected.rotation = BlockRotation::all()
+            .find(|rotation| rotation.index() > selected.rotation.index())
+            .unwrap_or(BlockRotation::IDENTITY);
+    }
+
+    if selected.is_changed() {
+        let name = definitions
+            .get(selected.block_type)
+            .map_or("Unknown", |definition| definition.name.as_str());
+        screen_print!(
+            "Placing {} facing {:?}",
+            name,
+            selected.rotation.rotate_face(BlockFace::NegativeZ)
+        );
+    }
+}
+
 fn character_controls(
     looking_at: Res<LookingAt>,
+    selected: Res<SelectedBlock>,
     state_query: Query<&ActionState<Action>>,
     transform_query: Query<&GlobalTransform>,
-    block_query: Query<&BlockPosition>,
     mut block_remove_queue: ResMut<ClientMessageOutQueue<BlockRemoveEvent>>,
     mut block_update_queue: ResMut<ClientMessageOutQueue<BlockUpdateEvent>>,
     mut try_enter_ship_queue: ResMut<ClientMessageOutQueue<TryEnterShipEvent>>,
 ) {
     let action_state = state_query.single();
 
-    if let LookingAt::Block(block_entity, ship_entity, intersect) = *looking_at {
+    if let LookingAt::Block(ship_entity, block_position, intersect) = *looking_at {
         if action_state.just_pressed(Action::PlaceBlock) {
             let global_transform = transform_query.get(ship_entity).unwrap();
             let block_position = BlockPosition::rounded(
@@ -319,17 +404,15 @@ fn character_controls(
 
             block_update_queue.send(BlockUpdateEvent {
                 ship_entity,
-                block_type: BlockType::Hull,
+                block_type: selected.block_type,
                 block_position,
-                block_rotation: BlockRotation::default(),
+                block_rotation: selected.rotation,
                 client_id: 0,
             });
         } else if action_state.just_pressed(Action::RemoveBlock) {
-            let block_position = block_query.get(block_entity).unwrap();
-
             block_remove_queue.send(BlockRemoveEvent {
                 ship_entity,
-                block_position: *block_position,
+                block_position,
                 client_id: 0,
             });
         }
@@ -347,8 +430,8 @@ fn character_movement(
     keybindings: Res<Keybindings>,
     time: Res<Time>,
     control_input: Res<ControlInput>,
-	character: Res<Character>,
-	mut transform_query: Query<(&mut Transform, &GlobalTransform)>,
+    character: Res<Character>,
+    mut transform_query: Query<(&mut Transform, &GlobalTransform)>,
     mut force_query: Query<&mut ExternalForce>,
     action_query: Query<&ActionState<Action>>,
     mut player_move_queue: ResMut<ClientMessageOutQueue<PlayerMoveEvent>>,
@@ -425,13 +508,11 @@ fn character_movement(
 }
 
 fn ship_controls(
-    time: Res<Time>,
     control_input: Res<ControlInput>,
     controlled_ship: Res<ControlledShip>,
     action_query: Query<&ActionState<Action>>,
     mut leave_ship_queue: ResMut<ClientMessageOutQueue<TryLeaveShipEvent>>,
     mut ship_move_queue: ResMut<ClientMessageOutQueue<ShipMoveEvent>>,
-    mut ship_query: Query<(&mut ExternalForce, &Thrust, &Gimbal, &Transform)>,
 ) {
     let ship_entity = match *controlled_ship {
         ControlledShip::Ship(entity) => entity,
@@ -451,26 +532,20 @@ fn ship_controls(
     }
 
     // Double thrust when shift is pressed
-    let thrust_multiplier = if action_state.pressed(Action::Boost) {
-        16.0
+    let throttle = if action_state.pressed(Action::Boost) {
+        1.0
     } else {
-        8.0
+        0.5
     };
 
-    rot_dir.x = -control_input.mouse_delta.y;
-    rot_dir.y = -control_input.mouse_delta.x;
-
-    let (mut force, thrust, gimbal, transform) = ship_query.single_mut();
-    let dt = time.delta_seconds();
-    let thrust = thrust.t * thrust_multiplier;
-    let gimbal = gimbal.t * thrust_multiplier;
-
-    force.force = rotate_vec_by_quat(mov_dir * thrust * dt, transform.rotation);
-    force.torque = rotate_vec_by_quat(rot_dir * gimbal * dt, transform.rotation);
+    rot_dir.x = -control_input.mouse_delta.y * STEERING_SENSITIVITY;
+    rot_dir.y = -control_input.mouse_delta.x * STEERING_SENSITIVITY;
 
+    // The server works out how hard the engines and gyroscopes can push
     ship_move_queue.send(ShipMoveEvent {
         ship_entity,
-        force: *force,
+        throttle: mov_dir * throttle,
+        steering: rot_dir.clamp(Vec3::splat(-1.), Vec3::ONE),
         client_id: 0,
     });
 
@@ -0,0 +1,57 @@
+use bevy::prelude::{EventReader, Plugin, Res, ResMut};
+use bevy_debug_text_overlay::screen_print;
+use iyes_loopless::prelude::IntoConditionalSystem;
+
+use crate::{
+    client::model::character::Character,
+    shared::{events::faction::FactionSyncEvent, model::faction::FactionInfo},
+};
+
+/// The factions on the server, as last sent by it.
+#[derive(Default)]
+pub struct Factions(pub Vec<FactionInfo>);
+
+impl Factions {
+    /// The faction the player with this name is a member of.
+    pub fn faction_of(&self, player_name: &str) -> Option<&FactionInfo> {
+        self.0
+            .iter()
+            .find(|faction| faction.members.iter().any(|member| member == player_name))
+    }
+}
+
+pub struct FactionPlugin;
+
+impl Plugin for FactionPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<Factions>()
+            .add_system(on_faction_sync.run_on_event::<FactionSyncEvent>());
+    }
+
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+}
+
+fn on_faction_sync(
+    character: Res<Character>,
+    mut factions: ResMut<Factions>,
+    mut events: EventReader<FactionSyncEvent>,
+) {
+    for event in events.iter() {
+        let previous = factions
+            .faction_of(&character.name)
+            .map(|faction| faction.name.clone());
+        factions.0 = event.factions.clone();
+        let current = factions
+            .faction_of(&character.name)
+            .map(|faction| faction.name.clone());
+
+        if previous != current {
+            match current {
+                Some(name) => screen_print!("You are now in faction {}", name),
+                None => screen_print!("You are no longer in a faction"),
+            }
+        }
+    }
+}
@@ -1,20 +1,25 @@
 use bevy::math::Vec3;
 
 use bevy::prelude::{
-    Color, GlobalTransform, ParallelSystemDescriptorCoercion, Plugin, Query, Res, ResMut, Transform,
+    Color, Entity, GlobalTransform, Local, ParallelSystemDescriptorCoercion, Plugin, Query, Res,
+    ResMut, Transform,
 };
+use bevy_debug_text_overlay::screen_print;
 use bevy_prototype_debug_lines::DebugLines;
 
 use crate::client::controller::LookingAt;
 use crate::client::controller::LookingAt::Block;
 
+use crate::model::ship::{ShipName, ShipOwnership};
+
 use super::labels::UpdateLabels;
 
 pub struct HighlightPlugin;
 
 impl Plugin for HighlightPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_system(highlight_mouse_block.after(UpdateLabels::Sync));
+        app.add_system(highlight_mouse_block.after(UpdateLabels::Sync))
+            .add_system(show_ship_ownership.after(UpdateLabels::Sync));
     }
 
     fn name(&self) -> &str {
@@ -40,28 +45,26 @@ static HOVER_COLOR: Color = Color::rgb(0., 0., 0.);
 
 pub fn highlight_mouse_block(
     looking_at: Res<LookingAt>,
-    transform_query: Query<(&Transform, &GlobalTransform)>,
+    transform_query: Query<&GlobalTransform>,
     mut lines: ResMut<DebugLines>,
 ) {
     match *looking_at {
         LookingAt::None => {}
-        Block(block_entity, _ship_entity, intersect) => {
-            if let Ok((_block_transform, block_global_transform)) =
-                transform_query.get(block_entity)
-            {
+        Block(ship_entity, block_position, intersect) => {
+            if let Ok(ship_global_transform) = transform_query.get(ship_entity) {
+                let block_transform = ship_global_transform
+                    .compute_transform()
+                    .mul_transform(block_position.into());
+
                 // Direction line
-                let start = block_global_transform.translation() + (intersect.normal / 2.);
+                let start = block_transform.translation + (intersect.normal / 2.);
                 let end = start + (intersect.normal / 2.);
                 lines.line_gradient(start, end, 0.0, Color::RED, Color::BLUE);
 
                 // Hovered Block
-                draw_rect(
-                    &mut lines,
-                    block_global_transform.compute_transform(),
-                    HOVER_COLOR,
-                );
+                draw_rect(&mut lines, block_transform, HOVER_COLOR);
 
-                let mut next_block_transform = block_global_transform.compute_transform();
+                let mut next_block_transform = block_transform;
                 next_block_transform.translation += intersect.normal;
 
                 draw_rect(&mut lines, next_block_transform, Color::ORANGE_RED);
@@ -77,3 +80,32 @@ fn draw_rect(lines: &mut DebugLines, transform: Transform, color: Color) {
         lines.line_colored(start, end, 0., color);
     }
 }
+
+/// Tell who owns a ship when starting to look at it.
+fn show_ship_ownership(
+    looking_at: Res<LookingAt>,
+    mut shown_ship: Local<Option<Entity>>,
+    ship_query: Query<(&ShipName, &ShipOwnership)>,
+) {
+    let ship_entity = match *looking_at {
+        Block(ship_entity, _, _) => Some(ship_entity),
+        LookingAt::None => None,
+    };
+    if ship_entity == *shown_ship {
+        return;
+    }
+    *shown_ship = ship_entity;
+
+    if let Some((ship_name, ownership)) = ship_entity.and_then(|e| ship_query.get(e).ok()) {
+        match &ownership.owner {
+            Some(owner) => screen_print!("{} is owned by {}", ship_name.name, owner),
+            None => screen_print!("{} is not owned by anyone", ship_name.name),
+        }
+        if !ownership.builders.is_empty() {
+            screen_print!("Builders: {}", ownership.builders.join(", "));
+        }
+        if !ownership.pilots.is_empty() {
+            screen_print!("Pilots: {}", ownership.pilots.join(", "));
+        }
+    }
+}
@@ -1,4 +1,9 @@
+pub mod chat;
+pub mod chunk_mesh;
+pub mod config;
+pub mod connection;
 pub mod controller;
+pub mod faction;
 pub mod highlight;
 pub mod labels;
 pub mod model;
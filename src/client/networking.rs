@@ -1,10 +1,4 @@
-use std::{
-    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
-    time::SystemTime,
-};
-
 use bevy::prelude::Plugin;
-use bevy_renet::renet::{ClientAuthentication, RenetClient, RenetConnectionConfig};
 use spacegame_core::client::{AppClientNetworkTrait, ClientNetworkPlugin};
 
 use crate::{
@@ -12,26 +6,32 @@ use crate::{
         player::{PlayerDespawnEvent, PlayerMoveEvent, PlayerReadyEvent},
         ship::{
             BlockRemoveEvent, BlockUpdateEvent, EnteredShipEvent, LeftShipEvent, LoadShipEvent,
-            ShipMoveEvent, TryEnterShipEvent, TryLeaveShipEvent, UnloadShipEvent,
+            ShipMoveEvent, ShipOwnershipEvent, TryEnterShipEvent, TryLeaveShipEvent,
+            UnloadShipEvent,
         },
     },
     shared::{
         events::{
+            chat::{AnnouncementEvent, ChatMessageEvent},
+            connection::DisconnectNoticeEvent,
+            faction::FactionSyncEvent,
             generic::GenericPositionSyncEvent,
             player::PlayerSpawnEvent,
             ship::{SyncShipBlocksEvent, SyncShipEvent, SyncShipPositionEvent},
         },
         networking::plugin::NetworkingPlugin,
     },
-    PROTOCOL_ID,
 };
 
+use super::connection::ConnectionPlugin;
+
 pub struct ClientNetworkingPlugin;
 
 impl Plugin for ClientNetworkingPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app.add_plugin(NetworkingPlugin)
             .add_plugin(ClientNetworkPlugin)
+            .add_plugin(ConnectionPlugin)
             .add_network_event::<SyncShipPositionEvent>()
             .add_network_event::<SyncShipBlocksEvent>()
             .add_network_event::<SyncShipEvent>()
@@ -49,7 +49,11 @@ impl Plugin for ClientNetworkingPlugin {
             .add_network_event::<ShipMoveEvent>()
             .add_network_event::<PlayerReadyEvent>()
             .add_network_event::<UnloadShipEvent>()
-            .insert_resource(create_renet_client());
+            .add_network_event::<DisconnectNoticeEvent>()
+            .add_network_event::<AnnouncementEvent>()
+            .add_network_event::<ChatMessageEvent>()
+            .add_network_event::<ShipOwnershipEvent>()
+            .add_network_event::<FactionSyncEvent>();
     }
 
     fn name(&self) -> &str {
@@ -58,33 +62,3 @@ impl Plugin for ClientNetworkingPlugin {
 }
 
 pub struct NetworkClient;
-
-fn create_renet_client() -> RenetClient {
-    let current_time = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap();
-
-    let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
-    let client_id = current_time.as_millis() as u64;
-
-    let connection_config = RenetConnectionConfig::default();
-
-    let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(85, 229, 106, 197)), 42069);
-    //let server_addr = SocketAddr::new(local_ip().unwrap(), 42069);
-
-    let authentication = ClientAuthentication::Unsecure {
-        protocol_id: PROTOCOL_ID,
-        client_id,
-        server_addr: server_addr,
-        user_data: None,
-    };
-
-    RenetClient::new(
-        current_time,
-        socket,
-        client_id,
-        connection_config,
-        authentication,
-    )
-    .unwrap()
-}
@@ -37,8 +37,10 @@ impl Plugin for PlayerPlugin {
 fn on_player_ready(mut character: ResMut<Character>, mut events: EventReader<PlayerReadyEvent>) {
     for event in events.iter() {
         character.client_id = event.own_client_it;
+        character.name = event.player_name.clone();
         screen_print!(
-            "Connected to server with {} players online",
+            "Connected to server as {} with {} players online",
+            event.player_name,
             event.players_online_count
         );
     }
@@ -47,6 +49,7 @@ fn on_player_ready(mut character: ResMut<Character>, mut events: EventReader<Pla
 fn on_player_spawn(
     mut commands: Commands,
     client: Res<RenetClient>,
+    mut player_id_map: ResMut<PlayerIdMap>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut events: EventReader<PlayerSpawnEvent>,
@@ -60,6 +63,8 @@ fn on_player_spawn(
             });
         }
 
+        player_id_map.insert(event.player_id, event.player_entity);
+
         commands
             .entity(event.player_entity)
             .insert_bundle(PlayerBundle {
@@ -79,10 +84,12 @@ fn on_player_spawn(
 fn on_player_despawn(
     mut commands: Commands,
     mut network_ids: ResMut<NetworkIdMap>,
+    mut player_id_map: ResMut<PlayerIdMap>,
     mut events: EventReader<PlayerDespawnEvent>,
 ) {
     for event in events.iter() {
         commands.entity(event.player_entity).despawn_recursive();
+        player_id_map.remove_client(event.player_id);
         network_ids.remove(event.player_entity);
 
         screen_print!("Despawned player");
@@ -90,13 +97,22 @@ fn on_player_despawn(
 }
 
 fn on_player_move(
+    character: Res<Character>,
     player_id_map: Res<PlayerIdMap>,
     mut events: EventReader<PlayerMoveEvent>,
     mut query: Query<&mut Transform>,
 ) {
     for event in events.iter() {
-        let player_entity = player_id_map.from_client(event.client_id).unwrap();
-        let mut transform = query.get_mut(player_entity).unwrap();
-        *transform = event.transform;
+        // Our own position only comes from the server when we are teleported
+        let player_entity = if event.client_id == character.client_id {
+            Some(character.entity)
+        } else {
+            player_id_map.from_client(event.client_id)
+        };
+        if let Some(player_entity) = player_entity {
+            if let Ok(mut transform) = query.get_mut(player_entity) {
+                *transform = event.transform;
+            }
+        }
     }
 }
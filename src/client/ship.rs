@@ -39,8 +39,8 @@ fn spawn_block(
             block_position,
             pbr_bundle: PbrBundle {
                 transform: block_position.into(),
-                mesh: block_registry.get_mesh(block_type),
-                material: block_registry.get_material(block_type),
+                mesh: block_registry.get_mesh(block_type).unwrap_or_default(),
+                material: block_registry.get_material(block_type).unwrap_or_default(),
                 ..default()
             },
             ..default()
@@ -1,7 +1,8 @@
 use bevy::{
     prelude::{
-        default, BuildChildren, Commands, DespawnRecursiveExt, Entity, EventReader,
-        ParallelSystemDescriptorCoercion, PbrBundle, Plugin, Query, Res, ResMut, Transform,
+        default, trace, BuildChildren, Camera3d, Commands, DespawnRecursiveExt, Entity,
+        EventReader, ParallelSystemDescriptorCoercion, Parent, Plugin, Query, Res, ResMut,
+        Transform, With,
     },
     transform::TransformBundle,
 };
@@ -9,16 +10,16 @@ use bevy_rapier3d::prelude::Velocity;
 use spacegame_core::network_id::NetworkIdMap;
 
 use crate::{
-    events::ship::{BlockRemoveEvent, BlockUpdateEvent, LoadShipEvent},
+    events::ship::{BlockRemoveEvent, BlockUpdateEvent, LoadShipEvent, UnloadShipEvent},
     model::{
-        block::{BlockBundle, BlockType},
+        block::BlockType,
         block_map::{BlockMap, BlockPosition, BlockRotation},
-        ship::{ShipBundle, ShipName},
+        ship::{ShipBundle, ShipName, ShipOwnership},
     },
     resources::block_registry::BlockRegistry,
     shared::events::{
         generic::GenericPositionSyncEvent,
-        ship::{SyncShipBlocksEvent, SyncShipEvent, SyncShipPositionEvent},
+        ship::{ShipOwnershipEvent, SyncShipEvent, SyncShipPositionEvent},
     },
 };
 
@@ -31,9 +32,11 @@ impl Plugin for SyncPlugin {
         app.add_system(on_sync_ship.label(UpdateLabels::Sync))
             .add_system(on_sync_ship_position.label(UpdateLabels::Sync))
             .add_system(on_load_ship)
+            .add_system(on_ship_ownership)
             .add_system(on_generic_position_sync.label(UpdateLabels::Sync))
             .add_system(on_block_update)
-            .add_system(on_block_remove);
+            .add_system(on_block_remove)
+            .add_system(on_unload_ship);
     }
 
     fn name(&self) -> &str {
@@ -41,16 +44,18 @@ impl Plugin for SyncPlugin {
     }
 }
 
-fn on_load_ship(
-    mut commands: Commands,
-    block_registry: Res<BlockRegistry>,
-    mut events: EventReader<LoadShipEvent>,
-) {
+fn on_load_ship(mut commands: Commands, mut events: EventReader<LoadShipEvent>) {
     for event in events.iter() {
+        let block_map = sync_blocks(
+            &mut commands,
+            &BlockMap::new(),
+            &event.block_map,
+            &event.ship_entity,
+        );
         commands
             .entity(event.ship_entity)
             .insert_bundle(ShipBundle {
-                block_map: event.block_map.clone(),
+                block_map,
                 transform_bundle: TransformBundle {
                     local: event.transform,
                     ..default()
@@ -60,14 +65,39 @@ fn on_load_ship(
                     name: event.name.clone(),
                 },
                 ..default()
-            });
-        sync_blocks(
-            &mut commands,
-            &block_registry,
-            &BlockMap::new(),
-            &event.block_map,
-            &event.ship_entity,
-        );
+            })
+            .insert(event.ownership.clone());
+    }
+}
+
+fn on_unload_ship(
+    mut commands: Commands,
+    mut network_ids: ResMut<NetworkIdMap>,
+    mut events: EventReader<UnloadShipEvent>,
+    camera_query: Query<(Entity, &Parent), With<Camera3d>>,
+) {
+    for event in events.iter() {
+        // Don't take the camera down with the ship if we were flying it
+        for (camera, parent) in camera_query.iter() {
+            if parent.get() == event.ship_entity {
+                commands
+                    .entity(event.ship_entity)
+                    .remove_children(&[camera]);
+            }
+        }
+        commands.entity(event.ship_entity).despawn_recursive();
+        network_ids.remove(event.ship_entity);
+    }
+}
+
+fn on_ship_ownership(
+    mut events: EventReader<ShipOwnershipEvent>,
+    mut ship_query: Query<&mut ShipOwnership>,
+) {
+    for event in events.iter() {
+        if let Ok(mut ownership) = ship_query.get_mut(event.ship_entity) {
+            *ownership = event.ownership.clone();
+        }
     }
 }
 
@@ -126,27 +156,25 @@ fn on_sync_ship_position(
     }
 }
 
-fn on_sync_ship_blocks(
-    mut network_ids: ResMut<NetworkIdMap>,
-    mut ship_events: EventReader<SyncShipBlocksEvent>,
-    mut ship_query: Query<(Entity, &mut BlockMap)>,
-) {
-}
-
 fn on_block_update(
     mut commands: Commands,
-    block_registry: Res<BlockRegistry>,
-    mut network_ids: ResMut<NetworkIdMap>,
     mut events: EventReader<BlockUpdateEvent>,
     mut ship_query: Query<&mut BlockMap>,
 ) {
     for event in events.iter() {
-        let mut block_map = ship_query.get_mut(event.ship_entity).unwrap();
-        spawn_block(
+        let mut block_map = match ship_query.get_mut(event.ship_entity) {
+            Ok(block_map) => block_map,
+            Err(_) => {
+                trace!(
+                    "Got a block update for unknown ship {:?}",
+                    event.ship_entity
+                );
+                continue;
+            }
+        };
+        set_block(
             &mut commands,
-            &block_registry,
             &mut block_map,
-            &event.ship_entity,
             event.block_position,
             event.block_type,
             event.block_rotation,
@@ -156,14 +184,24 @@ fn on_block_update(
 
 fn on_block_remove(
     mut commands: Commands,
-    block_registry: Res<BlockRegistry>,
-    mut network_ids: ResMut<NetworkIdMap>,
     mut events: EventReader<BlockRemoveEvent>,
     mut ship_query: Query<&mut BlockMap>,
 ) {
     for event in events.iter() {
-        let mut block_map = ship_query.get_mut(event.ship_entity).unwrap();
-        if let Some(entity) = block_map.remove(&event.block_position) {
+        let mut block_map = match ship_query.get_mut(event.ship_entity) {
+            Ok(block_map) => block_map,
+            Err(_) => {
+                trace!(
+                    "Got a block removal for unknown ship {:?}",
+                    event.ship_entity
+                );
+                continue;
+            }
+        };
+        if let Some(entity) = block_map
+            .remove(&event.block_position)
+            .and_then(|old_block| old_block.entity)
+        {
             commands.entity(entity).despawn_recursive();
         }
     }
@@ -171,26 +209,21 @@ fn on_block_remove(
 
 fn sync_blocks(
     commands: &mut Commands,
-    block_registry: &BlockRegistry,
     old_block_map: &BlockMap,
     new_block_map: &BlockMap,
     ship_entity: &Entity,
 ) -> BlockMap {
-    for (pos, entry) in old_block_map.entries() {
-        commands.entity(entry.entity).despawn_recursive();
-        commands
-            .entity(*ship_entity)
-            .remove_children(&[entry.entity]);
+    for (_, entity) in old_block_map.entities() {
+        commands.entity(entity).despawn_recursive();
+        commands.entity(*ship_entity).remove_children(&[entity]);
     }
 
     let mut return_map = BlockMap::new();
     for (pos, entry) in new_block_map.entries() {
-        spawn_block(
+        set_block(
             commands,
-            block_registry,
             &mut return_map,
-            ship_entity,
-            *pos,
+            pos,
             entry.block_type,
             entry.block_rotation,
         );
@@ -199,44 +232,23 @@ fn sync_blocks(
     return_map
 }
 
-fn spawn_block(
+/// Put a block into `block_map`, despawning the entity of the block it replaces.
+///
+/// Blocks that are not full cubes get an entity of their own once their chunk is rebuilt, as
+/// only then the block definitions are sure to be loaded.
+fn set_block(
     commands: &mut Commands,
-    block_registry: &BlockRegistry,
     block_map: &mut BlockMap,
-    ship_entity: &Entity,
     block_position: BlockPosition,
     block_type: BlockType,
     block_rotation: BlockRotation,
-) -> Entity {
-    let block_entity = create_block(commands, block_registry, block_position, block_type);
-    commands.entity(*ship_entity).add_child(block_entity);
-    if let Some(old_block) = block_map.set(block_entity, block_type, block_position, block_rotation)
+) {
+    if let Some(old_entity) = block_map
+        .set(block_position, block_type, block_rotation)
+        .and_then(|old_block| old_block.entity)
     {
-        commands.entity(old_block.entity).despawn_recursive();
+        commands.entity(old_entity).despawn_recursive();
     }
-
-    block_entity
-}
-
-fn create_block(
-    commands: &mut Commands,
-    block_registry: &BlockRegistry,
-    block_position: BlockPosition,
-    block_type: BlockType,
-) -> Entity {
-    commands
-        .spawn_bundle(BlockBundle {
-            block_type: block_type,
-            block_position: block_position,
-            pbr_bundle: PbrBundle {
-                transform: block_position.into(),
-                material: block_registry.get_material(block_type),
-                mesh: block_registry.get_mesh(block_type),
-                ..default()
-            },
-            ..default()
-        })
-        .id()
 }
 
 fn on_generic_position_sync(
@@ -12,3 +12,12 @@ pub mod client;
 pub mod server;
 
 pub const PROTOCOL_ID: u64 = 1;
+
+/// Version of the game protocol, sent when connecting so that mismatched clients can be told
+/// why they are turned away. Bump it whenever network messages change.
+pub const PROTOCOL_VERSION: u32 = 5;
+
+pub const DEFAULT_PORT: u16 = 42069;
+
+/// Port the token service listens on by default, next to the game port.
+pub const DEFAULT_TOKEN_PORT: u16 = 42070;
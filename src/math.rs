@@ -13,7 +13,7 @@
 //}
 
 use bevy::{
-    math::{Quat, Vec3},
+    math::{DMat3, DVec3, Quat, Vec3},
     prelude::Transform,
 };
 
@@ -33,3 +33,51 @@ pub fn translate_with_local_reference_frame(transform: Transform, translation: V
             + transform.local_z() * translation.z,
     )
 }
+
+/// Eigenvalues of a symmetric matrix and the matching eigenvectors as the columns of a rotation
+/// matrix, found with Jacobi rotations.
+pub fn symmetric_eigen(matrix: DMat3) -> (DVec3, DMat3) {
+    // Indexed as [column][row]
+    let mut a = matrix.to_cols_array_2d();
+    let mut v = DMat3::IDENTITY.to_cols_array_2d();
+
+    for _ in 0..32 {
+        let off_diagonal = a[1][0] * a[1][0] + a[2][0] * a[2][0] + a[2][1] * a[2][1];
+        let diagonal = a[0][0] * a[0][0] + a[1][1] * a[1][1] + a[2][2] * a[2][2];
+        if off_diagonal <= diagonal * f64::EPSILON * f64::EPSILON {
+            break;
+        }
+
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[q][p] == 0. {
+                continue;
+            }
+            // Rotate in the pq plane so that a[q][p] becomes zero
+            let theta = (a[q][q] - a[p][p]) / (2. * a[q][p]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.).sqrt());
+            let c = 1. / (t * t + 1.).sqrt();
+            let s = t * c;
+
+            for k in 0..3 {
+                let (kp, kq) = (a[p][k], a[q][k]);
+                a[p][k] = c * kp - s * kq;
+                a[q][k] = s * kp + c * kq;
+            }
+            for k in 0..3 {
+                let (pk, qk) = (a[k][p], a[k][q]);
+                a[k][p] = c * pk - s * qk;
+                a[k][q] = s * pk + c * qk;
+            }
+            for k in 0..3 {
+                let (kp, kq) = (v[p][k], v[q][k]);
+                v[p][k] = c * kp - s * kq;
+                v[q][k] = s * kp + c * kq;
+            }
+        }
+    }
+
+    (
+        DVec3::new(a[0][0], a[1][1], a[2][2]),
+        DMat3::from_cols_array_2d(&v),
+    )
+}
@@ -0,0 +1,295 @@
+use std::{
+    fmt::Display,
+    fs, io,
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    prelude::{warn, EventReader, EventWriter, Name, Plugin, Query, Res, ResMut, With, Without},
+    utils::HashSet,
+};
+use bevy_renet::renet::RenetServer;
+use iyes_loopless::prelude::IntoConditionalSystem;
+use serde::{Deserialize, Serialize};
+
+use crate::shared::{
+    config::ConfigError,
+    entities::player::{PlayerClientId, PlayerMarker},
+    events::connection::DisconnectReason,
+    networking::identity::PlayerIdentity,
+};
+
+use super::{
+    command::{AppCommandExt, CommandEvent, CommandFeedback, CommandInfo, CommandPermission},
+    config::ServerSettings,
+    lifecycle::PendingDisconnects,
+    session::DisconnectedSince,
+};
+
+/// A set of identities and IP addresses kept in a TOML file.
+pub struct AccessList {
+    path: PathBuf,
+    identities: HashSet<PlayerIdentity>,
+    ips: HashSet<IpAddr>,
+}
+
+/// How an [`AccessList`] is stored, with identities in their hex form.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct AccessListFile {
+    identities: Vec<String>,
+    ips: Vec<IpAddr>,
+}
+
+impl AccessList {
+    /// Load the list stored at `path`, starting out empty if the file does not exist yet.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let file = match fs::read_to_string(path) {
+            Ok(contents) => {
+                toml::from_str::<AccessListFile>(&contents).map_err(ConfigError::Parse)?
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => AccessListFile::default(),
+            Err(e) => return Err(ConfigError::Io(e)),
+        };
+
+        let identities: HashSet<PlayerIdentity> = file
+            .identities
+            .iter()
+            .map(|identity| identity.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| ConfigError::Invalid("access lists must contain identities in hex"))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            identities,
+            ips: file.ips.into_iter().collect(),
+        })
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let mut file = AccessListFile {
+            identities: self.identities.iter().map(|i| i.to_string()).collect(),
+            ips: self.ips.iter().copied().collect(),
+        };
+        // Keep the file stable so that it diffs nicely
+        file.identities.sort();
+        file.ips.sort();
+
+        let contents =
+            toml::to_string(&file).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(&self.path, contents)
+    }
+
+    pub fn contains(&self, identity: &PlayerIdentity, ip: Option<IpAddr>) -> bool {
+        self.identities.contains(identity) || ip.map_or(false, |ip| self.ips.contains(&ip))
+    }
+
+    /// Add an entry, returning whether it was new.
+    pub fn insert(&mut self, entry: AccessEntry) -> bool {
+        match entry {
+            AccessEntry::Identity(identity) => self.identities.insert(identity),
+            AccessEntry::Ip(ip) => self.ips.insert(ip),
+        }
+    }
+
+    /// Remove an entry, returning whether it was there.
+    pub fn remove(&mut self, entry: AccessEntry) -> bool {
+        match entry {
+            AccessEntry::Identity(identity) => self.identities.remove(&identity),
+            AccessEntry::Ip(ip) => self.ips.remove(&ip),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AccessEntry {
+    Identity(PlayerIdentity),
+    Ip(IpAddr),
+}
+
+impl AccessEntry {
+    /// Parse an IP address or an identity in hex.
+    pub fn parse(s: &str) -> Option<Self> {
+        if let Ok(ip) = s.parse() {
+            return Some(AccessEntry::Ip(ip));
+        }
+        s.parse().ok().map(AccessEntry::Identity)
+    }
+}
+
+impl Display for AccessEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AccessEntry::Identity(identity) => write!(f, "identity {}", identity),
+            AccessEntry::Ip(ip) => write!(f, "ip {}", ip),
+        }
+    }
+}
+
+/// Players that are not allowed to join.
+pub struct BanList(pub AccessList);
+
+/// The only players allowed to join, if enabled.
+pub struct Whitelist(pub Option<AccessList>);
+
+impl Whitelist {
+    pub fn allows(&self, identity: &PlayerIdentity, ip: Option<IpAddr>) -> bool {
+        match &self.0 {
+            Some(list) => list.contains(identity, ip),
+            None => true,
+        }
+    }
+}
+
+pub struct AccessPlugin;
+
+impl Plugin for AccessPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        let settings = app.world.resource::<ServerSettings>().clone();
+        let ban_list = AccessList::load(&settings.ban_list).unwrap_or_else(|e| {
+            panic!(
+                "Could not load ban list from {}: {}",
+                settings.ban_list.display(),
+                e
+            )
+        });
+        let whitelist = settings.whitelist.as_ref().map(|path| {
+            AccessList::load(path).unwrap_or_else(|e| {
+                panic!("Could not load whitelist from {}: {}", path.display(), e)
+            })
+        });
+
+        app.insert_resource(BanList(ban_list))
+            .insert_resource(Whitelist(whitelist))
+            .add_command(CommandInfo {
+                name: "ban",
+                usage: "<player|identity|ip> [reason]",
+                description: "Disconnect a player and keep them from joining again",
+                permission: CommandPermission::Admin,
+            })
+            .add_command(CommandInfo {
+                name: "unban",
+                usage: "<identity|ip>",
+                description: "Lift a ban",
+                permission: CommandPermission::Admin,
+            })
+            .add_command(CommandInfo {
+                name: "whitelist",
+                usage: "<add|remove> <player|identity|ip>",
+                description: "Change who can join while the whitelist is enabled",
+                permission: CommandPermission::Admin,
+            })
+            .add_system(on_command.run_on_event::<CommandEvent>());
+    }
+
+    fn name(&self) -> &str {
+        "access_plugin"
+    }
+}
+
+/// Resolve a command argument to an access list entry, looking up player names among the known
+/// players.
+fn resolve_entry(
+    argument: &str,
+    player_query: &Query<(&Name, &PlayerIdentity), With<PlayerMarker>>,
+) -> Option<AccessEntry> {
+    player_query
+        .iter()
+        .find(|(name, _)| name.as_str() == argument)
+        .map(|(_, identity)| AccessEntry::Identity(*identity))
+        .or_else(|| AccessEntry::parse(argument))
+}
+
+fn on_command(
+    server: Res<RenetServer>,
+    mut events: EventReader<CommandEvent>,
+    mut feedback: EventWriter<CommandFeedback>,
+    mut ban_list: ResMut<BanList>,
+    mut whitelist: ResMut<Whitelist>,
+    mut pending_disconnects: ResMut<PendingDisconnects>,
+    player_query: Query<(&Name, &PlayerIdentity), With<PlayerMarker>>,
+    online_query: Query<(&PlayerIdentity, &PlayerClientId), Without<DisconnectedSince>>,
+) {
+    for event in events.iter() {
+        let (list, entry, insert) = match (event.name.as_str(), event.args.first()) {
+            ("ban", Some(argument)) => (
+                &mut ban_list.0,
+                resolve_entry(argument, &player_query),
+                true,
+            ),
+            ("unban", Some(argument)) => (&mut ban_list.0, AccessEntry::parse(argument), false),
+            ("whitelist", Some(action)) => {
+                let list = match &mut whitelist.0 {
+                    Some(list) => list,
+                    None => {
+                        feedback.send(event.reply("The whitelist is not enabled"));
+                        continue;
+                    }
+                };
+                let entry = event
+                    .args
+                    .get(1)
+                    .and_then(|argument| resolve_entry(argument, &player_query));
+                match action.as_str() {
+                    "add" => (list, entry, true),
+                    "remove" => (list, entry, false),
+                    _ => (list, None, false),
+                }
+            }
+            ("ban" | "unban" | "whitelist", None) => (&mut ban_list.0, None, false),
+            _ => continue,
+        };
+
+        let entry = match entry {
+            Some(entry) => entry,
+            None => {
+                let info = match event.name.as_str() {
+                    "ban" => "ban <player|identity|ip> [reason]",
+                    "unban" => "unban <identity|ip>",
+                    _ => "whitelist <add|remove> <player|identity|ip>",
+                };
+                feedback.send(event.reply(format!("Usage: {}", info)));
+                continue;
+            }
+        };
+
+        let changed = if insert {
+            list.insert(entry)
+        } else {
+            list.remove(entry)
+        };
+        if let Err(e) = list.save() {
+            warn!("Could not save {}: {}", list.path().display(), e);
+        }
+
+        if event.is("ban") {
+            for (identity, client_id) in online_query.iter() {
+                let ip = server.client_addr(client_id.0).map(|addr| addr.ip());
+                let banned = match entry {
+                    AccessEntry::Identity(banned) => *identity == banned,
+                    AccessEntry::Ip(banned) => ip == Some(banned),
+                };
+                if banned {
+                    pending_disconnects.disconnect(
+                        client_id.0,
+                        DisconnectReason::Banned,
+                        event.rest_arg(1),
+                    );
+                }
+            }
+        }
+
+        let message = match (changed, insert) {
+            (true, true) => format!("Added {} to {}", entry, list.path().display()),
+            (true, false) => format!("Removed {} from {}", entry, list.path().display()),
+            (false, true) => format!("{} is already in {}", entry, list.path().display()),
+            (false, false) => format!("{} is not in {}", entry, list.path().display()),
+        };
+        feedback.send(event.reply(message));
+    }
+}
@@ -0,0 +1,80 @@
+use bevy::prelude::{EventReader, EventWriter, Name, Plugin, Query, Res, ResMut};
+use iyes_loopless::prelude::IntoConditionalSystem;
+use spacegame_core::message::ServerMessageOutQueue;
+
+use crate::{
+    networking::player_id::PlayerIdMap,
+    shared::events::chat::{AnnouncementEvent, ChatMessageEvent, MAX_CHAT_MESSAGE_LENGTH},
+};
+
+use super::command::{CommandFeedback, CommandInput, CommandSender};
+
+pub struct ChatPlugin;
+
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_system(on_chat_message.run_on_event::<ChatMessageEvent>())
+            .add_system(send_command_feedback.run_on_event::<CommandFeedback>());
+    }
+
+    fn name(&self) -> &str {
+        "chat_plugin"
+    }
+}
+
+/// Pass chat messages on to everyone, or run them as commands if they start with `/`.
+fn on_chat_message(
+    player_ids: Res<PlayerIdMap>,
+    name_query: Query<&Name>,
+    mut events: EventReader<ChatMessageEvent>,
+    mut command_inputs: EventWriter<CommandInput>,
+    mut chat_queue: ResMut<ServerMessageOutQueue<ChatMessageEvent>>,
+) {
+    for event in events.iter() {
+        let message = event.message.trim();
+        if message.is_empty() || message.chars().count() > MAX_CHAT_MESSAGE_LENGTH {
+            continue;
+        }
+
+        let sender_name = match player_ids
+            .from_client(event.client_id)
+            .and_then(|player_entity| name_query.get(player_entity).ok())
+        {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+
+        if let Some(line) = message.strip_prefix('/') {
+            println!("{} ran /{}", sender_name, line);
+            command_inputs.send(CommandInput {
+                sender: CommandSender::Player(event.client_id),
+                line: line.to_string(),
+            });
+            continue;
+        }
+
+        println!("<{}> {}", sender_name, message);
+        chat_queue.broadcast(ChatMessageEvent {
+            sender_name,
+            message: message.to_string(),
+            client_id: event.client_id,
+        });
+    }
+}
+
+/// Show the output of commands run from the chat to whoever ran them.
+fn send_command_feedback(
+    mut events: EventReader<CommandFeedback>,
+    mut announcement_queue: ResMut<ServerMessageOutQueue<AnnouncementEvent>>,
+) {
+    for event in events.iter() {
+        if let CommandSender::Player(client_id) = event.sender {
+            announcement_queue.send(
+                &client_id,
+                AnnouncementEvent {
+                    message: event.message.clone(),
+                },
+            );
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::{App, EventReader, EventWriter, Plugin, Res, Vec3};
+use iyes_loopless::prelude::IntoConditionalSystem;
+use spacegame_core::message::ClientId;
+
+use super::roles::{PlayerRoles, Role};
+
+/// Who issued a command, and who gets to see its output.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CommandSender {
+    Console,
+    Player(ClientId),
+}
+
+/// Who may run a command. The console may always run every command.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CommandPermission {
+    Everyone,
+    Admin,
+}
+
+impl CommandPermission {
+    pub fn allows(self, role: Role) -> bool {
+        match self {
+            CommandPermission::Everyone => true,
+            CommandPermission::Admin => role == Role::Admin,
+        }
+    }
+}
+
+/// Describes a command so that it can be dispatched and listed by `help`.
+#[derive(Clone, Debug)]
+pub struct CommandInfo {
+    pub name: &'static str,
+    /// The arguments, e.g. `<player> <x y z>`.
+    pub usage: &'static str,
+    pub description: &'static str,
+    pub permission: CommandPermission,
+}
+
+/// All commands known to the server.
+///
+/// Plugins add their commands with [`AppCommandExt::add_command`] and handle them by reading
+/// [`CommandEvent`]s with their name.
+#[derive(Default)]
+pub struct CommandRegistry {
+    commands: BTreeMap<&'static str, CommandInfo>,
+}
+
+impl CommandRegistry {
+    pub fn register(&mut self, info: CommandInfo) {
+        if self.commands.insert(info.name, info.clone()).is_some() {
+            panic!("Command {} was registered twice", info.name);
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CommandInfo> {
+        self.commands.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &CommandInfo> {
+        self.commands.values()
+    }
+}
+
+/// A line of input to run as a command.
+pub struct CommandInput {
+    pub sender: CommandSender,
+    pub line: String,
+}
+
+/// A command that was found in the registry, to be handled by the plugin that registered it.
+pub struct CommandEvent {
+    pub sender: CommandSender,
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+impl CommandEvent {
+    pub fn is(&self, name: &str) -> bool {
+        self.name == name
+    }
+
+    /// Build the feedback for whoever sent this command.
+    pub fn reply(&self, message: impl Into<String>) -> CommandFeedback {
+        CommandFeedback {
+            sender: self.sender,
+            message: message.into(),
+        }
+    }
+
+    /// Parse three arguments starting at `index` as a position.
+    pub fn vec3_arg(&self, index: usize) -> Option<Vec3> {
+        let mut coordinates = self.args.get(index..index + 3)?.iter();
+        let mut next = || coordinates.next()?.parse::<f32>().ok();
+        Some(Vec3::new(next()?, next()?, next()?))
+    }
+
+    /// All arguments starting at `index` joined back together, for free text like messages.
+    pub fn rest_arg(&self, index: usize) -> Option<String> {
+        self.args
+            .get(index..)
+            .filter(|rest| !rest.is_empty())
+            .map(|rest| rest.join(" "))
+    }
+}
+
+/// Output of a command.
+pub struct CommandFeedback {
+    pub sender: CommandSender,
+    pub message: String,
+}
+
+pub trait AppCommandExt {
+    fn add_command(&mut self, info: CommandInfo) -> &mut Self;
+}
+
+impl AppCommandExt for App {
+    fn add_command(&mut self, info: CommandInfo) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(CommandRegistry::default)
+            .register(info);
+        self
+    }
+}
+
+pub struct CommandPlugin;
+
+impl Plugin for CommandPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<CommandInput>()
+            .add_event::<CommandEvent>()
+            .add_event::<CommandFeedback>()
+            .init_resource::<CommandRegistry>()
+            .add_command(CommandInfo {
+                name: "help",
+                usage: "",
+                description: "List all commands",
+                permission: CommandPermission::Everyone,
+            })
+            .add_system(dispatch_commands.run_on_event::<CommandInput>())
+            .add_system(on_help.run_on_event::<CommandEvent>())
+            .add_system(print_console_feedback.run_on_event::<CommandFeedback>());
+    }
+
+    fn name(&self) -> &str {
+        "command_plugin"
+    }
+}
+
+fn dispatch_commands(
+    registry: Res<CommandRegistry>,
+    roles: PlayerRoles,
+    mut inputs: EventReader<CommandInput>,
+    mut commands: EventWriter<CommandEvent>,
+    mut feedback: EventWriter<CommandFeedback>,
+) {
+    for input in inputs.iter() {
+        let mut words = input.line.split_whitespace().map(String::from);
+        let name = match words.next() {
+            Some(name) => name,
+            None => continue,
+        };
+
+        let message = match registry.get(&name) {
+            Some(info) if roles.may_run(input.sender, info) => None,
+            Some(_) => Some(format!("You are not allowed to run {}", name)),
+            None => Some(format!("Unknown command {}, try help", name)),
+        };
+        if let Some(message) = message {
+            feedback.send(CommandFeedback {
+                sender: input.sender,
+                message,
+            });
+            continue;
+        }
+
+        commands.send(CommandEvent {
+            sender: input.sender,
+            name,
+            args: words.collect(),
+        });
+    }
+}
+
+fn on_help(
+    registry: Res<CommandRegistry>,
+    roles: PlayerRoles,
+    mut events: EventReader<CommandEvent>,
+    mut feedback: EventWriter<CommandFeedback>,
+) {
+    for event in events.iter().filter(|event| event.is("help")) {
+        let lines = registry
+            .iter()
+            .filter(|info| roles.may_run(event.sender, info))
+            .map(|info| {
+                let command = format!("{} {}", info.name, info.usage);
+                format!("{} - {}", command.trim_end(), info.description)
+            })
+            .collect::<Vec<_>>();
+        feedback.send(event.reply(lines.join("\n")));
+    }
+}
+
+fn print_console_feedback(mut events: EventReader<CommandFeedback>) {
+    for event in events.iter() {
+        if event.sender == CommandSender::Console {
+            println!("{}", event.message);
+        }
+    }
+}
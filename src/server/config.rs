@@ -0,0 +1,261 @@
+use std::{
+    fs,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    shared::{config::ConfigError, networking::user_data::MAX_PASSWORD_LENGTH},
+    DEFAULT_PORT, DEFAULT_TOKEN_PORT,
+};
+
+use super::roles::Role;
+
+/// Command line arguments of the server binary.
+///
+/// Any argument given here overrides the value from the config file.
+#[derive(Parser, Debug)]
+#[command(name = "server", about = "Dedicated server for the space game")]
+pub struct ServerArgs {
+    /// Path to a TOML config file
+    #[arg(long, short)]
+    pub config: Option<PathBuf>,
+    /// Address to bind the server socket to
+    #[arg(long)]
+    pub bind: Option<IpAddr>,
+    /// Address clients use to reach this server, defaults to the bind address
+    #[arg(long)]
+    pub public_address: Option<IpAddr>,
+    #[arg(long, short)]
+    pub port: Option<u16>,
+    #[arg(long)]
+    pub max_clients: Option<usize>,
+    /// Simulation ticks per second
+    #[arg(long)]
+    pub tick_rate: Option<f64>,
+    /// Path of the world save file
+    #[arg(long)]
+    pub world: Option<PathBuf>,
+    /// Seconds between saves of the world, 0 to disable autosaving
+    #[arg(long)]
+    pub autosave_interval: Option<f32>,
+    /// Directory ship blueprints are loaded from
+    #[arg(long)]
+    pub blueprints: Option<PathBuf>,
+    /// Log filter, in the same format as `RUST_LOG`
+    #[arg(long)]
+    pub log: Option<String>,
+    /// Seconds a disconnected player is kept around for them to reconnect
+    #[arg(long)]
+    pub reconnect_grace_period: Option<f32>,
+    /// Path of the key shared with the token issuer
+    #[arg(long)]
+    pub private_key: Option<PathBuf>,
+    /// Address to hand out connect tokens on, see `token_service` in the config
+    #[arg(long)]
+    pub token_service: Option<SocketAddr>,
+    /// Only let players on the whitelist in this file join
+    #[arg(long)]
+    pub whitelist: Option<PathBuf>,
+    /// Password players need to join
+    #[arg(long)]
+    pub password: Option<String>,
+    /// Path of the file roles are granted in
+    #[arg(long)]
+    pub roles: Option<PathBuf>,
+    /// Accept clients without a connect token. Only meant for development
+    #[arg(long)]
+    pub unsecure: bool,
+}
+
+/// Resource holding the resolved server configuration.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ServerSettings {
+    pub bind_address: IpAddr,
+    pub public_address: Option<IpAddr>,
+    pub port: u16,
+    pub max_clients: usize,
+    pub tick_rate: f64,
+    pub world: PathBuf,
+    /// Seconds between saves of the world, 0 to only save on `save` and shutdown.
+    pub autosave_interval: f32,
+    pub blueprints: PathBuf,
+    pub log_filter: String,
+    pub reconnect_grace_period: f32,
+    /// Key shared with the token issuer, created on first start.
+    pub private_key_file: PathBuf,
+    /// Run the token issuer inside the server, listening on this address.
+    ///
+    /// Defaults to all interfaces on [`DEFAULT_TOKEN_PORT`], where clients ask for tokens by
+    /// default. Not started in unsecure mode.
+    pub token_service: Option<SocketAddr>,
+    /// Tokens come from a separately running `token_issuer` sharing the key file, so the server
+    /// doesn't run its own.
+    pub external_token_service: bool,
+    /// Seconds an issued token can be used to connect.
+    pub token_expire_seconds: u64,
+    /// Skip connect tokens altogether, anyone can claim any identity.
+    pub unsecure: bool,
+    /// Banned identities and IP addresses, kept up to date by the ban commands.
+    pub ban_list: PathBuf,
+    /// If set, only the identities and IP addresses in this file can join.
+    pub whitelist: Option<PathBuf>,
+    /// If set, players need to send this password to join.
+    pub password: Option<String>,
+    /// Roles granted to identities, kept up to date by the grant and revoke commands.
+    pub roles: PathBuf,
+    /// Role of players that have not been granted one.
+    pub default_role: Role,
+    /// Where factions were kept before they were saved with the world. Factions found there are
+    /// moved into the world when it is loaded.
+    pub factions: PathBuf,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            bind_address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            public_address: None,
+            port: DEFAULT_PORT,
+            max_clients: 64,
+            tick_rate: 64.,
+            world: PathBuf::from("world.ron"),
+            autosave_interval: 300.,
+            blueprints: PathBuf::from("blueprints"),
+            log_filter: String::from("info,spacegame=trace"),
+            reconnect_grace_period: 60.,
+            private_key_file: PathBuf::from("server.key"),
+            token_service: Some(SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                DEFAULT_TOKEN_PORT,
+            )),
+            external_token_service: false,
+            token_expire_seconds: 300,
+            unsecure: false,
+            ban_list: PathBuf::from("bans.toml"),
+            whitelist: None,
+            password: None,
+            roles: PathBuf::from("roles.toml"),
+            default_role: Role::Builder,
+            factions: PathBuf::from("factions.toml"),
+        }
+    }
+}
+
+impl ServerSettings {
+    /// Resolve the settings from the config file given in `args`, if any, then apply the
+    /// command line overrides on top.
+    pub fn load(args: ServerArgs) -> Result<Self, ConfigError> {
+        let mut settings = match &args.config {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+
+        if let Some(bind) = args.bind {
+            settings.bind_address = bind;
+        }
+        if let Some(public_address) = args.public_address {
+            settings.public_address = Some(public_address);
+        }
+        if let Some(port) = args.port {
+            settings.port = port;
+        }
+        if let Some(max_clients) = args.max_clients {
+            settings.max_clients = max_clients;
+        }
+        if let Some(tick_rate) = args.tick_rate {
+            settings.tick_rate = tick_rate;
+        }
+        if let Some(world) = args.world {
+            settings.world = world;
+        }
+        if let Some(autosave_interval) = args.autosave_interval {
+            settings.autosave_interval = autosave_interval;
+        }
+        if let Some(blueprints) = args.blueprints {
+            settings.blueprints = blueprints;
+        }
+        if let Some(log) = args.log {
+            settings.log_filter = log;
+        }
+        if let Some(reconnect_grace_period) = args.reconnect_grace_period {
+            settings.reconnect_grace_period = reconnect_grace_period;
+        }
+        if let Some(private_key) = args.private_key {
+            settings.private_key_file = private_key;
+        }
+        if let Some(token_service) = args.token_service {
+            settings.token_service = Some(token_service);
+        }
+        if args.unsecure {
+            settings.unsecure = true;
+        }
+        if let Some(whitelist) = args.whitelist {
+            settings.whitelist = Some(whitelist);
+        }
+        if let Some(password) = args.password {
+            settings.password = Some(password);
+        }
+        if let Some(roles) = args.roles {
+            settings.roles = roles;
+        }
+
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let contents = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        toml::from_str(&contents).map_err(ConfigError::Parse)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.max_clients == 0 {
+            return Err(ConfigError::Invalid("max_clients must be at least 1"));
+        }
+        if !(self.tick_rate > 0.) {
+            return Err(ConfigError::Invalid("tick_rate must be positive"));
+        }
+        if !(self.reconnect_grace_period >= 0.) {
+            return Err(ConfigError::Invalid(
+                "reconnect_grace_period must not be negative",
+            ));
+        }
+        if self
+            .password
+            .as_ref()
+            .map_or(false, |password| password.len() > MAX_PASSWORD_LENGTH)
+        {
+            return Err(ConfigError::Invalid(
+                "password must be at most 64 bytes long",
+            ));
+        }
+        Ok(())
+    }
+
+    /// The address the server socket binds to.
+    pub fn bind_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.bind_address, self.port)
+    }
+
+    pub fn reconnect_grace_period(&self) -> Duration {
+        Duration::from_secs_f32(self.reconnect_grace_period)
+    }
+
+    /// The address clients connect to.
+    ///
+    /// Falls back to the bind address, or localhost when bound to all interfaces.
+    pub fn public_addr(&self) -> SocketAddr {
+        let ip = match self.public_address {
+            Some(ip) => ip,
+            None if self.bind_address.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            None => self.bind_address,
+        };
+        SocketAddr::new(ip, self.port)
+    }
+}
@@ -0,0 +1,58 @@
+use std::{
+    io::{self, BufRead},
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Mutex,
+    },
+    thread,
+};
+
+use bevy::prelude::{warn, EventWriter, Plugin, Res};
+
+use super::command::{CommandInput, CommandSender};
+
+/// Lines typed into the server's stdin, read on a separate thread so that waiting for input does
+/// not block the tick.
+struct ConsoleInput(Mutex<Receiver<String>>);
+
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        let (sender, receiver) = mpsc::channel();
+
+        let result = thread::Builder::new()
+            .name(String::from("console"))
+            .spawn(move || {
+                for line in io::stdin().lock().lines() {
+                    match line {
+                        Ok(line) if sender.send(line).is_ok() => {}
+                        _ => break,
+                    }
+                }
+            });
+        if let Err(e) = result {
+            warn!("Could not start the console: {}", e);
+        }
+
+        app.insert_resource(ConsoleInput(Mutex::new(receiver)))
+            .add_system(read_console_input);
+    }
+
+    fn name(&self) -> &str {
+        "console_plugin"
+    }
+}
+
+fn read_console_input(console: Res<ConsoleInput>, mut inputs: EventWriter<CommandInput>) {
+    let receiver = console.0.lock().unwrap();
+    loop {
+        match receiver.try_recv() {
+            Ok(line) => inputs.send(CommandInput {
+                sender: CommandSender::Console,
+                line,
+            }),
+            Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => break,
+        }
+    }
+}
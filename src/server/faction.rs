@@ -0,0 +1,438 @@
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+use bevy::prelude::{
+    Added, Entity, EventReader, EventWriter, Name, Plugin, Query, Res, ResMut, With,
+};
+use bevy_renet::renet::ServerEvent;
+use iyes_loopless::prelude::IntoConditionalSystem;
+use serde::Deserialize;
+use spacegame_core::message::ServerMessageOutQueue;
+
+use crate::shared::{
+    config::ConfigError,
+    entities::player::PlayerMarker,
+    events::{faction::FactionSyncEvent, ship::ShipOwnershipEvent},
+    model::{
+        faction::{FactionInfo, Relation},
+        ship::{Pilot, Ship},
+    },
+    networking::{identity::PlayerIdentity, user_data::validate_player_name},
+};
+
+use super::{
+    command::{
+        AppCommandExt, CommandEvent, CommandFeedback, CommandInfo, CommandPermission, CommandSender,
+    },
+    lifecycle::SaveWorldRequest,
+    ownership::{ship_ownership, Owner, SharedRights},
+    player::player_name,
+    roles::PlayerRoles,
+};
+
+pub struct Faction {
+    pub leader: PlayerIdentity,
+    /// Everyone in the faction, including the leader.
+    pub members: Vec<PlayerIdentity>,
+    /// Players that may join.
+    pub invites: Vec<PlayerIdentity>,
+    /// How this faction stands towards others, neutral unless set.
+    pub relations: BTreeMap<String, Relation>,
+}
+
+/// All factions by name, saved with the world.
+#[derive(Default)]
+pub struct Factions {
+    factions: BTreeMap<String, Faction>,
+}
+
+/// How a [`Faction`] was stored in the factions file, with identities in their hex form.
+#[derive(Deserialize)]
+struct FactionFile {
+    leader: String,
+    #[serde(default)]
+    members: Vec<String>,
+    #[serde(default)]
+    invites: Vec<String>,
+    #[serde(default)]
+    relations: BTreeMap<String, Relation>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct FactionsFile {
+    factions: BTreeMap<String, FactionFile>,
+}
+
+fn parse_identities(identities: &[String]) -> Result<Vec<PlayerIdentity>, ConfigError> {
+    identities
+        .iter()
+        .map(|identity| identity.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| ConfigError::Invalid("factions must contain identities in hex"))
+}
+
+impl FromIterator<(String, Faction)> for Factions {
+    fn from_iter<I: IntoIterator<Item = (String, Faction)>>(iter: I) -> Self {
+        Self {
+            factions: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl Factions {
+    /// Load the factions file at `path`, where factions were kept before worlds saved them, or
+    /// `None` if there is no such file.
+    pub fn load_legacy(path: &Path) -> Result<Option<Self>, ConfigError> {
+        let file = match fs::read_to_string(path) {
+            Ok(contents) => {
+                toml::from_str::<FactionsFile>(&contents).map_err(ConfigError::Parse)?
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(ConfigError::Io(e)),
+        };
+
+        let mut factions = BTreeMap::new();
+        for (name, faction) in file.factions {
+            let leader = faction
+                .leader
+                .parse()
+                .map_err(|_| ConfigError::Invalid("factions must contain identities in hex"))?;
+            factions.insert(
+                name,
+                Faction {
+                    leader,
+                    members: parse_identities(&faction.members)?,
+                    invites: parse_identities(&faction.invites)?,
+                    relations: faction.relations,
+                },
+            );
+        }
+
+        Ok(Some(Self { factions }))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Faction)> {
+        self.factions
+            .iter()
+            .map(|(name, faction)| (name.as_str(), faction))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.factions.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Faction> {
+        self.factions.get(name)
+    }
+
+    /// The faction a player is a member of, along with its name.
+    pub fn faction_of(&self, identity: &PlayerIdentity) -> Option<(&str, &Faction)> {
+        self.factions
+            .iter()
+            .find(|(_, faction)| faction.members.contains(identity))
+            .map(|(name, faction)| (name.as_str(), faction))
+    }
+
+    pub fn is_member(&self, faction: &str, identity: &PlayerIdentity) -> bool {
+        self.get(faction)
+            .map_or(false, |faction| faction.members.contains(identity))
+    }
+
+    /// How `from` stands towards `to`. A faction is always allied with itself.
+    pub fn relation(&self, from: &str, to: &str) -> Relation {
+        if from == to {
+            return Relation::Allied;
+        }
+        self.get(from)
+            .and_then(|faction| faction.relations.get(to))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Two factions are only allies if both say so.
+    pub fn are_allied(&self, a: &str, b: &str) -> bool {
+        self.relation(a, b) == Relation::Allied && self.relation(b, a) == Relation::Allied
+    }
+
+    /// What clients get to see of the factions.
+    pub fn info(
+        &self,
+        player_query: &Query<(&Name, &PlayerIdentity), With<PlayerMarker>>,
+    ) -> Vec<FactionInfo> {
+        self.factions
+            .iter()
+            .map(|(name, faction)| FactionInfo {
+                name: name.clone(),
+                leader: player_name(&faction.leader, player_query),
+                members: faction
+                    .members
+                    .iter()
+                    .map(|member| player_name(member, player_query))
+                    .collect(),
+                relations: faction
+                    .relations
+                    .iter()
+                    .filter(|(_, relation)| **relation != Relation::Neutral)
+                    .map(|(other, relation)| (other.clone(), *relation))
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+pub struct FactionPlugin;
+
+impl Plugin for FactionPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        // Filled in once the world is loaded
+        app.init_resource::<Factions>()
+            .add_command(CommandInfo {
+                name: "faction",
+                usage: "<list|create|invite|join|leave|relation|claim> [...]",
+                description: "Manage your faction, run it without arguments for details",
+                permission: CommandPermission::Everyone,
+            })
+            .add_system(on_command.run_on_event::<CommandEvent>())
+            .add_system(sync_factions)
+            .add_system(on_client_connect);
+    }
+
+    fn name(&self) -> &str {
+        "faction_plugin"
+    }
+}
+
+const FACTION_USAGE: &str = "faction list
+faction create <name>
+faction invite <player> - invite a player to your faction, as its leader
+faction join <name> - join a faction you were invited to
+faction leave
+faction relation <faction> <allied|neutral|hostile> - as the leader of your faction
+faction claim - hand the ship you are piloting over to your faction";
+
+fn on_command(
+    roles: PlayerRoles,
+    mut factions: ResMut<Factions>,
+    mut events: EventReader<CommandEvent>,
+    mut feedback: EventWriter<CommandFeedback>,
+    player_query: Query<(&Name, &PlayerIdentity), With<PlayerMarker>>,
+    mut ship_query: Query<(Entity, &Pilot, &mut Owner, &SharedRights), With<Ship>>,
+    mut ownership_queue: ResMut<ServerMessageOutQueue<ShipOwnershipEvent>>,
+    mut save_requests: EventWriter<SaveWorldRequest>,
+) {
+    for event in events.iter().filter(|event| event.is("faction")) {
+        let action = event.args.first().map(String::as_str);
+        if action == Some("list") {
+            let lines = factions
+                .info(&player_query)
+                .into_iter()
+                .map(|info| {
+                    format!(
+                        "{} ({}): {}",
+                        info.name,
+                        info.leader,
+                        info.members.join(", ")
+                    )
+                })
+                .collect::<Vec<_>>();
+            let message = if lines.is_empty() {
+                String::from("There are no factions")
+            } else {
+                lines.join("\n")
+            };
+            feedback.send(event.reply(message));
+            continue;
+        }
+
+        let (client_id, identity) = match event.sender {
+            CommandSender::Player(client_id) => match roles.identity_of(client_id) {
+                Some(identity) => (client_id, *identity),
+                None => continue,
+            },
+            CommandSender::Console => {
+                feedback.send(event.reply("Only players can be in a faction"));
+                continue;
+            }
+        };
+        let own_faction = factions
+            .faction_of(&identity)
+            .map(|(name, faction)| (name.to_string(), faction.leader == identity));
+
+        let message = match (action, event.args.get(1), &own_faction) {
+            (Some("create"), Some(name), None) => {
+                if let Err(e) = validate_player_name(name) {
+                    format!("Invalid faction name: {}", e)
+                } else if factions.get(name).is_some() {
+                    format!("Faction {} already exists", name)
+                } else {
+                    factions.factions.insert(
+                        name.clone(),
+                        Faction {
+                            leader: identity,
+                            members: vec![identity],
+                            invites: Vec::new(),
+                            relations: BTreeMap::new(),
+                        },
+                    );
+                    format!("Created faction {}", name)
+                }
+            }
+            (Some("create" | "join"), Some(_), Some((name, _))) => {
+                format!("You are already in faction {}", name)
+            }
+            (Some("invite"), Some(player), Some((name, true))) => {
+                let invited = player_query
+                    .iter()
+                    .find(|(player_name, _)| player_name.as_str() == player)
+                    .map(|(_, identity)| *identity);
+                match invited {
+                    Some(invited) => {
+                        let faction = factions.factions.get_mut(name).unwrap();
+                        if !faction.invites.contains(&invited) {
+                            faction.invites.push(invited);
+                        }
+                        format!("Invited {} to {}", player, name)
+                    }
+                    None => format!("No player named {}", player),
+                }
+            }
+            (Some("join"), Some(name), None) => match factions.factions.get_mut(name) {
+                Some(faction) if faction.invites.contains(&identity) => {
+                    faction.invites.retain(|invited| *invited != identity);
+                    faction.members.push(identity);
+                    format!("Joined faction {}", name)
+                }
+                Some(_) => format!("You have not been invited to {}", name),
+                None => format!("No faction named {}", name),
+            },
+            (Some("leave"), _, Some((name, _))) => {
+                leave_faction(&mut factions, name, &identity);
+                if factions.get(name).is_none() {
+                    // Nobody is left to look after the ships of the faction
+                    for (ship_entity, _, mut owner, shared_rights) in ship_query.iter_mut() {
+                        if *owner == Owner::Faction(name.clone()) {
+                            *owner = Owner::None;
+                            ownership_queue.broadcast(ShipOwnershipEvent {
+                                ship_entity,
+                                ownership: ship_ownership(&owner, shared_rights, &player_query),
+                            });
+                        }
+                    }
+                    format!("Left faction {}, which was disbanded", name)
+                } else {
+                    format!("Left faction {}", name)
+                }
+            }
+            (Some("relation"), Some(other), Some((name, true))) => {
+                let relation = event.args.get(2).and_then(|relation| relation.parse().ok());
+                match relation {
+                    _ if other == name => {
+                        String::from("Your faction can not change its own relation")
+                    }
+                    _ if factions.get(other).is_none() => format!("No faction named {}", other),
+                    Some(relation) => {
+                        let faction = factions.factions.get_mut(name).unwrap();
+                        if relation == Relation::Neutral {
+                            faction.relations.remove(other);
+                        } else {
+                            faction.relations.insert(other.clone(), relation);
+                        }
+                        format!("{} is now {} towards {}", name, relation, other)
+                    }
+                    None => {
+                        String::from("Usage: faction relation <faction> <allied|neutral|hostile>")
+                    }
+                }
+            }
+            (Some("claim"), _, Some((name, _))) => {
+                let ship = ship_query.iter_mut().find(|(_, pilot, _, _)| {
+                    matches!(pilot, Pilot::Pilot(pilot_id) if *pilot_id == client_id)
+                });
+                match ship {
+                    Some((ship_entity, _, mut owner, shared_rights))
+                        if *owner == Owner::Player(identity) =>
+                    {
+                        *owner = Owner::Faction(name.clone());
+                        ownership_queue.broadcast(ShipOwnershipEvent {
+                            ship_entity,
+                            ownership: ship_ownership(&owner, shared_rights, &player_query),
+                        });
+                        format!("Handed the ship over to {}", name)
+                    }
+                    Some(_) => String::from("You can only hand over ships you own"),
+                    None => String::from("You need to pilot the ship you want to hand over"),
+                }
+            }
+            (Some("invite" | "relation"), Some(_), Some(_)) => {
+                String::from("Only the leader of your faction can do that")
+            }
+            (Some("invite" | "relation" | "leave" | "claim"), _, None) => {
+                String::from("You are not in a faction")
+            }
+            _ => format!("Usage:\n{}", FACTION_USAGE),
+        };
+
+        feedback.send(event.reply(message));
+    }
+
+    // Factions are part of the world, so save it right away rather than lose them in a crash
+    if factions.is_changed() {
+        save_requests.send(SaveWorldRequest);
+    }
+}
+
+/// Take a player out of a faction, handing the lead to the next member and disbanding the faction
+/// once it is empty.
+fn leave_faction(factions: &mut Factions, name: &str, identity: &PlayerIdentity) {
+    let faction = match factions.factions.get_mut(name) {
+        Some(faction) => faction,
+        None => return,
+    };
+    faction.members.retain(|member| member != identity);
+
+    match faction.members.first() {
+        Some(next_leader) => {
+            if faction.leader == *identity {
+                faction.leader = *next_leader;
+            }
+        }
+        None => {
+            factions.factions.remove(name);
+            for faction in factions.factions.values_mut() {
+                faction.relations.remove(name);
+            }
+        }
+    }
+}
+
+/// Tell everyone about changed factions, and about the names of members that just came online.
+fn sync_factions(
+    factions: Res<Factions>,
+    joined_query: Query<(), Added<PlayerIdentity>>,
+    player_query: Query<(&Name, &PlayerIdentity), With<PlayerMarker>>,
+    mut faction_sync_queue: ResMut<ServerMessageOutQueue<FactionSyncEvent>>,
+) {
+    if factions.is_changed() || !joined_query.is_empty() {
+        faction_sync_queue.broadcast(FactionSyncEvent {
+            factions: factions.info(&player_query),
+        });
+    }
+}
+
+fn on_client_connect(
+    factions: Res<Factions>,
+    mut server_events: EventReader<ServerEvent>,
+    player_query: Query<(&Name, &PlayerIdentity), With<PlayerMarker>>,
+    mut faction_sync_queue: ResMut<ServerMessageOutQueue<FactionSyncEvent>>,
+) {
+    for event in server_events.iter() {
+        if let ServerEvent::ClientConnected(client_id, _) = event {
+            faction_sync_queue.send(
+                client_id,
+                FactionSyncEvent {
+                    factions: factions.info(&player_query),
+                },
+            );
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use bevy::{
+    app::AppExit,
+    prelude::{info, warn, CoreStage, EventReader, EventWriter, Plugin, Res, ResMut},
+};
+use bevy_renet::renet::RenetServer;
+use iyes_loopless::prelude::IntoConditionalSystem;
+use spacegame_core::message::{ClientId, ServerMessageOutQueue};
+
+use crate::shared::events::connection::{DisconnectNoticeEvent, DisconnectReason};
+
+use super::command::{AppCommandExt, CommandEvent, CommandFeedback, CommandInfo, CommandPermission};
+
+/// Ask the server to notify its clients, save the world and exit.
+pub struct ShutdownRequest;
+
+/// Ask the world to be saved to disk.
+pub struct SaveWorldRequest;
+
+/// Clients to disconnect once they have been told why.
+///
+/// The notice is sent first and the connection is only dropped on the following tick, after the
+/// outgoing queues have been flushed, so that the notice does not get lost.
+#[derive(Default)]
+pub struct PendingDisconnects {
+    queued: Vec<(ClientId, DisconnectReason, Option<String>)>,
+    notified: Vec<ClientId>,
+}
+
+impl PendingDisconnects {
+    pub fn disconnect(
+        &mut self,
+        client_id: ClientId,
+        reason: DisconnectReason,
+        message: Option<String>,
+    ) {
+        self.queued.push((client_id, reason, message));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queued.is_empty() && self.notified.is_empty()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ShutdownState {
+    Running,
+    /// Clients are being notified and the world saved.
+    ShuttingDown,
+    /// Everything is flushed, the app exits at the end of this tick.
+    Exiting,
+}
+
+/// Set from the Ctrl-C handler, which runs outside the ECS.
+struct CtrlCPressed(Arc<AtomicBool>);
+
+pub struct LifecyclePlugin;
+
+impl Plugin for LifecyclePlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        let ctrl_c_pressed = Arc::new(AtomicBool::new(false));
+        let handler_flag = ctrl_c_pressed.clone();
+        let result = ctrlc::set_handler(move || {
+            if handler_flag.swap(true, Ordering::SeqCst) {
+                // Pressed a second time, the graceful shutdown seems to be stuck
+                std::process::exit(1);
+            }
+        });
+        if let Err(e) = result {
+            warn!("Could not install Ctrl-C handler: {}", e);
+        }
+
+        app.add_event::<ShutdownRequest>()
+            .add_event::<SaveWorldRequest>()
+            .insert_resource(PendingDisconnects::default())
+            .insert_resource(ShutdownState::Running)
+            .insert_resource(CtrlCPressed(ctrl_c_pressed))
+            .add_system_to_stage(CoreStage::First, process_disconnects)
+            .add_command(CommandInfo {
+                name: "save",
+                usage: "",
+                description: "Save the world",
+                permission: CommandPermission::Admin,
+            })
+            .add_command(CommandInfo {
+                name: "stop",
+                usage: "",
+                description: "Notify all players, save the world and stop the server",
+                permission: CommandPermission::Admin,
+            })
+            .add_system(watch_ctrl_c)
+            .add_system(on_command.run_on_event::<CommandEvent>())
+            .add_system_to_stage(CoreStage::Last, shutdown);
+    }
+
+    fn name(&self) -> &str {
+        "lifecycle_plugin"
+    }
+}
+
+fn watch_ctrl_c(
+    ctrl_c_pressed: Res<CtrlCPressed>,
+    mut shutdown_requests: EventWriter<ShutdownRequest>,
+) {
+    if ctrl_c_pressed.0.load(Ordering::SeqCst) {
+        shutdown_requests.send(ShutdownRequest);
+    }
+}
+
+fn on_command(
+    mut events: EventReader<CommandEvent>,
+    mut feedback: EventWriter<CommandFeedback>,
+    mut save_requests: EventWriter<SaveWorldRequest>,
+    mut shutdown_requests: EventWriter<ShutdownRequest>,
+) {
+    for event in events.iter() {
+        if event.is("save") {
+            save_requests.send(SaveWorldRequest);
+            feedback.send(event.reply("Saving the world"));
+        } else if event.is("stop") {
+            shutdown_requests.send(ShutdownRequest);
+            feedback.send(event.reply("Stopping the server"));
+        }
+    }
+}
+
+fn process_disconnects(
+    mut pending: ResMut<PendingDisconnects>,
+    mut server: ResMut<RenetServer>,
+    mut notice_queue: ResMut<ServerMessageOutQueue<DisconnectNoticeEvent>>,
+) {
+    let pending = &mut *pending;
+    for client_id in pending.notified.drain(..) {
+        server.disconnect(client_id);
+    }
+
+    for (client_id, reason, message) in pending.queued.drain(..) {
+        info!("Disconnecting client [{}]: {}", client_id, reason);
+        notice_queue.send(&client_id, DisconnectNoticeEvent { reason, message });
+        pending.notified.push(client_id);
+    }
+}
+
+/// Runs at the end of the tick, so that the last round of messages has been sent before exiting.
+fn shutdown(
+    mut state: ResMut<ShutdownState>,
+    server: Res<RenetServer>,
+    mut pending: ResMut<PendingDisconnects>,
+    mut shutdown_requests: EventReader<ShutdownRequest>,
+    mut save_requests: EventWriter<SaveWorldRequest>,
+    mut app_exit: EventWriter<AppExit>,
+) {
+    match *state {
+        ShutdownState::Running => {
+            if shutdown_requests.iter().last().is_none() {
+                return;
+            }
+
+            info!("Shutting down");
+            for client_id in server.clients_id() {
+                pending.disconnect(client_id, DisconnectReason::ServerShuttingDown, None);
+            }
+            save_requests.send(SaveWorldRequest);
+            *state = ShutdownState::ShuttingDown;
+        }
+        ShutdownState::ShuttingDown => {
+            shutdown_requests.clear();
+            if pending.is_empty() {
+                *state = ShutdownState::Exiting;
+            }
+        }
+        ShutdownState::Exiting => {
+            info!("Goodbye");
+            app_exit.send(AppExit);
+        }
+    }
+}
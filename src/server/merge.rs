@@ -0,0 +1,503 @@
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::{
+        BuildChildren, ChangeTrackers, Commands, Component, CoreStage, DespawnRecursiveExt, Entity,
+        EventReader, EventWriter, Mat3, ParallelSystemDescriptorCoercion, Plugin, Quat, Query, Res,
+        ResMut, Transform, Vec3, With,
+    },
+    utils::HashSet,
+};
+use bevy_rapier3d::prelude::Velocity;
+use iyes_loopless::prelude::IntoConditionalSystem;
+use spacegame_core::message::ServerMessageOutQueue;
+
+use crate::{
+    chunk_collider::ChunkLabels,
+    events::ship::{BlockUpdateEvent, LeftShipEvent, UnloadShipEvent},
+    model::{
+        block::BlockBundle,
+        block_definitions::{BlockDefinitions, BlockTag},
+        block_map::{BlockBounds, BlockFace, BlockMap, BlockPosition, BlockRotation},
+        chunk::ChunkPosition,
+        ship::{Pilot, Ship},
+    },
+};
+
+use super::{
+    command::{
+        AppCommandExt, CommandEvent, CommandFeedback, CommandInfo, CommandPermission, CommandSender,
+    },
+    ownership::Owner,
+    physics::ShipMass,
+    propulsion::ShipPropulsion,
+};
+
+/// How far off the grid of another ship a ship may be and still be merged into it, in radians.
+const MAX_MISALIGNMENT_ANGLE: f32 = 0.1;
+/// How far off the grid of another ship a ship may be and still be merged into it, in blocks.
+const MAX_MISALIGNMENT_OFFSET: f32 = 0.25;
+
+pub struct MergePlugin;
+
+impl Plugin for MergePlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_system(merge_touching_ships)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                update_merge_blocks.label(ChunkLabels::Rebuild),
+            )
+            .add_command(CommandInfo {
+                name: "merge",
+                usage: "",
+                description:
+                    "Merge the closest ship lined up with the ship you are piloting into it",
+                permission: CommandPermission::Admin,
+            })
+            .add_system(on_command.run_on_event::<CommandEvent>());
+    }
+
+    fn name(&self) -> &str {
+        "merge_plugin"
+    }
+}
+
+/// How the grid of one ship lies in the grid of another one.
+#[derive(Clone, Copy, Debug)]
+pub struct GridAlignment {
+    pub rotation: BlockRotation,
+    pub offset: BlockPosition,
+}
+
+impl GridAlignment {
+    /// How the ship at `other` lies in the grid of the ship at `target`, if their grids line up.
+    pub fn between(target: &Transform, other: &Transform) -> Option<Self> {
+        let inverse = target.rotation.inverse();
+
+        let relative_rotation = inverse * other.rotation;
+        let rotation = BlockRotation::from(relative_rotation);
+        if Quat::from(rotation).angle_between(relative_rotation) > MAX_MISALIGNMENT_ANGLE {
+            return None;
+        }
+
+        let relative_offset = inverse * (other.translation - target.translation);
+        let offset = BlockPosition::rounded(relative_offset);
+        if (relative_offset - Vec3::from(offset)).abs().max_element() > MAX_MISALIGNMENT_OFFSET {
+            return None;
+        }
+
+        Some(Self { rotation, offset })
+    }
+
+    pub fn position(&self, position: BlockPosition) -> BlockPosition {
+        let rotated = self.rotation.rotate_position(position);
+        BlockPosition::new(
+            rotated.x + self.offset.x,
+            rotated.y + self.offset.y,
+            rotated.z + self.offset.z,
+        )
+    }
+
+    pub fn rotation(&self, rotation: BlockRotation) -> BlockRotation {
+        self.rotation.compose(rotation)
+    }
+
+    /// Where a box of blocks lies in the other grid.
+    pub fn bounds(&self, bounds: BlockBounds) -> BlockBounds {
+        // Rotations only swap and flip axes, so the corners stay opposite corners
+        BlockBounds::point(self.position(bounds.min)).including(self.position(bounds.max))
+    }
+
+    /// Whether the blocks of `other` fit into `target` this way, touching it without overlapping.
+    pub fn fits(&self, target: &BlockMap, other: &BlockMap) -> bool {
+        let mut touches = false;
+        for (position, _) in other.entries() {
+            let position = self.position(position);
+            if target.contains(&position) {
+                return false;
+            }
+            touches |= BlockFace::ALL
+                .into_iter()
+                .any(|face| target.contains(&position.neighbour(face)));
+        }
+        touches
+    }
+}
+
+/// The merge blocks of a ship, kept up to date from the chunks that changed so that finding
+/// touching ships doesn't need to go through every block.
+#[derive(Component, Default, Debug)]
+pub struct MergeBlocks {
+    positions: HashSet<BlockPosition>,
+    bounds: Option<BlockBounds>,
+}
+
+impl MergeBlocks {
+    pub fn contains(&self, position: &BlockPosition) -> bool {
+        self.positions.contains(position)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Rescan the chunks in `dirty` of `block_map`.
+    fn update(
+        &mut self,
+        definitions: &BlockDefinitions,
+        block_map: &BlockMap,
+        dirty: &HashSet<ChunkPosition>,
+    ) {
+        self.positions
+            .retain(|position| !dirty.contains(&ChunkPosition::of(*position)));
+        for chunk_position in dirty {
+            let chunk = match block_map.chunk(chunk_position) {
+                Some(chunk) => chunk,
+                None => continue,
+            };
+            let origin = chunk_position.origin();
+            self.positions.extend(
+                chunk
+                    .blocks()
+                    .filter(|(_, block)| {
+                        definitions
+                            .get(block.block_type)
+                            .map_or(false, |definition| definition.has_tag(BlockTag::Merge))
+                    })
+                    .map(|(local, _)| {
+                        BlockPosition::new(
+                            origin.x + local.x,
+                            origin.y + local.y,
+                            origin.z + local.z,
+                        )
+                    }),
+            );
+        }
+
+        self.bounds = self
+            .positions
+            .iter()
+            .fold(None, |bounds: Option<BlockBounds>, position| {
+                Some(match bounds {
+                    Some(bounds) => bounds.including(*position),
+                    None => BlockBounds::point(*position),
+                })
+            });
+    }
+}
+
+#[derive(SystemParam)]
+pub struct MergeQueues<'w, 's> {
+    block_update: ResMut<'w, ServerMessageOutQueue<BlockUpdateEvent>>,
+    unload_ship: ResMut<'w, ServerMessageOutQueue<UnloadShipEvent>>,
+    left_ship: ResMut<'w, ServerMessageOutQueue<LeftShipEvent>>,
+    #[system_param(ignore)]
+    marker: std::marker::PhantomData<&'s ()>,
+}
+
+type ShipQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static mut BlockMap,
+        &'static mut ShipMass,
+        &'static mut ShipPropulsion,
+        &'static Transform,
+        &'static mut Velocity,
+        &'static Pilot,
+        &'static Owner,
+    ),
+    With<Ship>,
+>;
+
+/// Move the blocks of `absorbed` into `target` and despawn it, keeping the momentum of both.
+///
+/// `alignment` is where `absorbed` lies in the grid of `target`, see [`GridAlignment::between`].
+pub fn merge_ships(
+    commands: &mut Commands,
+    definitions: &BlockDefinitions,
+    ships: &mut ShipQuery,
+    queues: &mut MergeQueues,
+    target: Entity,
+    absorbed: Entity,
+    alignment: GridAlignment,
+) {
+    let [target_ship, absorbed_ship] = match ships.get_many_mut([target, absorbed]) {
+        Ok(ships) => ships,
+        Err(_) => return,
+    };
+    let (_, mut block_map, mut ship_mass, mut propulsion, transform, mut velocity, ..) =
+        target_ship;
+    let (
+        _,
+        absorbed_map,
+        absorbed_mass,
+        _,
+        absorbed_transform,
+        absorbed_velocity,
+        absorbed_pilot,
+        _,
+    ) = absorbed_ship;
+
+    for (position, entry) in absorbed_map.entries() {
+        let position = alignment.position(position);
+        let rotation = alignment.rotation(entry.block_rotation);
+        let block_entity = commands
+            .spawn_bundle(BlockBundle::new(entry.block_type, position, rotation))
+            .id();
+        commands.entity(target).add_child(block_entity);
+        if let Some(old_entity) = block_map
+            .set(position, entry.block_type, rotation)
+            .and_then(|old_block| old_block.entity)
+        {
+            commands.entity(old_entity).despawn_recursive();
+        }
+        block_map.set_entity(position, block_entity);
+
+        queues.block_update.broadcast(BlockUpdateEvent {
+            ship_entity: target,
+            block_type: entry.block_type,
+            block_position: position,
+            block_rotation: rotation,
+            client_id: 0,
+        });
+    }
+
+    // Keep the linear and angular momentum of both ships, around the new center of mass
+    let merged_mass = ShipMass::from_block_map(definitions, &block_map);
+    let center = transform.mul_vec3(merged_mass.center_of_mass());
+    let parts = [
+        (&*ship_mass, transform, *velocity),
+        (&*absorbed_mass, absorbed_transform, *absorbed_velocity),
+    ];
+
+    let total_mass: f32 = parts.iter().map(|(mass, _, _)| mass.mass()).sum();
+    let mut momentum = Vec3::ZERO;
+    let mut angular_momentum = Vec3::ZERO;
+    for (mass, transform, velocity) in parts {
+        let rotation = Mat3::from_quat(transform.rotation);
+        let inertia = rotation * mass.inertia() * rotation.transpose();
+        let offset = transform.mul_vec3(mass.center_of_mass()) - center;
+
+        momentum += velocity.linvel * mass.mass();
+        angular_momentum += inertia * velocity.angvel + offset.cross(velocity.linvel * mass.mass());
+    }
+
+    let rotation = Mat3::from_quat(transform.rotation);
+    let inertia = rotation * merged_mass.inertia() * rotation.transpose();
+    if total_mass > 0. {
+        velocity.linvel = momentum / total_mass;
+    }
+    if inertia.determinant().abs() > f32::EPSILON {
+        velocity.angvel = inertia.inverse() * angular_momentum;
+    }
+
+    *ship_mass = merged_mass;
+    *propulsion = ShipPropulsion::from_block_map(definitions, &block_map);
+
+    if let Pilot::Pilot(pilot_id) = *absorbed_pilot {
+        queues.left_ship.broadcast(LeftShipEvent {
+            ship_entity: absorbed,
+            player_id: pilot_id,
+        });
+    }
+    queues.unload_ship.broadcast(UnloadShipEvent {
+        ship_entity: absorbed,
+    });
+    commands.entity(absorbed).despawn_recursive();
+}
+
+/// Which of two ships is absorbed into the other and how, if they can be merged.
+fn merge_order(ships: &ShipQuery, a: Entity, b: Entity) -> Option<(Entity, Entity, GridAlignment)> {
+    let (_, a_map, _, _, a_transform, _, _, _) = ships.get(a).ok()?;
+    let (_, b_map, _, _, b_transform, _, _, _) = ships.get(b).ok()?;
+
+    // The smaller ship goes into the bigger one
+    let (target, target_map, target_transform, absorbed, absorbed_map, absorbed_transform) =
+        if a_map.len() >= b_map.len() {
+            (a, a_map, a_transform, b, b_map, b_transform)
+        } else {
+            (b, b_map, b_transform, a, a_map, a_transform)
+        };
+
+    let alignment = GridAlignment::between(target_transform, absorbed_transform)?;
+    alignment
+        .fits(target_map, absorbed_map)
+        .then(|| (target, absorbed, alignment))
+}
+
+/// Keep the merge blocks of ships up to date with the chunks that changed, or rescan every chunk
+/// when the block definitions were reloaded.
+fn update_merge_blocks(
+    mut commands: Commands,
+    definitions: Res<BlockDefinitions>,
+    mut ships: Query<(Entity, &BlockMap, Option<&mut MergeBlocks>), With<Ship>>,
+) {
+    for (ship_entity, block_map, merge_blocks) in ships.iter_mut() {
+        let rescan = definitions.is_changed() || merge_blocks.is_none();
+        let dirty: HashSet<ChunkPosition> = if rescan {
+            block_map.chunks().map(|(position, _)| position).collect()
+        } else {
+            block_map.dirty_chunks().collect()
+        };
+        if dirty.is_empty() && !rescan {
+            continue;
+        }
+
+        match merge_blocks {
+            Some(mut merge_blocks) => {
+                if rescan {
+                    merge_blocks.positions.clear();
+                }
+                merge_blocks.update(&definitions, block_map, &dirty);
+            }
+            None => {
+                let mut merge_blocks = MergeBlocks::default();
+                merge_blocks.update(&definitions, block_map, &dirty);
+                commands.entity(ship_entity).insert(merge_blocks);
+            }
+        }
+    }
+}
+
+/// Merge ships with the same owner whose merge blocks are lined up face to face.
+///
+/// Only pairs with a ship that moved or whose merge blocks changed since the last tick are
+/// checked, as nothing else can bring merge blocks together.
+fn merge_touching_ships(
+    mut commands: Commands,
+    definitions: Res<BlockDefinitions>,
+    mut ships: ShipQuery,
+    mut queues: MergeQueues,
+    merge_query: Query<(
+        Entity,
+        &MergeBlocks,
+        ChangeTrackers<Transform>,
+        ChangeTrackers<MergeBlocks>,
+    )>,
+) {
+    let candidates: Vec<(Entity, &MergeBlocks, BlockBounds, bool)> = merge_query
+        .iter()
+        .filter_map(|(ship_entity, merge_blocks, transform, changed)| {
+            let bounds = merge_blocks.bounds?;
+            let changed = transform.is_changed() || changed.is_changed();
+            Some((ship_entity, merge_blocks, bounds, changed))
+        })
+        .collect();
+
+    let mut merged = HashSet::new();
+    for (i, (a, a_blocks, a_bounds, a_changed)) in candidates.iter().enumerate() {
+        for (b, b_blocks, b_bounds, b_changed) in &candidates[i + 1..] {
+            if !(*a_changed || *b_changed) || merged.contains(a) || merged.contains(b) {
+                continue;
+            }
+            let (_, _, _, _, a_transform, _, _, a_owner) = match ships.get(*a) {
+                Ok(ship) => ship,
+                Err(_) => continue,
+            };
+            let (_, _, _, _, b_transform, _, _, b_owner) = match ships.get(*b) {
+                Ok(ship) => ship,
+                Err(_) => continue,
+            };
+            if a_owner != b_owner {
+                continue;
+            }
+
+            // A merge block of b right next to a merge block of a
+            let alignment = match GridAlignment::between(a_transform, b_transform) {
+                Some(alignment) => alignment,
+                None => continue,
+            };
+            if !alignment.bounds(*b_bounds).is_within(a_bounds, 1) {
+                continue;
+            }
+            let touching = b_blocks.positions.iter().any(|position| {
+                let position = alignment.position(*position);
+                BlockFace::ALL
+                    .into_iter()
+                    .any(|face| a_blocks.contains(&position.neighbour(face)))
+            });
+            if !touching {
+                continue;
+            }
+
+            if let Some((target, absorbed, alignment)) = merge_order(&ships, *a, *b) {
+                merge_ships(
+                    &mut commands,
+                    &definitions,
+                    &mut ships,
+                    &mut queues,
+                    target,
+                    absorbed,
+                    alignment,
+                );
+                merged.insert(*a);
+                merged.insert(*b);
+            }
+        }
+    }
+}
+
+fn on_command(
+    mut commands: Commands,
+    definitions: Res<BlockDefinitions>,
+    mut ships: ShipQuery,
+    mut queues: MergeQueues,
+    mut events: EventReader<CommandEvent>,
+    mut feedback: EventWriter<CommandFeedback>,
+) {
+    for event in events.iter().filter(|event| event.is("merge")) {
+        let client_id = match event.sender {
+            CommandSender::Player(client_id) => client_id,
+            CommandSender::Console => {
+                feedback.send(event.reply("Only players piloting a ship can merge it"));
+                continue;
+            }
+        };
+
+        let piloted = ships.iter().find(
+            |(.., pilot, _)| matches!(pilot, Pilot::Pilot(pilot_id) if *pilot_id == client_id),
+        );
+        let (target, target_map, _, _, target_transform, ..) = match piloted {
+            Some(ship) => ship,
+            None => {
+                feedback.send(event.reply("You need to pilot the ship to merge into"));
+                continue;
+            }
+        };
+
+        // The closest ship that lines up with the piloted one and touches it
+        let closest = ships
+            .iter()
+            .filter(|(ship_entity, ..)| *ship_entity != target)
+            .filter_map(|(ship_entity, block_map, _, _, transform, ..)| {
+                let alignment = GridAlignment::between(target_transform, transform)?;
+                alignment.fits(target_map, block_map).then(|| {
+                    (
+                        ship_entity,
+                        alignment,
+                        transform.translation.distance(target_transform.translation),
+                    )
+                })
+            })
+            .min_by(|(_, _, a), (_, _, b)| a.total_cmp(b));
+
+        match closest {
+            Some((absorbed, alignment, _)) => {
+                merge_ships(
+                    &mut commands,
+                    &definitions,
+                    &mut ships,
+                    &mut queues,
+                    target,
+                    absorbed,
+                    alignment,
+                );
+                feedback.send(event.reply("Merged the closest ship into yours"));
+            }
+            None => {
+                feedback.send(event.reply("No ship is lined up with and touching yours"));
+            }
+        }
+    }
+}
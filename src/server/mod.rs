@@ -1,6 +1,20 @@
+pub mod access;
+pub mod chat;
+pub mod command;
+pub mod config;
+pub mod console;
+pub mod faction;
 pub mod labels;
+pub mod lifecycle;
+pub mod merge;
 pub mod networking;
+pub mod ownership;
 pub mod physics;
 pub mod player;
+pub mod propulsion;
+pub mod roles;
+pub mod session;
 pub mod ship;
 pub mod sync;
+pub mod tick;
+pub mod world;
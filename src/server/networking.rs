@@ -1,12 +1,12 @@
 use std::{
-    net::{SocketAddr, UdpSocket},
-    time::SystemTime,
+    net::UdpSocket,
+    time::{Instant, SystemTime},
 };
 
 use bevy::{
     prelude::{
-        default, Commands, DespawnRecursiveExt, Entity, EventReader, Name, Plugin, Query, ResMut,
-        Transform, With,
+        default, warn, Commands, Entity, EventReader, Name, Plugin, Query, Res, ResMut, Transform,
+        With,
     },
     transform::TransformBundle,
 };
@@ -14,7 +14,6 @@ use bevy::{
 use bevy_renet::renet::{
     RenetConnectionConfig, RenetServer, ServerAuthentication, ServerConfig, ServerEvent,
 };
-use local_ip_address::local_ip;
 use spacegame_core::{
     message::ServerMessageOutQueue,
     network_id::NetworkIdMap,
@@ -27,16 +26,40 @@ use crate::{
         player::{PlayerDespawnEvent, PlayerMoveEvent, PlayerReadyEvent},
         ship::{
             BlockRemoveEvent, BlockUpdateEvent, EnteredShipEvent, LeftShipEvent, LoadShipEvent,
-            ShipMoveEvent, SyncShipBlocksEvent, SyncShipEvent, SyncShipPositionEvent,
-            TryEnterShipEvent, TryLeaveShipEvent, UnloadShipEvent,
+            ShipMoveEvent, ShipOwnershipEvent, SyncShipBlocksEvent, SyncShipEvent,
+            SyncShipPositionEvent, TryEnterShipEvent, TryLeaveShipEvent, UnloadShipEvent,
         },
     },
+    model::ship::{Pilot, Ship},
     shared::{
         entities::player::{PlayerBundle, PlayerMarker},
-        events::{generic::GenericPositionSyncEvent, player::PlayerSpawnEvent},
-        networking::{player_id::PlayerIdMap, plugin::NetworkingPlugin},
+        events::{
+            chat::{AnnouncementEvent, ChatMessageEvent},
+            connection::{DisconnectNoticeEvent, DisconnectReason},
+            faction::FactionSyncEvent,
+            generic::GenericPositionSyncEvent,
+            player::PlayerSpawnEvent,
+        },
+        networking::{
+            auth::{PrivateKey, TokenIssuer},
+            identity::PlayerIdentity,
+            player_id::PlayerIdMap,
+            plugin::NetworkingPlugin,
+            user_data::{validate_player_name, ConnectUserData, NameError},
+        },
+    },
+    PROTOCOL_ID, PROTOCOL_VERSION,
+};
+
+use super::{
+    access::{BanList, Whitelist},
+    config::ServerSettings,
+    lifecycle::PendingDisconnects,
+    session::{
+        previous_session, transfer_seat, DisconnectedPlayers, DisconnectedSince, PreviousSession,
+        RestoreSeat, SessionPlugin,
     },
-    PROTOCOL_ID,
+    world::SavedPlayers,
 };
 
 pub struct ServerNetworkingPlugin;
@@ -45,6 +68,7 @@ impl Plugin for ServerNetworkingPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app.add_plugin(NetworkingPlugin)
             .add_plugin(ServerNetworkPlugin)
+            .add_plugin(SessionPlugin)
             .add_network_event::<SyncShipPositionEvent>()
             .add_network_event::<SyncShipBlocksEvent>()
             .add_network_event::<SyncShipEvent>()
@@ -62,8 +86,47 @@ impl Plugin for ServerNetworkingPlugin {
             .add_network_event::<ShipMoveEvent>()
             .add_network_event::<PlayerReadyEvent>()
             .add_network_event::<UnloadShipEvent>()
-            .add_system(on_client_connect)
-            .insert_resource(create_renet_server());
+            .add_network_event::<DisconnectNoticeEvent>()
+            .add_network_event::<AnnouncementEvent>()
+            .add_network_event::<ChatMessageEvent>()
+            .add_network_event::<ShipOwnershipEvent>()
+            .add_network_event::<FactionSyncEvent>()
+            .add_system(on_client_connect);
+
+        let settings = app.world.resource::<ServerSettings>().clone();
+        let authentication = if settings.unsecure {
+            warn!("Running in unsecure mode, clients are not authenticated");
+            ServerAuthentication::Unsecure
+        } else {
+            let private_key = PrivateKey::load_or_create(&settings.private_key_file)
+                .unwrap_or_else(|e| {
+                    panic!(
+                        "Could not load private key from {}: {}",
+                        settings.private_key_file.display(),
+                        e
+                    )
+                });
+
+            if let Some(listen_addr) = settings
+                .token_service
+                .filter(|_| !settings.external_token_service)
+            {
+                let issuer = TokenIssuer {
+                    private_key,
+                    protocol_id: PROTOCOL_ID,
+                    server_addresses: vec![settings.public_addr()],
+                    expire_seconds: settings.token_expire_seconds,
+                };
+                issuer.spawn_service(listen_addr).unwrap_or_else(|e| {
+                    panic!("Could not start token service on {}: {}", listen_addr, e)
+                });
+            }
+
+            ServerAuthentication::Secure {
+                private_key: private_key.0,
+            }
+        };
+        app.insert_resource(create_renet_server(&settings, authentication));
     }
 
     fn name(&self) -> &str {
@@ -71,24 +134,36 @@ impl Plugin for ServerNetworkingPlugin {
     }
 }
 
-fn create_renet_server() -> RenetServer {
+fn create_renet_server(
+    settings: &ServerSettings,
+    authentication: ServerAuthentication,
+) -> RenetServer {
     let current_time = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap();
 
-    let rt = tokio::runtime::Runtime::new().unwrap();
-    let public_ip = rt.block_on(public_ip::addr()).unwrap();
-    let server_addr = SocketAddr::new(public_ip, 42069);
-
-    //let server_addr = SocketAddr::new(local_ip().unwrap(), 42069);
-
-    let server_config =
-        ServerConfig::new(64, PROTOCOL_ID, server_addr, ServerAuthentication::Unsecure);
+    let server_config = ServerConfig::new(
+        settings.max_clients,
+        PROTOCOL_ID,
+        settings.public_addr(),
+        authentication,
+    );
 
     let connection_config = RenetConnectionConfig::default();
 
-    let inbound_server_addr = SocketAddr::new(local_ip().unwrap(), 42069);
-    let socket = UdpSocket::bind(inbound_server_addr).unwrap();
+    let socket = UdpSocket::bind(settings.bind_addr()).unwrap_or_else(|e| {
+        panic!(
+            "Could not bind server socket to {}: {}",
+            settings.bind_addr(),
+            e
+        )
+    });
+
+    println!(
+        "Listening on {}, reachable at {}",
+        settings.bind_addr(),
+        settings.public_addr()
+    );
 
     RenetServer::new(current_time, server_config, connection_config, socket).unwrap()
 }
@@ -98,44 +173,198 @@ fn on_client_connect(
     mut commands: Commands,
     mut network_ids: ResMut<NetworkIdMap>,
     mut player_ids: ResMut<PlayerIdMap>,
-    player_query: Query<(Entity, &Name, &PlayerClientId), With<PlayerMarker>>,
+    mut disconnected_players: ResMut<DisconnectedPlayers>,
+    mut saved_players: ResMut<SavedPlayers>,
+    player_query: Query<
+        (Entity, &Name, &PlayerClientId, &PlayerIdentity, &Transform),
+        With<PlayerMarker>,
+    >,
+    mut pilot_query: Query<(Entity, &mut Pilot), With<Ship>>,
+    settings: Res<ServerSettings>,
+    server: Res<RenetServer>,
+    ban_list: Res<BanList>,
+    whitelist: Res<Whitelist>,
+    mut pending_disconnects: ResMut<PendingDisconnects>,
     mut player_spawn_queue: ResMut<ServerMessageOutQueue<PlayerSpawnEvent>>,
-    mut player_despawn_queue: ResMut<ServerMessageOutQueue<PlayerDespawnEvent>>,
     mut player_ready_queue: ResMut<ServerMessageOutQueue<PlayerReadyEvent>>,
 ) {
     for event in server_events.iter() {
         match event {
             ServerEvent::ClientConnected(client_id, user_data) => {
-                println!("Client [{}] connected!", client_id);
+                let user_data = match ConnectUserData::from_bytes(user_data) {
+                    Ok(user_data) if user_data.version == PROTOCOL_VERSION => user_data,
+                    Ok(user_data) => {
+                        println!(
+                            "Client [{}] rejected, protocol version {} does not match {}",
+                            client_id, user_data.version, PROTOCOL_VERSION
+                        );
+                        pending_disconnects.disconnect(
+                            *client_id,
+                            DisconnectReason::ProtocolMismatch,
+                            None,
+                        );
+                        continue;
+                    }
+                    Err(e) => {
+                        println!("Client [{}] rejected: {}", client_id, e);
+                        pending_disconnects.disconnect(
+                            *client_id,
+                            DisconnectReason::ProtocolMismatch,
+                            None,
+                        );
+                        continue;
+                    }
+                };
+                let identity = user_data.identity;
+                let player_name = user_data.name;
+
+                let ip = server.client_addr(*client_id).map(|addr| addr.ip());
+                let rejection = if ban_list.0.contains(&identity, ip) {
+                    Some(DisconnectReason::Banned)
+                } else if !whitelist.allows(&identity, ip) {
+                    Some(DisconnectReason::NotWhitelisted)
+                } else if settings.password.is_some() && user_data.password != settings.password {
+                    Some(DisconnectReason::WrongPassword)
+                } else {
+                    None
+                };
+                if let Some(reason) = rejection {
+                    println!("{} [{}] rejected: {}", player_name, client_id, reason);
+                    pending_disconnects.disconnect(*client_id, reason, None);
+                    continue;
+                }
+
+                let name_check = validate_player_name(&player_name).and_then(|_| {
+                    let taken = player_query.iter().any(|(_, name, _, other_identity, _)| {
+                        *other_identity != identity && name.as_str() == player_name
+                    });
+                    if taken {
+                        Err(NameError::Taken)
+                    } else {
+                        Ok(())
+                    }
+                });
+                if let Err(e) = name_check {
+                    println!(
+                        "Client [{}] rejected, invalid name {:?}: {}",
+                        client_id, player_name, e
+                    );
+                    pending_disconnects.disconnect(
+                        *client_id,
+                        DisconnectReason::Kicked,
+                        Some(e.to_string()),
+                    );
+                    continue;
+                }
+
+                println!("{} [{}] connected!", player_name, client_id);
+
+                let previous = previous_session(
+                    &mut disconnected_players,
+                    player_query
+                        .iter()
+                        .map(|(entity, _, client_id, identity, _)| (entity, identity, client_id)),
+                    &identity,
+                );
+                let (player_entity, transform) = match previous {
+                    Some(PreviousSession {
+                        player_entity,
+                        old_client_id,
+                        still_connected,
+                    }) => {
+                        let (_, _, _, _, transform) = player_query.get(player_entity).unwrap();
+                        if still_connected {
+                            println!(
+                                "{} [{}] took over the session of client [{}], which is still connected",
+                                player_name, client_id, old_client_id
+                            );
+                            // Forget the old client first, so that its player is not kept as
+                            // disconnected once it is gone
+                            player_ids.remove_client(old_client_id);
+                            pending_disconnects.disconnect(
+                                old_client_id,
+                                DisconnectReason::Kicked,
+                                Some(String::from("Connected again from another client")),
+                            );
+                        } else {
+                            println!(
+                                "{} [{}] resumed the session of client [{}]",
+                                player_name, client_id, old_client_id
+                            );
+                        }
+
+                        commands
+                            .entity(player_entity)
+                            .insert(PlayerClientId(*client_id))
+                            .insert(Name::new(player_name.clone()))
+                            .remove::<DisconnectedSince>();
 
-                let transform = Transform::from_xyz(0., 3., 0.);
+                        if let Some(ship_entity) =
+                            transfer_seat(&mut pilot_query, old_client_id, *client_id)
+                        {
+                            commands
+                                .entity(player_entity)
+                                .insert(RestoreSeat { ship_entity });
+                        }
 
-                let player_entity = commands
-                    .spawn_bundle(PlayerBundle {
-                        physics_object: PhysicsObjectBundle {
-                            transform_bundle: TransformBundle {
-                                local: transform,
+                        (player_entity, *transform)
+                    }
+                    None => {
+                        let saved_player = saved_players.take(&identity);
+                        let transform = saved_player
+                            .as_ref()
+                            .map_or(Transform::from_xyz(0., 3., 0.), |player| player.transform);
+
+                        let player_entity = commands
+                            .spawn_bundle(PlayerBundle {
+                                physics_object: PhysicsObjectBundle {
+                                    transform_bundle: TransformBundle {
+                                        local: transform,
+                                        ..default()
+                                    },
+                                    ..default()
+                                },
+                                name: Name::new(player_name.clone()),
                                 ..default()
-                            },
-                            ..default()
-                        },
-                        name: Name::new(client_id.to_string()),
-                        ..default()
-                    })
-                    .insert(PlayerClientId(*client_id))
-                    .id();
-                let network_id = network_ids.insert(player_entity);
-                commands.entity(player_entity).insert(network_id);
+                            })
+                            .insert(PlayerClientId(*client_id))
+                            .insert(identity)
+                            .id();
+                        let network_id = network_ids.insert(player_entity);
+                        commands.entity(player_entity).insert(network_id);
+
+                        // Put the player back in the seat they had when the world was saved
+                        let seat = saved_player.and_then(|player| player.seat);
+                        if let Some(Ok((ship_entity, mut pilot))) =
+                            seat.map(|ship_entity| pilot_query.get_mut(ship_entity))
+                        {
+                            if matches!(*pilot, Pilot::None) {
+                                *pilot = Pilot::Pilot(*client_id);
+                                commands
+                                    .entity(player_entity)
+                                    .insert(RestoreSeat { ship_entity });
+                            }
+                        }
+
+                        (player_entity, transform)
+                    }
+                };
                 player_ids.insert(*client_id, player_entity);
 
                 let mut players_online_count = 0;
-                for (player_entity, player_name, player_client_id) in player_query.iter() {
+                for (other_entity, other_name, player_client_id, _, transform) in
+                    player_query.iter()
+                {
+                    if other_entity == player_entity {
+                        continue;
+                    }
+
                     player_spawn_queue.send(
                         client_id,
                         PlayerSpawnEvent {
-                            player_entity,
-                            player_name: player_name.to_string(),
-                            transform,
+                            player_entity: other_entity,
+                            player_name: other_name.to_string(),
+                            transform: *transform,
                             player_id: player_client_id.0,
                         },
                     );
@@ -147,7 +376,7 @@ fn on_client_connect(
                     client_id,
                     PlayerSpawnEvent {
                         player_entity,
-                        player_name: client_id.to_string(),
+                        player_name: player_name.clone(),
                         transform,
                         player_id: *client_id,
                     },
@@ -157,21 +386,22 @@ fn on_client_connect(
                     client_id,
                     PlayerReadyEvent {
                         own_client_it: *client_id,
+                        player_name,
                         players_online_count,
                     },
                 );
             }
             ServerEvent::ClientDisconnected(client_id) => {
-                println!("Client [{}] disconnected!", client_id);
+                if let Some(player_entity) = player_ids.remove_client(*client_id) {
+                    if let Ok((_, name, _, identity, _)) = player_query.get(player_entity) {
+                        println!("{} [{}] disconnected!", name, client_id);
+                        disconnected_players.insert(*identity, player_entity);
+                    }
 
-                let player_entity = player_ids.from_client(*client_id).unwrap();
-
-                commands.entity(player_entity).despawn_recursive();
-
-                player_despawn_queue.broadcast(PlayerDespawnEvent {
-                    player_entity: player_entity,
-                    player_id: *client_id,
-                });
+                    commands
+                        .entity(player_entity)
+                        .insert(DisconnectedSince(Instant::now()));
+                }
             }
         }
     }
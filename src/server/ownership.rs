@@ -0,0 +1,267 @@
+use bevy::prelude::{
+    Bundle, Component, Entity, EventReader, EventWriter, Name, Plugin, Query, Res, ResMut, With,
+};
+use iyes_loopless::prelude::IntoConditionalSystem;
+use spacegame_core::message::{ClientId, ServerMessageOutQueue};
+
+use crate::shared::{
+    entities::player::PlayerMarker,
+    events::ship::ShipOwnershipEvent,
+    model::ship::{OwnerName, Pilot, Ship, ShipOwnership},
+    networking::identity::PlayerIdentity,
+};
+
+use super::{
+    command::{
+        AppCommandExt, CommandEvent, CommandFeedback, CommandInfo, CommandPermission, CommandSender,
+    },
+    faction::Factions,
+    player::player_name,
+    roles::{PlayerRoles, Role},
+};
+
+/// Who owns a ship.
+#[derive(Component, Clone, PartialEq, Eq, Debug)]
+pub enum Owner {
+    /// Nobody owns the ship, so everyone allowed to build and pilot can use it.
+    None,
+    Player(PlayerIdentity),
+    /// Every member of the faction can build on the ship, and allies can pilot it.
+    Faction(String),
+}
+
+impl Owner {
+    /// Whether this player may decide who else gets to use the ship.
+    pub fn is_managed_by(&self, identity: &PlayerIdentity, factions: &Factions) -> bool {
+        match self {
+            Owner::None => false,
+            Owner::Player(owner) => owner == identity,
+            Owner::Faction(faction) => factions
+                .get(faction)
+                .map_or(false, |faction| faction.leader == *identity),
+        }
+    }
+}
+
+/// Players the owner lets use their ship.
+#[derive(Component, Clone, Default, Debug)]
+pub struct SharedRights {
+    pub builders: Vec<PlayerIdentity>,
+    pub pilots: Vec<PlayerIdentity>,
+}
+
+impl SharedRights {
+    pub fn may_build(&self, owner: &Owner, identity: &PlayerIdentity, factions: &Factions) -> bool {
+        let is_owner = match owner {
+            Owner::None => true,
+            Owner::Player(owner) => owner == identity,
+            Owner::Faction(faction) => factions.is_member(faction, identity),
+        };
+        is_owner || self.builders.contains(identity)
+    }
+
+    /// Builders may pilot as well.
+    pub fn may_pilot(&self, owner: &Owner, identity: &PlayerIdentity, factions: &Factions) -> bool {
+        let is_ally = match owner {
+            Owner::Faction(faction) => factions
+                .faction_of(identity)
+                .map_or(false, |(own_faction, _)| {
+                    factions.are_allied(faction, own_faction)
+                }),
+            _ => false,
+        };
+        is_ally || self.pilots.contains(identity) || self.may_build(owner, identity, factions)
+    }
+}
+
+#[derive(Bundle)]
+pub struct OwnershipBundle {
+    pub owner: Owner,
+    pub shared_rights: SharedRights,
+}
+
+impl OwnershipBundle {
+    pub fn new(owner: Owner) -> Self {
+        Self {
+            owner,
+            shared_rights: SharedRights::default(),
+        }
+    }
+}
+
+/// Whether a client may edit the blocks of a ship, going by both its role and the owner's say.
+pub fn may_build(
+    roles: &PlayerRoles,
+    factions: &Factions,
+    client_id: ClientId,
+    owner: &Owner,
+    shared_rights: &SharedRights,
+) -> bool {
+    let role = roles.of_client(client_id);
+    match roles.identity_of(client_id) {
+        Some(identity) => {
+            role == Role::Admin
+                || (role.may_build() && shared_rights.may_build(owner, identity, factions))
+        }
+        None => false,
+    }
+}
+
+/// Whether a client may pilot a ship, going by both its role and the owner's say.
+pub fn may_pilot(
+    roles: &PlayerRoles,
+    factions: &Factions,
+    client_id: ClientId,
+    owner: &Owner,
+    shared_rights: &SharedRights,
+) -> bool {
+    let role = roles.of_client(client_id);
+    match roles.identity_of(client_id) {
+        Some(identity) => {
+            role == Role::Admin
+                || (role.may_pilot() && shared_rights.may_pilot(owner, identity, factions))
+        }
+        None => false,
+    }
+}
+
+/// What clients get to see of the ownership of a ship, with identities replaced by player names.
+pub fn ship_ownership(
+    owner: &Owner,
+    shared_rights: &SharedRights,
+    player_query: &Query<(&Name, &PlayerIdentity), With<PlayerMarker>>,
+) -> ShipOwnership {
+    // Identities are what players log in with, so they must never be sent to other clients
+    let name_of = |identity: &PlayerIdentity| player_name(identity, player_query);
+
+    ShipOwnership {
+        owner: match owner {
+            Owner::None => None,
+            Owner::Player(identity) => Some(OwnerName::Player(name_of(identity))),
+            Owner::Faction(faction) => Some(OwnerName::Faction(faction.clone())),
+        },
+        builders: shared_rights.builders.iter().map(name_of).collect(),
+        pilots: shared_rights.pilots.iter().map(name_of).collect(),
+    }
+}
+
+pub struct OwnershipPlugin;
+
+impl Plugin for OwnershipPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_command(CommandInfo {
+            name: "share",
+            usage: "<build|pilot> <player>",
+            description: "Let a player use the ship you are piloting",
+            permission: CommandPermission::Everyone,
+        })
+        .add_command(CommandInfo {
+            name: "unshare",
+            usage: "<build|pilot> <player>",
+            description: "Stop sharing the ship you are piloting",
+            permission: CommandPermission::Everyone,
+        })
+        .add_system(on_command.run_on_event::<CommandEvent>());
+    }
+
+    fn name(&self) -> &str {
+        "ownership_plugin"
+    }
+}
+
+fn on_command(
+    roles: PlayerRoles,
+    factions: Res<Factions>,
+    mut events: EventReader<CommandEvent>,
+    mut feedback: EventWriter<CommandFeedback>,
+    mut ship_query: Query<(Entity, &Pilot, &Owner, &mut SharedRights), With<Ship>>,
+    player_query: Query<(&Name, &PlayerIdentity), With<PlayerMarker>>,
+    mut ownership_queue: ResMut<ServerMessageOutQueue<ShipOwnershipEvent>>,
+) {
+    for event in events.iter() {
+        let share = match event.name.as_str() {
+            "share" => true,
+            "unshare" => false,
+            _ => continue,
+        };
+
+        let client_id = match event.sender {
+            CommandSender::Player(client_id) => client_id,
+            CommandSender::Console => {
+                feedback.send(event.reply("Only players piloting a ship can share it"));
+                continue;
+            }
+        };
+        let (right, player_name) = match (event.args.get(0), event.args.get(1)) {
+            (Some(right), Some(player_name)) if right == "build" || right == "pilot" => {
+                (right, player_name)
+            }
+            _ => {
+                let usage = format!("Usage: {} <build|pilot> <player>", event.name);
+                feedback.send(event.reply(usage));
+                continue;
+            }
+        };
+
+        let ship = ship_query.iter_mut().find(
+            |(_, pilot, _, _)| matches!(pilot, Pilot::Pilot(pilot_id) if *pilot_id == client_id),
+        );
+        let (ship_entity, _, owner, mut shared_rights) = match ship {
+            Some(ship) => ship,
+            None => {
+                feedback.send(event.reply("You need to pilot the ship you want to share"));
+                continue;
+            }
+        };
+
+        let is_owner = roles
+            .identity_of(client_id)
+            .map_or(false, |identity| owner.is_managed_by(identity, &factions));
+        if !is_owner && roles.of_client(client_id) != Role::Admin {
+            feedback.send(event.reply("Only the owner can share this ship"));
+            continue;
+        }
+
+        let identity = match player_query
+            .iter()
+            .find(|(name, _)| name.as_str() == player_name)
+        {
+            Some((_, identity)) => *identity,
+            None => {
+                feedback.send(event.reply(format!("No player named {}", player_name)));
+                continue;
+            }
+        };
+
+        let list = if right == "build" {
+            &mut shared_rights.builders
+        } else {
+            &mut shared_rights.pilots
+        };
+        let changed = if share {
+            let is_new = !list.contains(&identity);
+            if is_new {
+                list.push(identity);
+            }
+            is_new
+        } else {
+            let len = list.len();
+            list.retain(|shared| *shared != identity);
+            list.len() != len
+        };
+
+        if changed {
+            ownership_queue.broadcast(ShipOwnershipEvent {
+                ship_entity,
+                ownership: ship_ownership(owner, &shared_rights, &player_query),
+            });
+        }
+        let message = match (changed, share) {
+            (true, true) => format!("{} may now {} this ship", player_name, right),
+            (true, false) => format!("{} may no longer {} this ship", player_name, right),
+            (false, true) => format!("{} may already {} this ship", player_name, right),
+            (false, false) => format!("{} was not allowed to {} this ship", player_name, right),
+        };
+        feedback.send(event.reply(message));
+    }
+}
@@ -1,9 +1,35 @@
+use std::ops::{AddAssign, SubAssign};
+
 use bevy::{
-    prelude::{Component, Quat, Vec3},
+    math::{DMat3, DVec3},
+    prelude::{Changed, Commands, Component, Entity, Mat3, Plugin, Quat, Query, Res, Vec3},
     reflect::{FromReflect, Reflect},
 };
+use bevy_rapier3d::prelude::{AdditionalMassProperties, MassProperties as RapierMassProperties};
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    math::symmetric_eigen,
+    model::{
+        block::BlockType,
+        block_definitions::{BlockCollider, BlockDefinitions},
+        block_map::{BlockMap, BlockPosition, BlockRotation},
+    },
+};
+
+pub struct PhysicsPlugin;
+
+impl Plugin for PhysicsPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_system(recompute_ship_mass)
+            .add_system(apply_ship_mass);
+    }
+
+    fn name(&self) -> &str {
+        "physics_plugin"
+    }
+}
+
 #[derive(
     Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Reflect, FromReflect, Component,
 )]
@@ -59,13 +85,199 @@ pub struct Damping {
     Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Reflect, FromReflect, Component,
 )]
 pub struct MassProperties {
-    pub center_of_map: Vec3,
+    pub center_of_mass: Vec3,
     pub mass: f32,
-    pub principal_intertial_rot: Quat,
-    pub principal_inertial: Vec3,
+    pub principal_inertia_rotation: Quat,
+    pub principal_inertia: Vec3,
+}
+
+impl From<MassProperties> for RapierMassProperties {
+    fn from(properties: MassProperties) -> Self {
+        RapierMassProperties {
+            local_center_of_mass: properties.center_of_mass,
+            mass: properties.mass,
+            principal_inertia_local_frame: properties.principal_inertia_rotation,
+            principal_inertia: properties.principal_inertia,
+        }
+    }
 }
 
 #[derive(
     Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Reflect, FromReflect, Component,
 )]
 pub struct RigidBody;
+
+/// Mass of a ship summed up from its blocks, kept up to date as blocks are placed and removed
+/// instead of being recomputed from the whole block map.
+///
+/// Blocks have no mass of their own as far as Rapier is concerned, the ship gets all of it through
+/// [`AdditionalMassProperties`].
+#[derive(Component, Clone, Copy, Default, Debug)]
+pub struct ShipMass {
+    mass: f64,
+    /// Sum of the mass of every block times its position.
+    moment: DVec3,
+    /// Inertia tensor around the origin of the ship.
+    inertia: DMat3,
+}
+
+impl ShipMass {
+    pub fn from_block_map(definitions: &BlockDefinitions, block_map: &BlockMap) -> Self {
+        let mut ship_mass = ShipMass::default();
+        for (position, entry) in block_map.entries() {
+            ship_mass += ShipMass::block(
+                definitions,
+                entry.block_type,
+                position,
+                entry.block_rotation,
+            );
+        }
+        ship_mass
+    }
+
+    /// The mass of a single block, blocks of unknown type weigh nothing.
+    pub fn block(
+        definitions: &BlockDefinitions,
+        block_type: BlockType,
+        position: BlockPosition,
+        rotation: BlockRotation,
+    ) -> Self {
+        let definition = match definitions.get(block_type) {
+            Some(definition) => definition,
+            None => return ShipMass::default(),
+        };
+        let mass = definition.mass as f64;
+
+        // Inertia of the block around its own center, along its own axes
+        let principal_inertia = match definition.collider {
+            BlockCollider::Cuboid {
+                half_extents: (x, y, z),
+            } => {
+                let (x, y, z) = (x as f64, y as f64, z as f64);
+                DVec3::new(y * y + z * z, x * x + z * z, x * x + y * y) * mass / 3.
+            }
+            BlockCollider::Ball { radius } => DVec3::splat(0.4 * mass * (radius * radius) as f64),
+        };
+        let rotation = Mat3::from_quat(rotation.into());
+        let rotation = DMat3::from_cols(
+            rotation.x_axis.as_dvec3(),
+            rotation.y_axis.as_dvec3(),
+            rotation.z_axis.as_dvec3(),
+        );
+        let local_inertia =
+            rotation * DMat3::from_diagonal(principal_inertia) * rotation.transpose();
+
+        // Parallel axis theorem to move it to the origin of the ship
+        let position = Vec3::from(position).as_dvec3();
+        ShipMass {
+            mass,
+            moment: position * mass,
+            inertia: local_inertia + parallel_axis(mass, position),
+        }
+    }
+
+    pub fn mass(&self) -> f32 {
+        self.mass as f32
+    }
+
+    pub fn center_of_mass(&self) -> Vec3 {
+        if self.mass > 0. {
+            (self.moment / self.mass).as_vec3()
+        } else {
+            Vec3::ZERO
+        }
+    }
+
+    /// Inertia tensor around the center of mass, in the frame of the ship.
+    pub fn inertia(&self) -> Mat3 {
+        let inertia = self.inertia_around_center();
+        Mat3::from_cols(
+            inertia.x_axis.as_vec3(),
+            inertia.y_axis.as_vec3(),
+            inertia.z_axis.as_vec3(),
+        )
+    }
+
+    fn inertia_around_center(&self) -> DMat3 {
+        if self.mass <= 0. {
+            return DMat3::ZERO;
+        }
+        self.inertia - parallel_axis(self.mass, self.moment / self.mass)
+    }
+
+    pub fn mass_properties(&self) -> MassProperties {
+        if self.mass <= 0. {
+            return MassProperties {
+                center_of_mass: Vec3::ZERO,
+                mass: 0.,
+                principal_inertia_rotation: Quat::IDENTITY,
+                principal_inertia: Vec3::ZERO,
+            };
+        }
+
+        let (principal_inertia, axes) = symmetric_eigen(self.inertia_around_center());
+        MassProperties {
+            center_of_mass: self.center_of_mass(),
+            mass: self.mass as f32,
+            principal_inertia_rotation: Quat::from_mat3(&Mat3::from_cols(
+                axes.x_axis.as_vec3(),
+                axes.y_axis.as_vec3(),
+                axes.z_axis.as_vec3(),
+            ))
+            .normalize(),
+            // Rounding errors can leave tiny negative values for flat ships
+            principal_inertia: principal_inertia.max(DVec3::ZERO).as_vec3(),
+        }
+    }
+}
+
+impl AddAssign for ShipMass {
+    fn add_assign(&mut self, other: Self) {
+        self.mass += other.mass;
+        self.moment += other.moment;
+        self.inertia = self.inertia + other.inertia;
+    }
+}
+
+impl SubAssign for ShipMass {
+    fn sub_assign(&mut self, other: Self) {
+        self.mass -= other.mass;
+        self.moment -= other.moment;
+        self.inertia = self.inertia - other.inertia;
+        // Don't leave rounding errors behind once the last block is gone
+        if self.mass <= f64::EPSILON {
+            *self = ShipMass::default();
+        }
+    }
+}
+
+/// Inertia tensor of a point `mass` at `offset`.
+fn parallel_axis(mass: f64, offset: DVec3) -> DMat3 {
+    (DMat3::from_diagonal(DVec3::splat(offset.length_squared()))
+        - DMat3::from_cols(offset * offset.x, offset * offset.y, offset * offset.z))
+        * mass
+}
+
+/// Start over from the block maps when the block definitions are reloaded, as the masses the
+/// running totals were built from may have changed.
+fn recompute_ship_mass(
+    definitions: Res<BlockDefinitions>,
+    mut query: Query<(&BlockMap, &mut ShipMass)>,
+) {
+    if !definitions.is_changed() {
+        return;
+    }
+    for (block_map, mut ship_mass) in query.iter_mut() {
+        *ship_mass = ShipMass::from_block_map(&definitions, block_map);
+    }
+}
+
+fn apply_ship_mass(mut commands: Commands, query: Query<(Entity, &ShipMass), Changed<ShipMass>>) {
+    for (ship_entity, ship_mass) in query.iter() {
+        commands
+            .entity(ship_entity)
+            .insert(AdditionalMassProperties::MassProperties(
+                ship_mass.mass_properties().into(),
+            ));
+    }
+}
@@ -1,13 +1,51 @@
-use bevy::prelude::{EventReader, Plugin, Query, Res, ResMut, Transform};
+use bevy::prelude::{EventReader, EventWriter, Name, Plugin, Query, Res, ResMut, Transform, With};
+use iyes_loopless::prelude::IntoConditionalSystem;
 use spacegame_core::message::ServerMessageOutQueue;
 
-use crate::{networking::player_id::PlayerIdMap, shared::events::player::PlayerMoveEvent};
+use crate::{
+    entities::player::{PlayerClientId, PlayerMarker},
+    networking::{identity::PlayerIdentity, player_id::PlayerIdMap},
+    shared::events::{
+        chat::AnnouncementEvent, connection::DisconnectReason, player::PlayerMoveEvent,
+    },
+};
+
+use super::{
+    command::{AppCommandExt, CommandEvent, CommandFeedback, CommandInfo, CommandPermission},
+    lifecycle::PendingDisconnects,
+    session::DisconnectedSince,
+};
 
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_system(on_player_move);
+        app.add_command(CommandInfo {
+            name: "list",
+            usage: "",
+            description: "List the players on the server",
+            permission: CommandPermission::Everyone,
+        })
+        .add_command(CommandInfo {
+            name: "kick",
+            usage: "<player> [reason]",
+            description: "Disconnect a player",
+            permission: CommandPermission::Admin,
+        })
+        .add_command(CommandInfo {
+            name: "tp",
+            usage: "<player> <x y z>",
+            description: "Teleport a player",
+            permission: CommandPermission::Admin,
+        })
+        .add_command(CommandInfo {
+            name: "say",
+            usage: "<message>",
+            description: "Send a message to all players",
+            permission: CommandPermission::Admin,
+        })
+        .add_system(on_player_move)
+        .add_system(on_command.run_on_event::<CommandEvent>());
     }
 
     fn name(&self) -> &str {
@@ -15,6 +53,20 @@ impl Plugin for PlayerPlugin {
     }
 }
 
+/// The name of the player with this identity, if they have been on the server since it started.
+pub fn player_name(
+    identity: &PlayerIdentity,
+    player_query: &Query<(&Name, &PlayerIdentity), With<PlayerMarker>>,
+) -> String {
+    player_query
+        .iter()
+        .find(|(_, player_identity)| *player_identity == identity)
+        .map_or_else(
+            || String::from("an offline player"),
+            |(name, _)| name.to_string(),
+        )
+}
+
 pub fn on_player_move(
     player_ids: Res<PlayerIdMap>,
     mut events: EventReader<PlayerMoveEvent>,
@@ -22,7 +74,10 @@ pub fn on_player_move(
     mut player_move_queue: ResMut<ServerMessageOutQueue<PlayerMoveEvent>>,
 ) {
     for event in events.iter() {
-        let player_entity = player_ids.from_client(event.client_id).unwrap();
+        let player_entity = match player_ids.from_client(event.client_id) {
+            Some(player_entity) => player_entity,
+            None => continue,
+        };
         let mut transform = player_query.get_mut(player_entity).unwrap();
         *transform = event.transform;
 
@@ -35,3 +90,108 @@ pub fn on_player_move(
         );
     }
 }
+
+fn on_command(
+    mut events: EventReader<CommandEvent>,
+    mut feedback: EventWriter<CommandFeedback>,
+    mut pending_disconnects: ResMut<PendingDisconnects>,
+    mut player_query: Query<
+        (
+            &Name,
+            &PlayerIdentity,
+            &PlayerClientId,
+            &mut Transform,
+            Option<&DisconnectedSince>,
+        ),
+        With<PlayerMarker>,
+    >,
+    mut player_move_queue: ResMut<ServerMessageOutQueue<PlayerMoveEvent>>,
+    mut announcement_queue: ResMut<ServerMessageOutQueue<AnnouncementEvent>>,
+) {
+    for event in events.iter() {
+        match event.name.as_str() {
+            "list" => {
+                let mut lines = Vec::new();
+                for (name, identity, client_id, _, disconnected_since) in player_query.iter() {
+                    let status = match disconnected_since {
+                        Some(_) => "disconnected",
+                        None => "online",
+                    };
+                    lines.push(format!(
+                        "{} [{}] {} ({})",
+                        name, client_id.0, identity, status
+                    ));
+                }
+                lines.insert(0, format!("{} players", lines.len()));
+                feedback.send(event.reply(lines.join("\n")));
+            }
+            "kick" => {
+                let player_name = match event.args.first() {
+                    Some(player_name) => player_name,
+                    None => {
+                        feedback.send(event.reply("Usage: kick <player> [reason]"));
+                        continue;
+                    }
+                };
+                let player = player_query
+                    .iter()
+                    .find(|(name, _, _, _, _)| name.as_str() == player_name);
+                let (_, _, client_id, _, disconnected_since) = match player {
+                    Some(player) => player,
+                    None => {
+                        feedback.send(event.reply(format!("No player named {}", player_name)));
+                        continue;
+                    }
+                };
+
+                if disconnected_since.is_some() {
+                    feedback.send(event.reply(format!("{} is not online", player_name)));
+                    continue;
+                }
+                pending_disconnects.disconnect(
+                    client_id.0,
+                    DisconnectReason::Kicked,
+                    event.rest_arg(1),
+                );
+                feedback.send(event.reply(format!("Kicked {}", player_name)));
+            }
+            "tp" => {
+                let (player_name, position) = match (event.args.first(), event.vec3_arg(1)) {
+                    (Some(player_name), Some(position)) => (player_name, position),
+                    _ => {
+                        feedback.send(event.reply("Usage: tp <player> <x y z>"));
+                        continue;
+                    }
+                };
+                let player = player_query
+                    .iter_mut()
+                    .find(|(name, _, _, _, _)| name.as_str() == player_name);
+                let (_, _, client_id, mut transform, _) = match player {
+                    Some(player) => player,
+                    None => {
+                        feedback.send(event.reply(format!("No player named {}", player_name)));
+                        continue;
+                    }
+                };
+
+                transform.translation = position;
+                // The client owns the position of its player, so it has to be told as well
+                player_move_queue.broadcast(PlayerMoveEvent {
+                    transform: *transform,
+                    client_id: client_id.0,
+                });
+                feedback.send(event.reply(format!("Teleported {} to {}", player_name, position)));
+            }
+            "say" => match event.rest_arg(0) {
+                Some(message) => {
+                    announcement_queue.broadcast(AnnouncementEvent {
+                        message: message.clone(),
+                    });
+                    feedback.send(event.reply(format!("[Server] {}", message)));
+                }
+                None => feedback.send(event.reply("Usage: say <message>")),
+            },
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use std::ops::{AddAssign, SubAssign};
+
+use bevy::prelude::{Component, Mat3, Plugin, Query, Res, Transform, Vec3, With};
+use bevy_rapier3d::prelude::ExternalForce;
+
+use crate::model::{
+    block::BlockType,
+    block_definitions::BlockDefinitions,
+    block_map::{BlockFace, BlockMap, BlockPosition, BlockRotation},
+    ship::{Pilot, Ship},
+};
+
+use super::physics::ShipMass;
+
+/// How fast ships turn when the pilot steers as hard as they can and the gyroscopes are strong
+/// enough for it, in radians per second squared.
+const MAX_ANGULAR_ACCELERATION: f32 = 4.;
+
+pub struct PropulsionPlugin;
+
+impl Plugin for PropulsionPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_system(recompute_propulsion)
+            .add_system(apply_thrust);
+    }
+
+    fn name(&self) -> &str {
+        "propulsion_plugin"
+    }
+}
+
+/// What the pilot of a ship asks of it, in the ship's own frame.
+#[derive(Component, Clone, Copy, Default, Debug)]
+pub struct ShipControls {
+    /// How hard to push along each axis, from -1 to 1.
+    pub throttle: Vec3,
+    /// How hard to turn around each axis, from -1 to 1.
+    pub steering: Vec3,
+}
+
+/// Thrust of a single direction, summed over the engines pushing that way.
+#[derive(Clone, Copy, Default, Debug)]
+struct DirectionThrust {
+    force: f32,
+    /// Sum of the force of every engine times its position.
+    moment: Vec3,
+}
+
+/// Engines of a ship grouped by the direction they push it in and the torque of its gyroscopes,
+/// kept up to date as blocks are placed and removed.
+#[derive(Component, Clone, Copy, Default, Debug)]
+pub struct ShipPropulsion {
+    /// Indexed by [`BlockFace::index`].
+    directions: [DirectionThrust; 6],
+    gyroscope_torque: f32,
+}
+
+impl ShipPropulsion {
+    pub fn from_block_map(definitions: &BlockDefinitions, block_map: &BlockMap) -> Self {
+        let mut propulsion = ShipPropulsion::default();
+        for (position, entry) in block_map.entries() {
+            propulsion += ShipPropulsion::block(
+                definitions,
+                entry.block_type,
+                position,
+                entry.block_rotation,
+            );
+        }
+        propulsion
+    }
+
+    /// What a single block adds, which is nothing for anything but engines and gyroscopes.
+    pub fn block(
+        definitions: &BlockDefinitions,
+        block_type: BlockType,
+        position: BlockPosition,
+        rotation: BlockRotation,
+    ) -> Self {
+        let mut propulsion = ShipPropulsion::default();
+        let definition = match definitions.get(block_type) {
+            Some(definition) => definition,
+            None => return propulsion,
+        };
+
+        if let Some(thruster) = definition.thruster {
+            let direction = rotation.rotate_face(BlockFace::NegativeZ);
+            propulsion.directions[direction.index() as usize] = DirectionThrust {
+                force: thruster.force,
+                moment: Vec3::from(position) * thruster.force,
+            };
+        }
+        if let Some(gyroscope) = definition.gyroscope {
+            propulsion.gyroscope_torque = gyroscope.torque;
+        }
+        propulsion
+    }
+
+    /// The most force the engines can put out towards `direction`.
+    pub fn max_force(&self, direction: BlockFace) -> f32 {
+        self.directions[direction.index() as usize].force
+    }
+
+    /// The most torque the gyroscopes can put out around any axis.
+    pub fn max_torque(&self) -> f32 {
+        self.gyroscope_torque
+    }
+
+    /// Torque to turn the ship as asked by `steering`, limited by what the gyroscopes can do.
+    ///
+    /// The pilot asks for an angular acceleration, so small ships don't spin out of control, and
+    /// the torque needed for it against `inertia` is clamped to the torque of the gyroscopes, so
+    /// big ships need more of them to turn as quickly.
+    pub fn steering_torque(&self, steering: Vec3, inertia: Mat3) -> Vec3 {
+        let steering = steering.clamp(Vec3::splat(-1.), Vec3::ONE);
+        (inertia * (steering * MAX_ANGULAR_ACCELERATION)).clamp_length_max(self.gyroscope_torque)
+    }
+
+    /// Force and torque around `center_of_mass` for the given throttle, in the ship's frame.
+    ///
+    /// Each axis only uses the engines pushing in the direction asked for, at their own
+    /// positions, so engines that are off-center also turn the ship.
+    pub fn force_and_torque(&self, throttle: Vec3, center_of_mass: Vec3) -> (Vec3, Vec3) {
+        let mut force = Vec3::ZERO;
+        let mut torque = Vec3::ZERO;
+        let axes = [
+            (throttle.x, BlockFace::PositiveX, BlockFace::NegativeX),
+            (throttle.y, BlockFace::PositiveY, BlockFace::NegativeY),
+            (throttle.z, BlockFace::PositiveZ, BlockFace::NegativeZ),
+        ];
+        for (amount, positive, negative) in axes {
+            let face = if amount >= 0. { positive } else { negative };
+            let thrust = self.directions[face.index() as usize];
+            let amount = amount.abs().min(1.);
+            let normal: Vec3 = face.normal().into();
+
+            force += normal * thrust.force * amount;
+            torque += (thrust.moment - center_of_mass * thrust.force).cross(normal) * amount;
+        }
+        (force, torque)
+    }
+}
+
+impl AddAssign for ShipPropulsion {
+    fn add_assign(&mut self, other: Self) {
+        for (direction, other) in self.directions.iter_mut().zip(other.directions) {
+            direction.force += other.force;
+            direction.moment += other.moment;
+        }
+        self.gyroscope_torque += other.gyroscope_torque;
+    }
+}
+
+impl SubAssign for ShipPropulsion {
+    fn sub_assign(&mut self, other: Self) {
+        for (direction, other) in self.directions.iter_mut().zip(other.directions) {
+            direction.force -= other.force;
+            direction.moment -= other.moment;
+            // Don't leave rounding errors behind once the last engine is gone
+            if direction.force <= 0. {
+                *direction = DirectionThrust::default();
+            }
+        }
+        self.gyroscope_torque = (self.gyroscope_torque - other.gyroscope_torque).max(0.);
+    }
+}
+
+/// Start over from the block maps when the block definitions are reloaded, as engines and
+/// gyroscopes may have changed.
+fn recompute_propulsion(
+    definitions: Res<BlockDefinitions>,
+    mut query: Query<(&BlockMap, &mut ShipPropulsion)>,
+) {
+    if !definitions.is_changed() {
+        return;
+    }
+    for (block_map, mut propulsion) in query.iter_mut() {
+        *propulsion = ShipPropulsion::from_block_map(&definitions, block_map);
+    }
+}
+
+fn apply_thrust(
+    mut query: Query<
+        (
+            &Pilot,
+            &ShipControls,
+            &ShipPropulsion,
+            &ShipMass,
+            &Transform,
+            &mut ExternalForce,
+        ),
+        With<Ship>,
+    >,
+) {
+    for (pilot, controls, propulsion, ship_mass, transform, mut external_force) in query.iter_mut()
+    {
+        // Nobody at the controls, nothing pushes the ship
+        let controls = match pilot {
+            Pilot::Pilot(_) => *controls,
+            Pilot::None => ShipControls::default(),
+        };
+
+        let (force, torque) =
+            propulsion.force_and_torque(controls.throttle, ship_mass.center_of_mass());
+        let torque = torque + propulsion.steering_torque(controls.steering, ship_mass.inertia());
+
+        external_force.force = transform.rotation * force;
+        external_force.torque = transform.rotation * torque;
+    }
+}
@@ -0,0 +1,290 @@
+use std::{collections::BTreeMap, fmt::Display, fs, io, path::PathBuf, str::FromStr};
+
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::{warn, EventReader, EventWriter, Name, Plugin, Query, Res, ResMut, With, Without},
+    utils::HashMap,
+};
+use iyes_loopless::prelude::IntoConditionalSystem;
+use serde::{Deserialize, Serialize};
+use spacegame_core::message::{ClientId, ServerMessageOutQueue};
+
+use crate::shared::{
+    config::ConfigError,
+    entities::player::{PlayerClientId, PlayerMarker},
+    events::chat::AnnouncementEvent,
+    networking::{identity::PlayerIdentity, player_id::PlayerIdMap},
+};
+
+use super::{
+    command::{
+        AppCommandExt, CommandEvent, CommandFeedback, CommandInfo, CommandPermission, CommandSender,
+    },
+    config::ServerSettings,
+    session::DisconnectedSince,
+};
+
+/// What a player is allowed to do on the server, each role including the ones before it.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Can walk around and chat.
+    Guest,
+    /// Can also edit and pilot ships.
+    Builder,
+    /// Can also run every command.
+    Admin,
+}
+
+impl Role {
+    pub fn may_build(self) -> bool {
+        self >= Role::Builder
+    }
+
+    pub fn may_pilot(self) -> bool {
+        self >= Role::Builder
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Role::Guest => write!(f, "guest"),
+            Role::Builder => write!(f, "builder"),
+            Role::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "guest" => Ok(Role::Guest),
+            "builder" => Ok(Role::Builder),
+            "admin" => Ok(Role::Admin),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The roles granted to player identities, kept in a TOML file.
+///
+/// Identities without a granted role get the default role from the [`ServerSettings`].
+pub struct Roles {
+    path: PathBuf,
+    granted: HashMap<PlayerIdentity, Role>,
+    default_role: Role,
+}
+
+/// How [`Roles`] are stored, with identities in their hex form.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct RolesFile {
+    roles: BTreeMap<String, Role>,
+}
+
+impl Roles {
+    /// Load the roles stored at `path`, starting out empty if the file does not exist yet.
+    pub fn load(path: PathBuf, default_role: Role) -> Result<Self, ConfigError> {
+        let file = match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str::<RolesFile>(&contents).map_err(ConfigError::Parse)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => RolesFile::default(),
+            Err(e) => return Err(ConfigError::Io(e)),
+        };
+
+        let granted: HashMap<PlayerIdentity, Role> = file
+            .roles
+            .iter()
+            .map(|(identity, role)| identity.parse().map(|identity| (identity, *role)))
+            .collect::<Result<_, _>>()
+            .map_err(|_| ConfigError::Invalid("roles must be granted to identities in hex"))?;
+
+        Ok(Self {
+            path,
+            granted,
+            default_role,
+        })
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let file = RolesFile {
+            roles: self
+                .granted
+                .iter()
+                .map(|(identity, role)| (identity.to_string(), *role))
+                .collect(),
+        };
+        let contents =
+            toml::to_string(&file).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(&self.path, contents)
+    }
+
+    pub fn get(&self, identity: &PlayerIdentity) -> Role {
+        self.granted
+            .get(identity)
+            .copied()
+            .unwrap_or(self.default_role)
+    }
+
+    /// Grant a role, returning the one the identity had before.
+    pub fn grant(&mut self, identity: PlayerIdentity, role: Role) -> Role {
+        let previous = self.get(&identity);
+        self.granted.insert(identity, role);
+        previous
+    }
+
+    /// Take away the granted role so that the identity falls back to the default role, returning
+    /// the role it had before.
+    pub fn revoke(&mut self, identity: &PlayerIdentity) -> Role {
+        let previous = self.get(identity);
+        self.granted.remove(identity);
+        previous
+    }
+
+    pub fn default_role(&self) -> Role {
+        self.default_role
+    }
+}
+
+/// Looks up the role of connected clients.
+#[derive(SystemParam)]
+pub struct PlayerRoles<'w, 's> {
+    roles: Res<'w, Roles>,
+    player_ids: Res<'w, PlayerIdMap>,
+    identity_query: Query<'w, 's, &'static PlayerIdentity, With<PlayerMarker>>,
+}
+
+impl<'w, 's> PlayerRoles<'w, 's> {
+    /// The role of a client, or the lowest role if it is not a known player.
+    pub fn of_client(&self, client_id: ClientId) -> Role {
+        self.identity_of(client_id)
+            .map_or(Role::Guest, |identity| self.roles.get(identity))
+    }
+
+    pub fn identity_of(&self, client_id: ClientId) -> Option<&PlayerIdentity> {
+        self.player_ids
+            .from_client(client_id)
+            .and_then(|player_entity| self.identity_query.get(player_entity).ok())
+    }
+
+    /// The role of whoever sent a command. The console is always an admin.
+    pub fn of_sender(&self, sender: CommandSender) -> Role {
+        match sender {
+            CommandSender::Console => Role::Admin,
+            CommandSender::Player(client_id) => self.of_client(client_id),
+        }
+    }
+
+    pub fn may_run(&self, sender: CommandSender, info: &CommandInfo) -> bool {
+        info.permission.allows(self.of_sender(sender))
+    }
+}
+
+pub struct RolesPlugin;
+
+impl Plugin for RolesPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        let settings = app.world.resource::<ServerSettings>().clone();
+        let roles =
+            Roles::load(settings.roles.clone(), settings.default_role).unwrap_or_else(|e| {
+                panic!(
+                    "Could not load roles from {}: {}",
+                    settings.roles.display(),
+                    e
+                )
+            });
+
+        app.insert_resource(roles)
+            .add_command(CommandInfo {
+                name: "grant",
+                usage: "<player|identity> <guest|builder|admin>",
+                description: "Give a player a role",
+                permission: CommandPermission::Admin,
+            })
+            .add_command(CommandInfo {
+                name: "revoke",
+                usage: "<player|identity>",
+                description: "Put a player back to the default role",
+                permission: CommandPermission::Admin,
+            })
+            .add_system(on_command.run_on_event::<CommandEvent>());
+    }
+
+    fn name(&self) -> &str {
+        "roles_plugin"
+    }
+}
+
+fn on_command(
+    mut roles: ResMut<Roles>,
+    mut events: EventReader<CommandEvent>,
+    mut feedback: EventWriter<CommandFeedback>,
+    player_query: Query<(&Name, &PlayerIdentity), With<PlayerMarker>>,
+    online_query: Query<(&PlayerIdentity, &PlayerClientId), Without<DisconnectedSince>>,
+    mut announcement_queue: ResMut<ServerMessageOutQueue<AnnouncementEvent>>,
+) {
+    for event in events.iter() {
+        let (target, role) = match event.name.as_str() {
+            "grant" => {
+                let role = event.args.get(1).and_then(|role| role.parse().ok());
+                match (event.args.first(), role) {
+                    (Some(target), Some(role)) => (target, role),
+                    _ => {
+                        feedback.send(
+                            event.reply("Usage: grant <player|identity> <guest|builder|admin>"),
+                        );
+                        continue;
+                    }
+                }
+            }
+            "revoke" => match event.args.first() {
+                Some(target) => (target, roles.default_role()),
+                None => {
+                    feedback.send(event.reply("Usage: revoke <player|identity>"));
+                    continue;
+                }
+            },
+            _ => continue,
+        };
+
+        // Look up player names first, so that any known player can be named
+        let identity = player_query
+            .iter()
+            .find(|(name, _)| name.as_str() == target)
+            .map(|(_, identity)| *identity)
+            .or_else(|| target.parse::<PlayerIdentity>().ok());
+        let identity = match identity {
+            Some(identity) => identity,
+            None => {
+                feedback.send(event.reply(format!("No player or identity {}", target)));
+                continue;
+            }
+        };
+
+        let previous = if event.is("grant") {
+            roles.grant(identity, role)
+        } else {
+            roles.revoke(&identity)
+        };
+        if let Err(e) = roles.save() {
+            warn!("Could not save {}: {}", roles.path.display(), e);
+        }
+
+        if previous != role {
+            for (_, client_id) in online_query
+                .iter()
+                .filter(|(online_identity, _)| **online_identity == identity)
+            {
+                announcement_queue.send(
+                    &client_id.0,
+                    AnnouncementEvent {
+                        message: format!("You are now {}", role),
+                    },
+                );
+            }
+        }
+        feedback.send(event.reply(format!("{} is now {} (was {})", target, role, previous)));
+    }
+}
@@ -0,0 +1,200 @@
+use std::time::Instant;
+
+use bevy::{
+    prelude::{
+        info, Commands, Component, DespawnRecursiveExt, Entity, Name, Plugin, Query, Res, ResMut,
+        Transform, With,
+    },
+    utils::HashMap,
+};
+use spacegame_core::message::ServerMessageOutQueue;
+
+use crate::{
+    entities::player::PlayerClientId,
+    events::{
+        player::PlayerDespawnEvent,
+        ship::{EnteredShipEvent, LeftShipEvent},
+    },
+    model::ship::{Pilot, Ship},
+    shared::networking::identity::PlayerIdentity,
+};
+
+use super::{
+    config::ServerSettings,
+    world::{SavedPlayer, SavedPlayers},
+};
+
+/// Players that lost their connection but whose entity is kept around, so that they can pick up
+/// where they left off if they reconnect within the grace period.
+pub struct DisconnectedPlayers {
+    map: HashMap<PlayerIdentity, Entity>,
+}
+
+impl DisconnectedPlayers {
+    pub fn new() -> Self {
+        Self {
+            map: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, identity: PlayerIdentity, player_entity: Entity) {
+        self.map.insert(identity, player_entity);
+    }
+
+    /// Take the entity of a disconnected player, if they are still within the grace period.
+    pub fn take(&mut self, identity: &PlayerIdentity) -> Option<Entity> {
+        self.map.remove(identity)
+    }
+}
+
+/// Marks a player entity whose client is disconnected.
+#[derive(Component)]
+pub struct DisconnectedSince(pub Instant);
+
+/// A player that resumed their session while piloting a ship.
+///
+/// The seat is given back a tick after the ships have been sent, so that the client knows the ship
+/// by the time it is told it is piloting it.
+#[derive(Component)]
+pub struct RestoreSeat {
+    pub ship_entity: Entity,
+}
+
+pub struct SessionPlugin;
+
+impl Plugin for SessionPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.insert_resource(DisconnectedPlayers::new())
+            .add_system(expire_disconnected_players)
+            .add_system(restore_seats);
+    }
+
+    fn name(&self) -> &str {
+        "session_plugin"
+    }
+}
+
+/// The player entity a connecting identity already has.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PreviousSession {
+    pub player_entity: Entity,
+    pub old_client_id: u64,
+    /// The old client has not been noticed to be gone yet, as happens when a client reconnects
+    /// right after crashing, so it still has to be dropped.
+    pub still_connected: bool,
+}
+
+/// Find the session a connecting `identity` takes over, so that it never gets a second player.
+///
+/// Disconnected players within the grace period are taken out of `disconnected_players`,
+/// otherwise a player that is still online with the same identity is picked.
+pub fn previous_session<'a>(
+    disconnected_players: &mut DisconnectedPlayers,
+    players: impl IntoIterator<Item = (Entity, &'a PlayerIdentity, &'a PlayerClientId)>,
+    identity: &PlayerIdentity,
+) -> Option<PreviousSession> {
+    let disconnected = disconnected_players.take(identity);
+    players
+        .into_iter()
+        .find(|(player_entity, other_identity, _)| match disconnected {
+            Some(disconnected) => *player_entity == disconnected,
+            None => *other_identity == identity,
+        })
+        .map(|(player_entity, _, client_id)| PreviousSession {
+            player_entity,
+            old_client_id: client_id.0,
+            still_connected: disconnected.is_none(),
+        })
+}
+
+/// Hand the seat of a ship over from a client to its new connection.
+///
+/// Returns the ship the player was piloting, if any.
+pub fn transfer_seat(
+    pilot_query: &mut Query<(Entity, &mut Pilot), With<Ship>>,
+    old_client_id: u64,
+    new_client_id: u64,
+) -> Option<Entity> {
+    for (ship_entity, mut pilot) in pilot_query.iter_mut() {
+        if let Pilot::Pilot(client_id) = *pilot {
+            if client_id == old_client_id {
+                *pilot = Pilot::Pilot(new_client_id);
+                return Some(ship_entity);
+            }
+        }
+    }
+    None
+}
+
+fn restore_seats(
+    mut commands: Commands,
+    query: Query<(Entity, &PlayerClientId, &RestoreSeat)>,
+    mut entered_ship_queue: ResMut<ServerMessageOutQueue<EnteredShipEvent>>,
+) {
+    for (player_entity, client_id, restore_seat) in query.iter() {
+        entered_ship_queue.broadcast(EnteredShipEvent {
+            ship_entity: restore_seat.ship_entity,
+            player_id: client_id.0,
+        });
+        commands.entity(player_entity).remove::<RestoreSeat>();
+    }
+}
+
+fn expire_disconnected_players(
+    mut commands: Commands,
+    settings: Res<ServerSettings>,
+    mut disconnected_players: ResMut<DisconnectedPlayers>,
+    mut saved_players: ResMut<SavedPlayers>,
+    query: Query<(
+        Entity,
+        &PlayerIdentity,
+        &PlayerClientId,
+        &Name,
+        &Transform,
+        &DisconnectedSince,
+    )>,
+    mut pilot_query: Query<(Entity, &mut Pilot), With<Ship>>,
+    mut left_ship_queue: ResMut<ServerMessageOutQueue<LeftShipEvent>>,
+    mut player_despawn_queue: ResMut<ServerMessageOutQueue<PlayerDespawnEvent>>,
+) {
+    let grace_period = settings.reconnect_grace_period();
+    for (player_entity, identity, client_id, name, transform, disconnected_since) in query.iter() {
+        if disconnected_since.0.elapsed() < grace_period {
+            continue;
+        }
+
+        info!(
+            "Client [{}] did not reconnect in time, removing their player",
+            client_id.0
+        );
+
+        for (ship_entity, mut pilot) in pilot_query.iter_mut() {
+            if let Pilot::Pilot(pilot_id) = *pilot {
+                if pilot_id == client_id.0 {
+                    *pilot = Pilot::None;
+                    left_ship_queue.broadcast(LeftShipEvent {
+                        ship_entity,
+                        player_id: client_id.0,
+                    });
+                }
+            }
+        }
+
+        disconnected_players.take(identity);
+        // Keep where they were, so that they come back there when they join again
+        saved_players.insert(
+            *identity,
+            SavedPlayer {
+                name: name.to_string(),
+                transform: *transform,
+                seat: None,
+            },
+        );
+        commands.entity(player_entity).despawn_recursive();
+
+        player_despawn_queue.broadcast(PlayerDespawnEvent {
+            player_entity,
+            player_id: client_id.0,
+        });
+    }
+}
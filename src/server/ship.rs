@@ -1,23 +1,44 @@
-use bevy::prelude::{
-    trace, BuildChildren, Changed, Commands, DespawnRecursiveExt, Entity, EventReader,
-    ParallelSystemDescriptorCoercion, Plugin, Query, ResMut, Transform, With,
+use bevy::{
+    prelude::{
+        default, trace, BuildChildren, Changed, Commands, DespawnRecursiveExt, Entity, EventReader,
+        EventWriter, Name, ParallelSystemDescriptorCoercion, Plugin, Query, Res, ResMut, Transform,
+        Vec3, With,
+    },
+    transform::TransformBundle,
 };
-use bevy_rapier3d::prelude::{ExternalForce, Velocity};
+use bevy_rapier3d::prelude::Velocity;
+use iyes_loopless::prelude::IntoConditionalSystem;
 use spacegame_core::message::ServerMessageOutQueue;
 
 use crate::{
+    entities::player::PlayerMarker,
     events::ship::{
-        BlockRemoveEvent, BlockUpdateEvent, EnteredShipEvent, LeftShipEvent, ShipMoveEvent,
-        SyncShipPositionEvent, TryEnterShipEvent, TryLeaveShipEvent, UnloadShipEvent,
+        BlockRemoveEvent, BlockUpdateEvent, EnteredShipEvent, LeftShipEvent, LoadShipEvent,
+        ShipMoveEvent, SyncShipPositionEvent, TryEnterShipEvent, TryLeaveShipEvent,
+        UnloadShipEvent,
     },
     model::{
         block::BlockBundle,
+        block_definitions::BlockDefinitions,
         block_map::BlockMap,
-        ship::{Pilot, Ship},
+        blueprint::{Blueprint, BlueprintBlock},
+        ship::{Pilot, Ship, ShipBundle, ShipName},
     },
+    networking::identity::PlayerIdentity,
 };
 
-use super::labels::UpdateLabels;
+use super::{
+    command::{
+        AppCommandExt, CommandEvent, CommandFeedback, CommandInfo, CommandPermission, CommandSender,
+    },
+    config::ServerSettings,
+    faction::Factions,
+    labels::UpdateLabels,
+    ownership::{may_build, may_pilot, ship_ownership, Owner, OwnershipBundle, SharedRights},
+    physics::ShipMass,
+    propulsion::{ShipControls, ShipPropulsion},
+    roles::PlayerRoles,
+};
 
 pub struct ShipPlugin;
 
@@ -28,7 +49,14 @@ impl Plugin for ShipPlugin {
             .add_system(on_ship_move)
             .add_system(on_try_enter_ship)
             .add_system(on_try_leave_ship)
-            .add_system(sync_ship_position_velocity.label(UpdateLabels::Sync));
+            .add_system(sync_ship_position_velocity.label(UpdateLabels::Sync))
+            .add_command(CommandInfo {
+                name: "spawn_ship",
+                usage: "<blueprint> <x y z>",
+                description: "Spawn a ship from a blueprint",
+                permission: CommandPermission::Admin,
+            })
+            .add_system(on_command.run_on_event::<CommandEvent>());
     }
 
     fn name(&self) -> &str {
@@ -36,14 +64,175 @@ impl Plugin for ShipPlugin {
     }
 }
 
+/// Spawn a ship built from `blueprint`, returning its entity and block map.
+pub fn spawn_ship(
+    commands: &mut Commands,
+    definitions: &BlockDefinitions,
+    blueprint: &Blueprint,
+    transform: Transform,
+    owner: Owner,
+) -> (Entity, BlockMap) {
+    let ship_entity = commands.spawn().id();
+    let mut block_map = BlockMap::new();
+
+    for block in &blueprint.blocks {
+        let block_entity = commands
+            .spawn_bundle(BlockBundle::new(
+                block.block_type,
+                block.position,
+                block.rotation,
+            ))
+            .id();
+        commands.entity(ship_entity).add_child(block_entity);
+
+        if let Some(old_entity) = block_map
+            .set(block.position, block.block_type, block.rotation)
+            .and_then(|old_block| old_block.entity)
+        {
+            commands.entity(old_entity).despawn_recursive();
+        }
+        block_map.set_entity(block.position, block_entity);
+    }
+
+    commands
+        .entity(ship_entity)
+        .insert_bundle(ShipBundle {
+            block_map: block_map.clone(),
+            transform_bundle: TransformBundle {
+                local: transform,
+                ..default()
+            },
+            ship_name: ShipName {
+                name: blueprint.name.clone(),
+            },
+            ..default()
+        })
+        .insert_bundle(OwnershipBundle::new(owner))
+        .insert(ShipMass::from_block_map(definitions, &block_map))
+        .insert(ShipPropulsion::from_block_map(definitions, &block_map))
+        .insert(ShipControls::default());
+
+    (ship_entity, block_map)
+}
+
+fn on_command(
+    mut commands: Commands,
+    settings: Res<ServerSettings>,
+    definitions: Res<BlockDefinitions>,
+    roles: PlayerRoles,
+    player_query: Query<(&Name, &PlayerIdentity), With<PlayerMarker>>,
+    mut events: EventReader<CommandEvent>,
+    mut feedback: EventWriter<CommandFeedback>,
+    mut load_ship_queue: ResMut<ServerMessageOutQueue<LoadShipEvent>>,
+) {
+    for event in events.iter().filter(|event| event.is("spawn_ship")) {
+        let (blueprint_name, position) = match (event.args.first(), event.vec3_arg(1)) {
+            (Some(blueprint_name), Some(position)) => (blueprint_name, position),
+            _ => {
+                feedback.send(event.reply("Usage: spawn_ship <blueprint> <x y z>"));
+                continue;
+            }
+        };
+
+        let blueprint = match Blueprint::load(&settings.blueprints, blueprint_name) {
+            Ok(blueprint) if !blueprint.blocks.is_empty() => blueprint,
+            Ok(_) => {
+                feedback.send(event.reply(format!("Blueprint {} has no blocks", blueprint_name)));
+                continue;
+            }
+            Err(e) => {
+                feedback.send(event.reply(format!(
+                    "Could not load blueprint {}: {}",
+                    blueprint_name, e
+                )));
+                continue;
+            }
+        };
+        if let Some(block) = blueprint
+            .blocks
+            .iter()
+            .find(|block| definitions.get(block.block_type).is_none())
+        {
+            feedback.send(event.reply(format!(
+                "Blueprint {} has blocks of unknown type {}",
+                blueprint_name, block.block_type
+            )));
+            continue;
+        }
+
+        // Ships spawned from the console belong to nobody
+        let owner = match event.sender {
+            CommandSender::Player(client_id) => roles
+                .identity_of(client_id)
+                .map_or(Owner::None, |identity| Owner::Player(*identity)),
+            CommandSender::Console => Owner::None,
+        };
+        let transform = Transform::from_translation(position);
+        let (ship_entity, block_map) = spawn_ship(
+            &mut commands,
+            &definitions,
+            &blueprint,
+            transform,
+            owner.clone(),
+        );
+        load_ship_queue.broadcast(LoadShipEvent {
+            ship_entity,
+            block_map,
+            transform,
+            velocity: Velocity::zero(),
+            name: blueprint.name.clone(),
+            ownership: ship_ownership(&owner, &SharedRights::default(), &player_query),
+        });
+
+        feedback.send(event.reply(format!("Spawned {} at {}", blueprint.name, position)));
+    }
+}
+
 fn on_block_update(
     mut commands: Commands,
+    definitions: Res<BlockDefinitions>,
+    roles: PlayerRoles,
+    factions: Res<Factions>,
     mut events: EventReader<BlockUpdateEvent>,
-    mut query: Query<&mut BlockMap>,
+    mut query: Query<(
+        &mut BlockMap,
+        &mut ShipMass,
+        &mut ShipPropulsion,
+        &Owner,
+        &SharedRights,
+    )>,
     mut block_update_queue: ResMut<ServerMessageOutQueue<BlockUpdateEvent>>,
 ) {
     for event in events.iter() {
-        let mut block_map = query.get_mut(event.ship_entity).unwrap();
+        let (mut block_map, mut ship_mass, mut propulsion, owner, shared_rights) =
+            match query.get_mut(event.ship_entity) {
+                Ok(ship) => ship,
+                Err(_) => {
+                    trace!(
+                        "{:?} sent a block update for unknown ship {:?}",
+                        event.client_id,
+                        event.ship_entity
+                    );
+                    continue;
+                }
+            };
+        if !may_build(&roles, &factions, event.client_id, owner, shared_rights) {
+            trace!(
+                "{:?} is not allowed to build on ship {:?}",
+                event.client_id,
+                event.ship_entity
+            );
+            continue;
+        }
+        if definitions.get(event.block_type).is_none() {
+            trace!(
+                "{:?} sent unknown block type {}",
+                event.client_id,
+                event.block_type
+            );
+            continue;
+        }
+
         // TODO: Check if an identical block already exists here
         let block_entity = commands
             .spawn_bundle(BlockBundle::new(
@@ -63,53 +252,233 @@ fn on_block_update(
             client_id: 0,
         });
 
-        if let Some(old_block) = block_map.set(
-            block_entity,
+        *ship_mass += ShipMass::block(
+            &definitions,
+            event.block_type,
+            event.block_position,
+            event.block_rotation,
+        );
+        *propulsion += ShipPropulsion::block(
+            &definitions,
             event.block_type,
             event.block_position,
             event.block_rotation,
-        ) {
-            commands.entity(old_block.entity).despawn_recursive();
+        );
+        let old_block = block_map.set(event.block_position, event.block_type, event.block_rotation);
+        block_map.set_entity(event.block_position, block_entity);
+        if let Some(old_block) = old_block {
+            *ship_mass -= ShipMass::block(
+                &definitions,
+                old_block.block_type,
+                event.block_position,
+                old_block.block_rotation,
+            );
+            *propulsion -= ShipPropulsion::block(
+                &definitions,
+                old_block.block_type,
+                event.block_position,
+                old_block.block_rotation,
+            );
+            if let Some(old_entity) = old_block.entity {
+                commands.entity(old_entity).despawn_recursive();
+            }
         }
     }
 }
 
 fn on_block_remove(
     mut commands: Commands,
+    definitions: Res<BlockDefinitions>,
+    roles: PlayerRoles,
+    factions: Res<Factions>,
+    player_query: Query<(&Name, &PlayerIdentity), With<PlayerMarker>>,
     mut events: EventReader<BlockRemoveEvent>,
-    mut query: Query<(Entity, &mut BlockMap)>,
+    mut query: Query<(
+        Entity,
+        &mut BlockMap,
+        &mut ShipMass,
+        &mut ShipPropulsion,
+        &Owner,
+        &SharedRights,
+        &ShipName,
+        &Transform,
+        &Velocity,
+    )>,
     mut block_remove_queue: ResMut<ServerMessageOutQueue<BlockRemoveEvent>>,
+    mut load_ship_queue: ResMut<ServerMessageOutQueue<LoadShipEvent>>,
 ) {
     for event in events.iter() {
-        let (ship_entity, mut block_map) = query.get_mut(event.ship_entity).unwrap();
+        let (
+            ship_entity,
+            mut block_map,
+            mut ship_mass,
+            mut propulsion,
+            owner,
+            shared_rights,
+            ship_name,
+            transform,
+            velocity,
+        ) = match query.get_mut(event.ship_entity) {
+            Ok(ship) => ship,
+            Err(_) => {
+                trace!(
+                    "{:?} sent a block removal for unknown ship {:?}",
+                    event.client_id,
+                    event.ship_entity
+                );
+                continue;
+            }
+        };
+        if !may_build(&roles, &factions, event.client_id, owner, shared_rights) {
+            trace!(
+                "{:?} is not allowed to build on ship {:?}",
+                event.client_id,
+                ship_entity
+            );
+            continue;
+        }
+
+        if let Some(old_block) = block_map.remove(&event.block_position) {
+            // Where the ship was spinning around until now
+            let center_of_mass = ship_mass.center_of_mass();
 
-        if let Some(old_block_entity) = block_map.remove(&event.block_position) {
+            *ship_mass -= ShipMass::block(
+                &definitions,
+                old_block.block_type,
+                event.block_position,
+                old_block.block_rotation,
+            );
+            *propulsion -= ShipPropulsion::block(
+                &definitions,
+                old_block.block_type,
+                event.block_position,
+                old_block.block_rotation,
+            );
             block_remove_queue.broadcast(BlockRemoveEvent {
                 ship_entity,
                 block_position: event.block_position,
                 client_id: 0,
             });
 
-            commands.entity(old_block_entity).despawn_recursive();
+            if let Some(old_entity) = old_block.entity {
+                commands.entity(old_entity).despawn_recursive();
+            }
+
+            // Only a block holding others together can split the ship
+            if block_map.neighbours(event.block_position).count() < 2 {
+                continue;
+            }
+
+            // The biggest part stays the ship, everything else flies off on its own
+            let parts = block_map.connected_parts();
+            for part in parts.iter().skip(1) {
+                let blueprint = Blueprint {
+                    name: ship_name.name.clone(),
+                    blocks: part
+                        .iter()
+                        .filter_map(|position| {
+                            block_map.get(position).map(|block| BlueprintBlock {
+                                block_type: block.block_type,
+                                position: *position,
+                                rotation: block.block_rotation,
+                            })
+                        })
+                        .collect(),
+                };
+                for position in part {
+                    if let Some(old_block) = block_map.remove(position) {
+                        if let Some(old_entity) = old_block.entity {
+                            commands.entity(old_entity).despawn_recursive();
+                        }
+                        block_remove_queue.broadcast(BlockRemoveEvent {
+                            ship_entity,
+                            block_position: *position,
+                            client_id: 0,
+                        });
+                    }
+                }
+
+                let (part_entity, part_block_map) = spawn_ship(
+                    &mut commands,
+                    &definitions,
+                    &blueprint,
+                    *transform,
+                    owner.clone(),
+                );
+
+                // Keep moving the way this part of the ship was moving
+                let offset = ShipMass::from_block_map(&definitions, &part_block_map)
+                    .center_of_mass()
+                    - center_of_mass;
+                let part_velocity = Velocity {
+                    linvel: velocity.linvel + velocity.angvel.cross(transform.rotation * offset),
+                    angvel: velocity.angvel,
+                };
+                commands
+                    .entity(part_entity)
+                    .insert(part_velocity)
+                    .insert(shared_rights.clone());
+
+                load_ship_queue.broadcast(LoadShipEvent {
+                    ship_entity: part_entity,
+                    block_map: part_block_map,
+                    transform: *transform,
+                    velocity: part_velocity,
+                    name: blueprint.name,
+                    ownership: ship_ownership(owner, shared_rights, &player_query),
+                });
+                trace!(
+                    "Ship {:?} split off {:?} with {} blocks",
+                    ship_entity,
+                    part_entity,
+                    part.len()
+                );
+            }
+
+            if parts.len() > 1 {
+                *ship_mass = ShipMass::from_block_map(&definitions, &block_map);
+                *propulsion = ShipPropulsion::from_block_map(&definitions, &block_map);
+            }
         }
     }
 }
 
 fn on_try_enter_ship(
+    roles: PlayerRoles,
+    factions: Res<Factions>,
     mut events: EventReader<TryEnterShipEvent>,
     mut queue: ResMut<ServerMessageOutQueue<EnteredShipEvent>>,
-    mut pilot_query: Query<&mut Pilot, With<Ship>>,
+    mut pilot_query: Query<(&mut Pilot, &Owner, &SharedRights), With<Ship>>,
 ) {
     for event in events.iter() {
-        let mut pilot = pilot_query.get_mut(event.ship_entity).unwrap();
+        let (mut pilot, owner, shared_rights) = match pilot_query.get_mut(event.ship_entity) {
+            Ok(ship) => ship,
+            Err(_) => {
+                trace!(
+                    "{:?} sent an enter request for unknown ship {:?}",
+                    event.client_id,
+                    event.ship_entity
+                );
+                continue;
+            }
+        };
         trace!(
             "{:?} tried to enter ship {:?}",
             event.client_id,
             event.ship_entity
         );
+        if !may_pilot(&roles, &factions, event.client_id, owner, shared_rights) {
+            trace!(
+                "{:?} is not allowed to pilot ship {:?}",
+                event.client_id,
+                event.ship_entity
+            );
+            continue;
+        }
+
         match *pilot {
             Pilot::None => {
-                // TODO: Check that player can in-fact enter this ship, distance, faction, etc.
+                // TODO: Check that the player is close enough to the ship
                 *pilot = Pilot::Pilot(event.client_id);
                 queue.broadcast(EnteredShipEvent {
                     ship_entity: event.ship_entity,
@@ -128,7 +497,17 @@ fn on_try_leave_ship(
     mut pilot_query: Query<&mut Pilot, With<Ship>>,
 ) {
     for event in events.iter() {
-        let mut pilot = pilot_query.get_mut(event.ship_entity).unwrap();
+        let mut pilot = match pilot_query.get_mut(event.ship_entity) {
+            Ok(pilot) => pilot,
+            Err(_) => {
+                trace!(
+                    "{:?} sent a leave request for unknown ship {:?}",
+                    event.client_id,
+                    event.ship_entity
+                );
+                continue;
+            }
+        };
         match *pilot {
             Pilot::Pilot(client_id) => {
                 trace!(
@@ -152,12 +531,15 @@ fn on_try_leave_ship(
 
 fn on_ship_move(
     mut events: EventReader<ShipMoveEvent>,
-    mut query: Query<(&Pilot, &mut ExternalForce), With<Ship>>,
+    mut query: Query<(&Pilot, &mut ShipControls), With<Ship>>,
 ) {
     for event in events.iter() {
-        if let Ok((Pilot::Pilot(pilot_id), mut force)) = query.get_mut(event.ship_entity) {
+        if let Ok((Pilot::Pilot(pilot_id), mut controls)) = query.get_mut(event.ship_entity) {
             if *pilot_id == event.client_id {
-                *force = event.force;
+                *controls = ShipControls {
+                    throttle: event.throttle.clamp(Vec3::splat(-1.), Vec3::ONE),
+                    steering: event.steering,
+                };
             }
         }
     }
@@ -185,7 +567,7 @@ fn despawn_empty_ship(
     mut unload_ship_queue: ResMut<ServerMessageOutQueue<UnloadShipEvent>>,
 ) {
     for (ship_entity, block_map, pilot) in query.iter() {
-        if block_map.block_count == 0 {
+        if block_map.is_empty() {
             unload_ship_queue.broadcast(UnloadShipEvent { ship_entity });
             commands.entity(ship_entity).despawn_recursive();
         }
@@ -1,20 +1,24 @@
 use bevy::prelude::{
-    BuildChildren, Changed, Commands, Component, Entity, EventReader, GlobalTransform, Or, Plugin,
-    Query, ResMut, Transform, With,
+    BuildChildren, Changed, Commands, Component, Entity, EventReader, GlobalTransform, Name, Or,
+    Plugin, Query, ResMut, Transform, With,
 };
 use bevy_rapier3d::prelude::Velocity;
 use bevy_renet::renet::ServerEvent;
 use spacegame_core::{message::ServerMessageOutQueue, network_id::NetworkId};
 
 use crate::{
+    entities::player::PlayerMarker,
     events::{
         generic::{BindPositionEvent, UnbindPositionEvent},
         ship::LoadShipEvent,
     },
-    model::block_map::BlockMap,
+    model::{block_map::BlockMap, ship::ShipName},
+    networking::identity::PlayerIdentity,
     shared::events::generic::GenericPositionSyncEvent,
 };
 
+use super::ownership::{ship_ownership, Owner, SharedRights};
+
 pub struct SyncPlugin;
 
 impl Plugin for SyncPlugin {
@@ -30,13 +34,31 @@ impl Plugin for SyncPlugin {
 
 fn on_client_connect(
     mut server_events: EventReader<ServerEvent>,
-    ship_query: Query<(Entity, &Transform, &Velocity, &BlockMap)>,
+    ship_query: Query<(
+        Entity,
+        &Transform,
+        &Velocity,
+        &BlockMap,
+        &ShipName,
+        &Owner,
+        &SharedRights,
+    )>,
+    player_query: Query<(&Name, &PlayerIdentity), With<PlayerMarker>>,
     mut ship_queue: ResMut<ServerMessageOutQueue<LoadShipEvent>>,
 ) {
     for event in server_events.iter() {
         match event {
             ServerEvent::ClientConnected(client_id, _) => {
-                for (ship_entity, transform, velocity, block_map) in ship_query.iter() {
+                for (
+                    ship_entity,
+                    transform,
+                    velocity,
+                    block_map,
+                    ship_name,
+                    owner,
+                    shared_rights,
+                ) in ship_query.iter()
+                {
                     ship_queue.send(
                         client_id,
                         LoadShipEvent {
@@ -44,7 +66,8 @@ fn on_client_connect(
                             transform: transform.clone(),
                             velocity: velocity.clone(),
                             block_map: block_map.clone(),
-                            name: String::from("some ship generic ass name"),
+                            name: ship_name.name.clone(),
+                            ownership: ship_ownership(owner, shared_rights, &player_query),
                         },
                     );
                 }
@@ -75,4 +98,3 @@ fn generic_position_sync(
         });
     }
 }
-
@@ -0,0 +1,82 @@
+use std::time::{Duration, Instant};
+
+use bevy::prelude::{warn, CoreStage, Plugin, Res, ResMut};
+
+/// The fixed rate the server simulates at.
+#[derive(Clone, Copy, Debug)]
+pub struct TickSettings {
+    pub tick_rate: f64,
+}
+
+impl TickSettings {
+    pub fn new(tick_rate: f64) -> Self {
+        Self { tick_rate }
+    }
+
+    /// How long a single tick is allowed to take.
+    pub fn budget(&self) -> Duration {
+        Duration::from_secs_f64(1. / self.tick_rate)
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        (1. / self.tick_rate) as f32
+    }
+}
+
+impl Default for TickSettings {
+    fn default() -> Self {
+        Self { tick_rate: 64. }
+    }
+}
+
+/// The number of ticks the server has run since startup.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ServerTick(pub u64);
+
+struct TickTimer {
+    started_at: Instant,
+}
+
+/// Keeps track of the current tick and reports ticks that take longer than their budget.
+///
+/// The pacing itself is done by the [bevy::app::ScheduleRunnerPlugin], which has to be configured
+/// with [TickSettings::budget] before it is added.
+pub struct TickPlugin;
+
+impl Plugin for TickPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        if !app.world.contains_resource::<TickSettings>() {
+            app.insert_resource(TickSettings::default());
+        }
+
+        app.insert_resource(ServerTick::default())
+            .insert_resource(TickTimer {
+                started_at: Instant::now(),
+            })
+            .add_system_to_stage(CoreStage::First, start_tick)
+            .add_system_to_stage(CoreStage::Last, end_tick);
+    }
+
+    fn name(&self) -> &str {
+        "tick_plugin"
+    }
+}
+
+fn start_tick(mut timer: ResMut<TickTimer>, mut tick: ResMut<ServerTick>) {
+    timer.started_at = Instant::now();
+    tick.0 += 1;
+}
+
+fn end_tick(settings: Res<TickSettings>, timer: Res<TickTimer>, tick: Res<ServerTick>) {
+    let elapsed = timer.started_at.elapsed();
+    let budget = settings.budget();
+    if elapsed > budget {
+        warn!(
+            "Tick {} took {:.2}ms, {:.2}ms over its {:.2}ms budget",
+            tick.0,
+            elapsed.as_secs_f64() * 1000.,
+            (elapsed - budget).as_secs_f64() * 1000.,
+            budget.as_secs_f64() * 1000.,
+        );
+    }
+}
@@ -0,0 +1,660 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fmt::Display,
+    fs, io, mem,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    prelude::{
+        info, warn, Commands, CoreStage, Entity, EventReader, EventWriter, Name, Plugin, Query,
+        Res, ResMut, Transform, With,
+    },
+    utils::HashMap,
+};
+use bevy_rapier3d::prelude::Velocity;
+use iyes_loopless::prelude::IntoConditionalSystem;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::shared::{
+    entities::player::{PlayerClientId, PlayerMarker},
+    migration::{load_versioned, unsupported_version, MigrationError, Versioned},
+    model::{
+        block::BlockType,
+        block_definitions::{block_definitions_loaded, BlockDefinitions},
+        block_map::{BlockMap, BlockPosition, BlockRotation},
+        blueprint::{Blueprint, BlueprintBlock},
+        faction::Relation,
+        ship::{Pilot, Ship, ShipName},
+    },
+    networking::identity::PlayerIdentity,
+    remote_refs::{TransformDef, VelocityDef},
+};
+
+use super::{
+    config::ServerSettings,
+    faction::{Faction, Factions},
+    lifecycle::{SaveWorldRequest, ShutdownState},
+    ownership::{Owner, SharedRights},
+    ship::spawn_ship,
+};
+
+/// Version of the world save format, to be bumped whenever it changes.
+pub const WORLD_VERSION: u32 = 4;
+
+/// Everything about the world that outlives a restart of the server.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WorldSave {
+    pub version: u32,
+    pub ships: Vec<ShipSave>,
+    pub players: Vec<PlayerSave>,
+    pub factions: BTreeMap<String, FactionSave>,
+}
+
+/// A ship, with its blocks stored like in a blueprint so that no entity ids end up on disk.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ShipSave {
+    pub name: String,
+    pub blocks: Vec<BlueprintBlock>,
+    #[serde(with = "TransformDef")]
+    pub transform: Transform,
+    #[serde(with = "VelocityDef")]
+    pub velocity: Velocity,
+    pub owner: SavedOwner,
+    #[serde(default)]
+    pub builders: Vec<SavedIdentity>,
+    #[serde(default)]
+    pub pilots: Vec<SavedIdentity>,
+    /// Who was piloting the ship, to get their seat back when they join again.
+    #[serde(default)]
+    pub pilot: Option<SavedIdentity>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PlayerSave {
+    pub identity: SavedIdentity,
+    pub name: String,
+    #[serde(with = "TransformDef")]
+    pub transform: Transform,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FactionSave {
+    pub leader: SavedIdentity,
+    #[serde(default)]
+    pub members: Vec<SavedIdentity>,
+    #[serde(default)]
+    pub invites: Vec<SavedIdentity>,
+    #[serde(default)]
+    pub relations: BTreeMap<String, Relation>,
+}
+
+impl From<&Faction> for FactionSave {
+    fn from(faction: &Faction) -> Self {
+        Self {
+            leader: SavedIdentity(faction.leader),
+            members: faction.members.iter().map(|i| SavedIdentity(*i)).collect(),
+            invites: faction.invites.iter().map(|i| SavedIdentity(*i)).collect(),
+            relations: faction.relations.clone(),
+        }
+    }
+}
+
+impl From<FactionSave> for Faction {
+    fn from(faction: FactionSave) -> Self {
+        Self {
+            leader: faction.leader.0,
+            members: faction.members.into_iter().map(|i| i.0).collect(),
+            invites: faction.invites.into_iter().map(|i| i.0).collect(),
+            relations: faction.relations,
+        }
+    }
+}
+
+/// A player identity, saved in its hex form like in the other server files.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SavedIdentity(pub PlayerIdentity);
+
+impl Serialize for SavedIdentity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for SavedIdentity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        hex.parse()
+            .map(SavedIdentity)
+            .map_err(|_| de::Error::custom("identities must be in hex"))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub enum SavedOwner {
+    None,
+    Player(SavedIdentity),
+    Faction(String),
+}
+
+impl From<&Owner> for SavedOwner {
+    fn from(owner: &Owner) -> Self {
+        match owner {
+            Owner::None => SavedOwner::None,
+            Owner::Player(identity) => SavedOwner::Player(SavedIdentity(*identity)),
+            Owner::Faction(faction) => SavedOwner::Faction(faction.clone()),
+        }
+    }
+}
+
+impl From<SavedOwner> for Owner {
+    fn from(owner: SavedOwner) -> Self {
+        match owner {
+            SavedOwner::None => Owner::None,
+            SavedOwner::Player(identity) => Owner::Player(identity.0),
+            SavedOwner::Faction(faction) => Owner::Faction(faction),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum WorldError {
+    Io(io::Error),
+    Load(MigrationError),
+    Serialize(ron::Error),
+}
+
+impl Display for WorldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WorldError::Io(e) => write!(f, "{}", e),
+            WorldError::Load(e) => write!(f, "could not load world: {}", e),
+            WorldError::Serialize(e) => write!(f, "could not serialize world: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for WorldError {}
+
+impl WorldSave {
+    /// Read the world saved at `path`, or `None` if there is no world there yet.
+    pub fn load(path: &Path) -> Result<Option<Self>, WorldError> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(WorldError::Io(e)),
+        };
+
+        load_versioned(&contents)
+            .map(Some)
+            .map_err(WorldError::Load)
+    }
+
+    /// Write the world to `path`, going through a temporary file so that a crash halfway through
+    /// does not leave a broken save behind.
+    pub fn write(&self, path: &Path) -> Result<(), WorldError> {
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(WorldError::Serialize)?;
+        let temporary_path = path.with_extension("tmp");
+        fs::write(&temporary_path, contents).map_err(WorldError::Io)?;
+        fs::rename(&temporary_path, path).map_err(WorldError::Io)
+    }
+}
+
+impl Versioned for WorldSave {
+    const VERSION: u32 = WORLD_VERSION;
+
+    fn migrate(version: u32, contents: &str) -> Result<Self, MigrationError> {
+        // Worlds have been versioned since the first one was saved
+        match version {
+            1 => Ok(ron::from_str::<legacy::WorldSaveV1>(contents)?.into()),
+            2 => Ok(ron::from_str::<legacy::WorldSaveV2>(contents)?.into()),
+            3 => Ok(ron::from_str::<legacy::WorldSaveV3>(contents)?.into()),
+            _ => Err(unsupported_version::<Self>(version)),
+        }
+    }
+}
+
+/// Versions 1 to 3, which differ from the current version in the format of the blocks and in
+/// keeping factions in a file of their own.
+mod legacy {
+    use bevy::prelude::Transform;
+    use bevy_rapier3d::prelude::Velocity;
+    use serde::Deserialize;
+
+    use crate::shared::{
+        model::blueprint::{v1, v2, BlueprintBlock},
+        remote_refs::{TransformDef, VelocityDef},
+    };
+
+    use super::{PlayerSave, SavedIdentity, SavedOwner, WORLD_VERSION};
+
+    pub type WorldSaveV1 = WorldSave<v1::BlueprintBlock>;
+    pub type WorldSaveV2 = WorldSave<v2::BlueprintBlock>;
+    pub type WorldSaveV3 = WorldSave<BlueprintBlock>;
+
+    #[derive(Deserialize)]
+    pub struct WorldSave<B> {
+        pub ships: Vec<ShipSave<B>>,
+        pub players: Vec<PlayerSave>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct ShipSave<B> {
+        pub name: String,
+        pub blocks: Vec<B>,
+        #[serde(with = "TransformDef")]
+        pub transform: Transform,
+        #[serde(with = "VelocityDef")]
+        pub velocity: Velocity,
+        pub owner: SavedOwner,
+        #[serde(default)]
+        pub builders: Vec<SavedIdentity>,
+        #[serde(default)]
+        pub pilots: Vec<SavedIdentity>,
+        #[serde(default)]
+        pub pilot: Option<SavedIdentity>,
+    }
+
+    impl<B: Into<BlueprintBlock>> From<ShipSave<B>> for super::ShipSave {
+        fn from(ship: ShipSave<B>) -> Self {
+            Self {
+                name: ship.name,
+                blocks: ship.blocks.into_iter().map(Into::into).collect(),
+                transform: ship.transform,
+                velocity: ship.velocity,
+                owner: ship.owner,
+                builders: ship.builders,
+                pilots: ship.pilots,
+                pilot: ship.pilot,
+            }
+        }
+    }
+
+    impl<B: Into<BlueprintBlock>> From<WorldSave<B>> for super::WorldSave {
+        fn from(world: WorldSave<B>) -> Self {
+            Self {
+                version: WORLD_VERSION,
+                ships: world.ships.into_iter().map(Into::into).collect(),
+                players: world.players,
+                // Left to be imported from the factions file when the world is loaded
+                factions: Default::default(),
+            }
+        }
+    }
+}
+
+/// Players that are not in the world right now, kept until they join again.
+#[derive(Default)]
+pub struct SavedPlayers {
+    map: HashMap<PlayerIdentity, SavedPlayer>,
+}
+
+pub struct SavedPlayer {
+    pub name: String,
+    pub transform: Transform,
+    /// The ship the player was piloting when the world was saved.
+    pub seat: Option<Entity>,
+}
+
+impl SavedPlayers {
+    pub fn insert(&mut self, identity: PlayerIdentity, player: SavedPlayer) {
+        self.map.insert(identity, player);
+    }
+
+    pub fn take(&mut self, identity: &PlayerIdentity) -> Option<SavedPlayer> {
+        self.map.remove(identity)
+    }
+}
+
+/// Writes saves on a separate thread, so that serializing and writing a large world does not
+/// hold up the tick.
+pub struct WorldSaver {
+    sender: Mutex<Sender<WorldSave>>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl WorldSaver {
+    fn spawn(path: PathBuf) -> Self {
+        let (sender, receiver) = mpsc::channel::<WorldSave>();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+
+        let thread_in_flight = in_flight.clone();
+        thread::Builder::new()
+            .name(String::from("world-saver"))
+            .spawn(move || {
+                for world in receiver {
+                    match world.write(&path) {
+                        Ok(()) => info!("Saved the world to {}", path.display()),
+                        Err(e) => warn!("Could not save the world to {}: {}", path.display(), e),
+                    }
+                    thread_in_flight.fetch_sub(1, Ordering::SeqCst);
+                }
+            })
+            .expect("Could not start the world saver");
+
+        Self {
+            sender: Mutex::new(sender),
+            in_flight,
+        }
+    }
+
+    pub fn save(&self, world: WorldSave) {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        if self.sender.lock().unwrap().send(world).is_err() {
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            warn!("The world saver is gone, the world was not saved");
+        }
+    }
+
+    /// Wait for all saves to be written.
+    pub fn flush(&self) {
+        while self.in_flight.load(Ordering::SeqCst) > 0 {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+/// Inserted once the saved world has been spawned.
+pub struct WorldLoaded;
+
+struct Autosave {
+    interval: Duration,
+    last_save: Instant,
+}
+
+pub struct WorldPlugin;
+
+impl Plugin for WorldPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        let settings = app.world.resource::<ServerSettings>().clone();
+
+        app.init_resource::<SavedPlayers>()
+            .insert_resource(WorldSaver::spawn(settings.world.clone()))
+            .add_system(
+                load_world
+                    .run_if(block_definitions_loaded)
+                    .run_unless_resource_exists::<WorldLoaded>(),
+            )
+            // Saving before the world is loaded would overwrite it with an empty one
+            .add_system(
+                save_world
+                    .run_on_event::<SaveWorldRequest>()
+                    .run_if_resource_exists::<WorldLoaded>(),
+            )
+            .add_system_to_stage(CoreStage::Last, flush_on_exit);
+
+        if settings.autosave_interval > 0. {
+            app.insert_resource(Autosave {
+                interval: Duration::from_secs_f32(settings.autosave_interval),
+                last_save: Instant::now(),
+            })
+            .add_system(autosave);
+        }
+    }
+
+    fn name(&self) -> &str {
+        "world_plugin"
+    }
+}
+
+/// Runs once the block definitions are loaded, as blocks can't be spawned without them.
+fn load_world(
+    mut commands: Commands,
+    settings: Res<ServerSettings>,
+    definitions: Res<BlockDefinitions>,
+    mut saved_players: ResMut<SavedPlayers>,
+    mut factions: ResMut<Factions>,
+    mut save_requests: EventWriter<SaveWorldRequest>,
+) {
+    commands.insert_resource(WorldLoaded);
+
+    let mut world = WorldSave::load(&settings.world).unwrap_or_else(|e| {
+        panic!(
+            "Could not load the world from {}: {}",
+            settings.world.display(),
+            e
+        )
+    });
+
+    if let Some(saved) = world.as_mut().map(|world| mem::take(&mut world.factions)) {
+        *factions = saved
+            .into_iter()
+            .map(|(name, faction)| (name, faction.into()))
+            .collect();
+    }
+    if import_legacy_factions(&settings.factions, &mut factions) {
+        save_requests.send(SaveWorldRequest);
+    }
+
+    let world = match world {
+        Some(world) => world,
+        None => {
+            info!("Creating a new world at {}", settings.world.display());
+            let blueprint = Blueprint {
+                name: String::from("Starter"),
+                blocks: vec![BlueprintBlock {
+                    block_type: BlockType::HULL,
+                    position: BlockPosition::splat(0),
+                    rotation: BlockRotation::default(),
+                }],
+            };
+            spawn_ship(
+                &mut commands,
+                &definitions,
+                &blueprint,
+                Transform::default(),
+                Owner::None,
+            );
+            return;
+        }
+    };
+
+    for player in &world.players {
+        saved_players.insert(
+            player.identity.0,
+            SavedPlayer {
+                name: player.name.clone(),
+                transform: player.transform,
+                seat: None,
+            },
+        );
+    }
+
+    let ship_count = world.ships.len();
+    for ship in world.ships {
+        let blueprint = Blueprint {
+            name: ship.name,
+            blocks: ship.blocks,
+        };
+        let (ship_entity, _) = spawn_ship(
+            &mut commands,
+            &definitions,
+            &blueprint,
+            ship.transform,
+            ship.owner.into(),
+        );
+        commands
+            .entity(ship_entity)
+            .insert(ship.velocity)
+            .insert(SharedRights {
+                builders: ship.builders.iter().map(|identity| identity.0).collect(),
+                pilots: ship.pilots.iter().map(|identity| identity.0).collect(),
+            });
+
+        if let Some(pilot) = ship.pilot {
+            if let Some(player) = saved_players.map.get_mut(&pilot.0) {
+                player.seat = Some(ship_entity);
+            }
+        }
+    }
+
+    info!(
+        "Loaded {} ships and {} players from {}",
+        ship_count,
+        world.players.len(),
+        settings.world.display()
+    );
+}
+
+/// Factions used to be kept in a file of their own, so move the ones found there into the world.
+///
+/// The file is renamed rather than removed, as the world holding its factions is only written by
+/// the next save. Returns whether any factions were imported.
+fn import_legacy_factions(path: &Path, factions: &mut Factions) -> bool {
+    let legacy = Factions::load_legacy(path)
+        .unwrap_or_else(|e| panic!("Could not load factions from {}: {}", path.display(), e));
+    let legacy = match legacy {
+        Some(legacy) => legacy,
+        None => return false,
+    };
+
+    let mut migrated_path = OsString::from(path);
+    migrated_path.push(".migrated");
+    let migrated_path = PathBuf::from(migrated_path);
+
+    let imported = factions.is_empty();
+    if imported {
+        *factions = legacy;
+        info!(
+            "Moved the factions from {} into the world, keeping the old file as {}",
+            path.display(),
+            migrated_path.display()
+        );
+    } else {
+        warn!(
+            "The world already has factions, ignoring the ones in {} and keeping it as {}",
+            path.display(),
+            migrated_path.display()
+        );
+    }
+    if let Err(e) = fs::rename(path, &migrated_path) {
+        warn!("Could not rename {}: {}", path.display(), e);
+    }
+    imported
+}
+
+fn save_world(
+    mut save_requests: EventReader<SaveWorldRequest>,
+    saver: Res<WorldSaver>,
+    saved_players: Res<SavedPlayers>,
+    factions: Res<Factions>,
+    ship_query: Query<
+        (
+            Entity,
+            &ShipName,
+            &BlockMap,
+            &Transform,
+            &Velocity,
+            &Pilot,
+            &Owner,
+            &SharedRights,
+        ),
+        With<Ship>,
+    >,
+    player_query: Query<(&PlayerIdentity, &PlayerClientId, &Name, &Transform), With<PlayerMarker>>,
+) {
+    // Several requests in the same tick would all save the same world
+    if save_requests.iter().last().is_none() {
+        return;
+    }
+
+    let ships = ship_query
+        .iter()
+        .map(
+            |(
+                ship_entity,
+                ship_name,
+                block_map,
+                transform,
+                velocity,
+                pilot,
+                owner,
+                shared_rights,
+            )| {
+                let pilot = match pilot {
+                    Pilot::Pilot(pilot_id) => player_query
+                        .iter()
+                        .find(|(_, client_id, _, _)| client_id.0 == *pilot_id)
+                        .map(|(identity, _, _, _)| SavedIdentity(*identity)),
+                    // Seats loaded with the world stay empty until their player joins again
+                    Pilot::None => saved_players
+                        .map
+                        .iter()
+                        .find(|(_, player)| player.seat == Some(ship_entity))
+                        .map(|(identity, _)| SavedIdentity(*identity)),
+                };
+                ShipSave {
+                    name: ship_name.name.clone(),
+                    blocks: Blueprint::from_block_map(ship_name.name.clone(), block_map).blocks,
+                    transform: *transform,
+                    velocity: *velocity,
+                    owner: owner.into(),
+                    builders: shared_rights
+                        .builders
+                        .iter()
+                        .map(|i| SavedIdentity(*i))
+                        .collect(),
+                    pilots: shared_rights
+                        .pilots
+                        .iter()
+                        .map(|i| SavedIdentity(*i))
+                        .collect(),
+                    pilot,
+                }
+            },
+        )
+        .collect();
+
+    let mut players: Vec<PlayerSave> = player_query
+        .iter()
+        .map(|(identity, _, name, transform)| PlayerSave {
+            identity: SavedIdentity(*identity),
+            name: name.to_string(),
+            transform: *transform,
+        })
+        .collect();
+    // Players that have not joined since the world was loaded
+    players.extend(
+        saved_players
+            .map
+            .iter()
+            .map(|(identity, player)| PlayerSave {
+                identity: SavedIdentity(*identity),
+                name: player.name.clone(),
+                transform: player.transform,
+            }),
+    );
+
+    saver.save(WorldSave {
+        version: WORLD_VERSION,
+        ships,
+        players,
+        factions: factions
+            .iter()
+            .map(|(name, faction)| (name.to_string(), faction.into()))
+            .collect(),
+    });
+}
+
+fn autosave(mut autosave: ResMut<Autosave>, mut save_requests: EventWriter<SaveWorldRequest>) {
+    if autosave.last_save.elapsed() >= autosave.interval {
+        autosave.last_save = Instant::now();
+        save_requests.send(SaveWorldRequest);
+    }
+}
+
+/// Make sure the last save made it to disk before the app exits.
+fn flush_on_exit(state: Res<ShutdownState>, saver: Res<WorldSaver>) {
+    if *state == ShutdownState::Exiting {
+        saver.flush();
+    }
+}
@@ -0,0 +1,245 @@
+use bevy::prelude::{
+    BuildChildren, Bundle, Commands, Component, CoreStage, DespawnRecursiveExt, Entity,
+    ParallelSystemDescriptorCoercion, Plugin, Quat, Query, Res, SystemLabel, TransformBundle, Vec3,
+};
+use bevy::utils::{HashMap, HashSet};
+use bevy_rapier3d::prelude::{Collider, ColliderMassProperties};
+
+use crate::model::{
+    block_definitions::BlockDefinitions,
+    block_map::{BlockBounds, BlockMap, BlockPosition},
+    chunk::{ChunkPosition, CHUNK_SIZE},
+};
+
+/// How far into a block a ray hit is looked up, so it lands inside the block that was hit rather
+/// than on the face it shares with the next one.
+const HIT_DEPTH: f32 = 0.01;
+
+/// Gives ships one compound collider per chunk instead of a collider per block, on both the
+/// server and the client.
+pub struct ChunkColliderPlugin;
+
+impl Plugin for ChunkColliderPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        // After the update stage, so no block edit slips in between rebuilding and clearing
+        app.add_system_to_stage(
+            CoreStage::PostUpdate,
+            update_chunk_colliders.label(ChunkLabels::Rebuild),
+        )
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            clear_dirty_chunks.after(ChunkLabels::Rebuild),
+        );
+    }
+
+    fn name(&self) -> &str {
+        "chunk_collider_plugin"
+    }
+}
+
+/// Systems that rebuild something from the dirty chunks of block maps, which are cleared once
+/// all of them ran.
+#[derive(SystemLabel)]
+pub enum ChunkLabels {
+    Rebuild,
+}
+
+/// The collider of one chunk of a ship.
+///
+/// A ray hitting a compound collider doesn't tell which of its shapes it hit, so this keeps the
+/// blocks covered by each shape to find the block from where it was hit.
+#[derive(Component, Debug)]
+pub struct ChunkCollider {
+    pub chunk_position: ChunkPosition,
+    shapes: Vec<BlockBounds>,
+}
+
+impl ChunkCollider {
+    /// The block hit at `point` on its side facing `normal`, both in the frame of the ship.
+    pub fn block_at(&self, point: Vec3, normal: Vec3) -> Option<BlockPosition> {
+        let point = point - normal * HIT_DEPTH;
+        let bounds = self.shapes.iter().find(|bounds| {
+            let min = Vec3::from(bounds.min) - Vec3::splat(0.5 + HIT_DEPTH);
+            let max = Vec3::from(bounds.max) + Vec3::splat(0.5 + HIT_DEPTH);
+            point.cmpge(min).all() && point.cmple(max).all()
+        })?;
+
+        // Hits on the edge of a shape may round to the block next to it
+        let position = BlockPosition::rounded(point);
+        Some(BlockPosition::new(
+            position.x.clamp(bounds.min.x, bounds.max.x),
+            position.y.clamp(bounds.min.y, bounds.max.y),
+            position.z.clamp(bounds.min.z, bounds.max.z),
+        ))
+    }
+}
+
+/// The entities holding the colliders of the chunks of a ship.
+#[derive(Component, Default, Debug)]
+pub struct ChunkColliders(HashMap<ChunkPosition, Entity>);
+
+#[derive(Bundle)]
+struct ChunkColliderBundle {
+    chunk_collider: ChunkCollider,
+    collider: Collider,
+    // Blocks weigh nothing on their own, the ship they are part of carries their mass
+    mass_properties: ColliderMassProperties,
+    #[bundle]
+    transform_bundle: TransformBundle,
+}
+
+/// Build the collider of a chunk of `block_map`, relative to the ship, `None` if it is empty.
+///
+/// Full cubes are merged into as few boxes as possible, each grown along x, then y, then z, while
+/// other blocks keep the shape from their definition.
+pub fn chunk_collider(
+    definitions: &BlockDefinitions,
+    block_map: &BlockMap,
+    chunk_position: ChunkPosition,
+) -> Option<(Collider, ChunkCollider)> {
+    let chunk = block_map.chunk(&chunk_position)?;
+    let origin = chunk_position.origin();
+    let size = CHUNK_SIZE as usize;
+    let index = |x: usize, y: usize, z: usize| x + y * size + z * size * size;
+
+    let mut shapes = Vec::new();
+    let mut bounds = Vec::new();
+    let mut solid = vec![false; size * size * size];
+    for (local, block) in chunk.blocks() {
+        if definitions.is_full_cube(block.block_type) {
+            solid[index(local.x as usize, local.y as usize, local.z as usize)] = true;
+            continue;
+        }
+        let position =
+            BlockPosition::new(origin.x + local.x, origin.y + local.y, origin.z + local.z);
+        shapes.push((
+            Vec3::from(position),
+            Quat::from(block.block_rotation),
+            definitions.collider(block.block_type),
+        ));
+        bounds.push(BlockBounds {
+            min: position,
+            max: position,
+        });
+    }
+
+    for z in 0..size {
+        for y in 0..size {
+            for x in 0..size {
+                if !solid[index(x, y, z)] {
+                    continue;
+                }
+
+                let mut width = 1;
+                while x + width < size && solid[index(x + width, y, z)] {
+                    width += 1;
+                }
+                let mut height = 1;
+                while y + height < size && (x..x + width).all(|x| solid[index(x, y + height, z)]) {
+                    height += 1;
+                }
+                let mut depth = 1;
+                while z + depth < size
+                    && (y..y + height)
+                        .all(|y| (x..x + width).all(|x| solid[index(x, y, z + depth)]))
+                {
+                    depth += 1;
+                }
+                for z in z..z + depth {
+                    for y in y..y + height {
+                        solid[index(x, y, z)..index(x + width, y, z)].fill(false);
+                    }
+                }
+
+                let min = BlockPosition::new(
+                    origin.x + x as i32,
+                    origin.y + y as i32,
+                    origin.z + z as i32,
+                );
+                let max = BlockPosition::new(
+                    min.x + width as i32 - 1,
+                    min.y + height as i32 - 1,
+                    min.z + depth as i32 - 1,
+                );
+                let half_extents = Vec3::new(width as f32, height as f32, depth as f32) / 2.;
+                shapes.push((
+                    (Vec3::from(min) + Vec3::from(max)) / 2.,
+                    Quat::IDENTITY,
+                    Collider::cuboid(half_extents.x, half_extents.y, half_extents.z),
+                ));
+                bounds.push(BlockBounds { min, max });
+            }
+        }
+    }
+
+    if shapes.is_empty() {
+        return None;
+    }
+    Some((
+        Collider::compound(shapes),
+        ChunkCollider {
+            chunk_position,
+            shapes: bounds,
+        },
+    ))
+}
+
+/// Rebuild the colliders of the chunks that changed, or of every chunk when the block
+/// definitions were reloaded.
+fn update_chunk_colliders(
+    mut commands: Commands,
+    definitions: Res<BlockDefinitions>,
+    mut ships: Query<(Entity, &BlockMap, Option<&mut ChunkColliders>)>,
+) {
+    for (ship_entity, block_map, chunk_colliders) in ships.iter_mut() {
+        let mut inserted = None;
+        let chunk_colliders = match chunk_colliders {
+            Some(chunk_colliders) => chunk_colliders.into_inner(),
+            None => inserted.insert(ChunkColliders::default()),
+        };
+
+        let dirty: HashSet<ChunkPosition> = if definitions.is_changed() {
+            block_map
+                .chunks()
+                .map(|(position, _)| position)
+                .chain(chunk_colliders.0.keys().copied())
+                .collect()
+        } else {
+            block_map.dirty_chunks().collect()
+        };
+
+        for chunk_position in dirty {
+            if let Some(entity) = chunk_colliders.0.remove(&chunk_position) {
+                commands.entity(entity).despawn_recursive();
+            }
+
+            if let Some((collider, chunk_collider)) =
+                chunk_collider(&definitions, block_map, chunk_position)
+            {
+                let entity = commands
+                    .spawn_bundle(ChunkColliderBundle {
+                        chunk_collider,
+                        collider,
+                        mass_properties: ColliderMassProperties::Density(0.),
+                        transform_bundle: TransformBundle::default(),
+                    })
+                    .id();
+                commands.entity(ship_entity).add_child(entity);
+                chunk_colliders.0.insert(chunk_position, entity);
+            }
+        }
+
+        if let Some(chunk_colliders) = inserted {
+            commands.entity(ship_entity).insert(chunk_colliders);
+        }
+    }
+}
+
+fn clear_dirty_chunks(mut block_maps: Query<&mut BlockMap>) {
+    for mut block_map in block_maps.iter_mut() {
+        // Only touch block maps that changed, so change detection keeps working
+        if block_map.dirty_chunks().next().is_some() {
+            block_map.take_dirty_chunks();
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+use spacegame_proc_macros::{bidirectional, client_bound};
+
+/// Longest chat message the server accepts, in characters.
+pub const MAX_CHAT_MESSAGE_LENGTH: usize = 256;
+
+/// A chat message written by a player.
+///
+/// Clients send it with an empty `sender_name`, the server fills it in before passing the message
+/// on to everyone.
+#[bidirectional]
+#[derive(Serialize, Deserialize)]
+pub struct ChatMessageEvent {
+    pub sender_name: String,
+    pub message: String,
+}
+
+/// A message from the server, such as an announcement or the output of a command.
+#[client_bound]
+#[derive(Serialize, Deserialize)]
+pub struct AnnouncementEvent {
+    pub message: String,
+}
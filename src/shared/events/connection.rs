@@ -0,0 +1,50 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+use spacegame_proc_macros::client_bound;
+
+/// Why the server ended a connection.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DisconnectReason {
+    Kicked,
+    Banned,
+    NotWhitelisted,
+    /// The server requires a password and the client sent none or a wrong one.
+    WrongPassword,
+    /// The client speaks another version of the protocol than the server.
+    ProtocolMismatch,
+    ServerShuttingDown,
+    Timeout,
+}
+
+impl DisconnectReason {
+    /// Whether it makes sense for the client to try connecting again right away.
+    pub fn should_reconnect(&self) -> bool {
+        matches!(self, DisconnectReason::Timeout)
+    }
+}
+
+impl Display for DisconnectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DisconnectReason::Kicked => write!(f, "kicked from the server"),
+            DisconnectReason::Banned => write!(f, "banned from the server"),
+            DisconnectReason::NotWhitelisted => write!(f, "not on the server's whitelist"),
+            DisconnectReason::WrongPassword => write!(f, "wrong server password"),
+            DisconnectReason::ProtocolMismatch => {
+                write!(f, "client and server versions do not match")
+            }
+            DisconnectReason::ServerShuttingDown => write!(f, "the server is shutting down"),
+            DisconnectReason::Timeout => write!(f, "connection timed out"),
+        }
+    }
+}
+
+/// Sent right before the server drops the connection of the receiving client.
+#[client_bound]
+#[derive(Serialize, Deserialize)]
+pub struct DisconnectNoticeEvent {
+    pub reason: DisconnectReason,
+    /// Extra explanation, such as the reason given by the admin who kicked the player.
+    pub message: Option<String>,
+}
@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+use spacegame_proc_macros::client_bound;
+
+use crate::model::faction::FactionInfo;
+
+/// All factions on the server, sent whenever one of them changes.
+#[client_bound]
+#[derive(Serialize, Deserialize)]
+pub struct FactionSyncEvent {
+    pub factions: Vec<FactionInfo>,
+}
@@ -1,3 +1,6 @@
+pub mod chat;
+pub mod connection;
+pub mod faction;
 pub mod generic;
 pub mod player;
 pub mod ship;
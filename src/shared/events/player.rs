@@ -38,5 +38,7 @@ pub struct PlayerDespawnEvent {
 #[derive(Serialize, Deserialize)]
 pub struct PlayerReadyEvent {
     pub own_client_it: ClientId,
+    /// The name the server accepted for this player.
+    pub player_name: String,
     pub players_online_count: i16,
 }
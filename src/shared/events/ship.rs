@@ -1,7 +1,7 @@
 use std::fmt::Debug;
 
-use bevy::prelude::{Entity, Transform};
-use bevy_rapier3d::prelude::{ExternalForce, Velocity};
+use bevy::prelude::{Entity, Transform, Vec3};
+use bevy_rapier3d::prelude::Velocity;
 use serde::{Deserialize, Serialize};
 use spacegame_core::message::ClientId;
 use spacegame_proc_macros::{bidirectional, client_bound, server_bound};
@@ -10,8 +10,9 @@ use crate::{
     model::{
         block::BlockType,
         block_map::{BlockMap, BlockPosition, BlockRotation},
+        ship::ShipOwnership,
     },
-    shared::remote_refs::{ExternalForceDef, TransformDef, VelocityDef},
+    shared::remote_refs::{TransformDef, VelocityDef},
 };
 
 #[client_bound]
@@ -60,6 +61,16 @@ pub struct LoadShipEvent {
     #[serde(with = "VelocityDef")]
     pub velocity: Velocity,
     pub name: String,
+    pub ownership: ShipOwnership,
+}
+
+#[client_bound]
+#[derive(Serialize, Deserialize)]
+pub struct ShipOwnershipEvent {
+    #[entity]
+    #[missing = "drop"]
+    pub ship_entity: Entity,
+    pub ownership: ShipOwnership,
 }
 
 #[client_bound]
@@ -130,6 +141,8 @@ pub struct ShipMoveEvent {
     #[entity]
     #[missing = "drop"]
     pub ship_entity: Entity,
-    #[serde(with = "ExternalForceDef")]
-    pub force: ExternalForce,
+    /// How hard to push along each of the ship's axes, from -1 to 1.
+    pub throttle: Vec3,
+    /// How hard to turn around each of the ship's axes, from -1 to 1.
+    pub steering: Vec3,
 }
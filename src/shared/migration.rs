@@ -0,0 +1,75 @@
+use std::fmt::Display;
+
+use serde::{de::DeserializeOwned, Deserialize};
+
+/// A file format that carries a schema version, so that files written by older versions of the
+/// game can still be read.
+pub trait Versioned: DeserializeOwned {
+    /// The version files are written with.
+    const VERSION: u32;
+
+    /// Parse `contents` written with an older `version` and upgrade it one version at a time.
+    ///
+    /// Implementations keep the types of every older version around and convert each of them
+    /// into the next one, so that adding a version only needs a single new step.
+    fn migrate(version: u32, contents: &str) -> Result<Self, MigrationError>;
+}
+
+#[derive(Debug)]
+pub enum MigrationError {
+    Parse(ron::Error),
+    /// The file was written with a version there is no migration for, usually by a newer game.
+    UnsupportedVersion {
+        found: u32,
+        supported: u32,
+    },
+}
+
+impl Display for MigrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationError::Parse(e) => write!(f, "{}", e),
+            MigrationError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "version {} is not supported, only up to {}",
+                found, supported
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<ron::Error> for MigrationError {
+    fn from(e: ron::Error) -> Self {
+        MigrationError::Parse(e)
+    }
+}
+
+/// Just the version of a file, ignoring everything else in it.
+#[derive(Deserialize)]
+struct SchemaVersion {
+    /// Files written before they were versioned count as version 0.
+    #[serde(default)]
+    version: u32,
+}
+
+/// Parse `contents` in the current version of `T`, migrating it first if it is older.
+pub fn load_versioned<T: Versioned>(contents: &str) -> Result<T, MigrationError> {
+    let version = ron::from_str::<SchemaVersion>(contents)?.version;
+    if version == T::VERSION {
+        Ok(ron::from_str(contents)?)
+    } else if version < T::VERSION {
+        T::migrate(version, contents)
+    } else {
+        Err(unsupported_version::<T>(version))
+    }
+}
+
+/// The error for a version `T` has no migration for.
+pub fn unsupported_version<T: Versioned>(version: u32) -> MigrationError {
+    MigrationError::UnsupportedVersion {
+        found: version,
+        supported: T::VERSION,
+    }
+}
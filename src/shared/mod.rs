@@ -1,6 +1,9 @@
 pub mod binding;
+pub mod chunk_collider;
+pub mod config;
 pub mod entities;
 pub mod events;
+pub mod migration;
 pub mod model;
 pub mod networking;
 pub mod remote_refs;
@@ -1,12 +1,14 @@
+use std::fmt::Display;
+
 use bevy::prelude::{default, Bundle, Component, PbrBundle, Transform};
-use bevy_rapier3d::geometry::Collider;
-use bevy_rapier3d::prelude::{Ccd, Sleeping};
 use serde::{Deserialize, Serialize};
 
 use crate::model::block_map::BlockPosition;
 
 use super::block_map::BlockRotation;
 
+/// A block of a ship. Its collider is part of the collider of its chunk, see
+/// [`ChunkColliderPlugin`](crate::chunk_collider::ChunkColliderPlugin).
 #[derive(Bundle)]
 pub struct BlockBundle {
     // The type of this block
@@ -16,9 +18,6 @@ pub struct BlockBundle {
     pub block_rotation: BlockRotation,
     #[bundle]
     pub pbr_bundle: PbrBundle,
-    pub collider: Collider,
-    pub sleeping: Sleeping,
-    pub ccd: Ccd,
 }
 
 impl BlockBundle {
@@ -39,28 +38,6 @@ impl BlockBundle {
                 },
                 ..default()
             },
-            ..default()
-        }
-    }
-
-    pub fn with_type(
-        block_type: BlockType,
-        block_position: BlockPosition,
-        block_rotation: BlockRotation,
-    ) -> Self {
-        Self {
-            block_type,
-            block_position,
-            block_rotation,
-            pbr_bundle: PbrBundle {
-                transform: Transform {
-                    translation: block_position.into(),
-                    rotation: block_rotation.into(),
-                    ..default()
-                },
-                ..default()
-            },
-            ..default()
         }
     }
 }
@@ -68,18 +45,28 @@ impl BlockBundle {
 impl Default for BlockBundle {
     fn default() -> Self {
         Self {
-            block_type: BlockType::Hull,
+            block_type: BlockType::HULL,
             block_position: BlockPosition::default(),
             block_rotation: BlockRotation::default(),
             pbr_bundle: PbrBundle::default(),
-            collider: Collider::cuboid(0.5, 0.5, 0.5),
-            sleeping: Sleeping::disabled(),
-            ccd: Ccd::enabled(),
         }
     }
 }
 
+/// Stable id of a type of block, see [`BlockDefinitions`] for what the block is like.
 #[derive(Component, Eq, PartialEq, Hash, Clone, Copy, Serialize, Deserialize, Debug)]
-pub enum BlockType {
-    Hull,
+#[serde(transparent)]
+pub struct BlockType(pub u16);
+
+impl BlockType {
+    /// Empty space, which block maps store where there is no block. No block may use this id.
+    pub const EMPTY: BlockType = BlockType(0);
+    /// The plain hull block, which new ships are made of.
+    pub const HULL: BlockType = BlockType(1);
+}
+
+impl Display for BlockType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
 }
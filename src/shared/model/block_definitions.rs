@@ -0,0 +1,242 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::{
+        info, warn, AssetEvent, AssetServer, Assets, Commands, EventReader, Handle, Plugin, Res,
+        ResMut,
+    },
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashMap, HashSet},
+};
+use bevy_rapier3d::geometry::Collider;
+use serde::Deserialize;
+
+use super::block::BlockType;
+
+/// Where the block definitions are loaded from, relative to the assets directory.
+pub const BLOCK_DEFINITIONS_PATH: &str = "blocks/default.blocks.ron";
+
+/// Everything there is to know about a type of block, as written in the block definitions file.
+#[derive(Deserialize, Clone, Debug)]
+pub struct BlockDefinition {
+    /// Stable id that blocks are referred to by on the network and in saves. Never reuse the id
+    /// of a removed block, and never use 0, which is empty space.
+    pub id: BlockType,
+    pub name: String,
+    /// Mass in kilograms.
+    pub mass: f32,
+    pub health: f32,
+    pub collider: BlockCollider,
+    /// Texture the block is drawn with, relative to the assets directory.
+    pub texture: String,
+    #[serde(default)]
+    pub tags: Vec<BlockTag>,
+    #[serde(default)]
+    pub thruster: Option<ThrusterDefinition>,
+    #[serde(default)]
+    pub gyroscope: Option<GyroscopeDefinition>,
+}
+
+impl BlockDefinition {
+    pub fn has_tag(&self, tag: BlockTag) -> bool {
+        self.tags.contains(&tag)
+    }
+}
+
+/// Shape of the collider of a block, centered on the block.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum BlockCollider {
+    Cuboid { half_extents: (f32, f32, f32) },
+    Ball { radius: f32 },
+}
+
+impl BlockCollider {
+    pub fn collider(&self) -> Collider {
+        match *self {
+            BlockCollider::Cuboid {
+                half_extents: (x, y, z),
+            } => Collider::cuboid(x, y, z),
+            BlockCollider::Ball { radius } => Collider::ball(radius),
+        }
+    }
+
+    /// Whether the block fills its whole cell, hiding the faces of the blocks next to it.
+    pub fn is_full_cube(&self) -> bool {
+        matches!(
+            *self,
+            BlockCollider::Cuboid {
+                half_extents: (x, y, z),
+            } if x == 0.5 && y == 0.5 && z == 0.5
+        )
+    }
+}
+
+impl Default for BlockCollider {
+    fn default() -> Self {
+        BlockCollider::Cuboid {
+            half_extents: (0.5, 0.5, 0.5),
+        }
+    }
+}
+
+/// What a block does besides taking up space.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockTag {
+    /// Makes up the frame of a ship.
+    Structure,
+    /// Merges its ship with another one when it is lined up face to face with a merge block of
+    /// that ship.
+    Merge,
+}
+
+/// A block that pushes its ship towards its front, the -Z side of the block before it is rotated.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct ThrusterDefinition {
+    /// Force at full throttle, in newtons.
+    pub force: f32,
+}
+
+/// A block that lets its ship turn. The torque of all gyroscopes on a ship adds up.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct GyroscopeDefinition {
+    /// Torque around any axis, in newton meters.
+    pub torque: f32,
+}
+
+/// The contents of a block definitions file.
+#[derive(Deserialize, TypeUuid, Debug)]
+#[uuid = "2f0fd7a4-6a3e-4f43-9d36-8f5d2c7a1b0e"]
+pub struct BlockDefinitionsAsset {
+    pub blocks: Vec<BlockDefinition>,
+}
+
+#[derive(Default)]
+struct BlockDefinitionsLoader;
+
+impl AssetLoader for BlockDefinitionsLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let asset: BlockDefinitionsAsset = ron::de::from_bytes(bytes)?;
+
+            let mut ids = HashSet::new();
+            for definition in &asset.blocks {
+                if definition.id == BlockType::EMPTY {
+                    anyhow::bail!("block id 0 is reserved for empty space");
+                }
+                if !ids.insert(definition.id) {
+                    anyhow::bail!("block id {} is used by more than one block", definition.id);
+                }
+            }
+
+            load_context.set_default_asset(LoadedAsset::new(asset));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["blocks.ron"]
+    }
+}
+
+/// Registry of all block types, filled in once the block definitions asset has loaded.
+#[derive(Default)]
+pub struct BlockDefinitions {
+    definitions: HashMap<BlockType, BlockDefinition>,
+}
+
+impl BlockDefinitions {
+    pub fn get(&self, block_type: BlockType) -> Option<&BlockDefinition> {
+        self.definitions.get(&block_type)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &BlockDefinition> {
+        self.definitions.values()
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        !self.definitions.is_empty()
+    }
+
+    /// The collider of a block type, or a full block if it is unknown.
+    pub fn collider(&self, block_type: BlockType) -> Collider {
+        self.get(block_type)
+            .map(|definition| definition.collider)
+            .unwrap_or_default()
+            .collider()
+    }
+
+    /// Whether blocks of this type fill their whole cell, unknown types don't.
+    pub fn is_full_cube(&self, block_type: BlockType) -> bool {
+        self.get(block_type)
+            .map_or(false, |definition| definition.collider.is_full_cube())
+    }
+}
+
+/// Run condition for systems that need the block definitions.
+pub fn block_definitions_loaded(definitions: Res<BlockDefinitions>) -> bool {
+    definitions.is_loaded()
+}
+
+struct BlockDefinitionsHandle(Handle<BlockDefinitionsAsset>);
+
+pub struct BlockDefinitionsPlugin;
+
+impl Plugin for BlockDefinitionsPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_asset::<BlockDefinitionsAsset>()
+            .init_asset_loader::<BlockDefinitionsLoader>()
+            .init_resource::<BlockDefinitions>()
+            .add_startup_system(load_block_definitions)
+            .add_system(update_block_definitions);
+    }
+
+    fn name(&self) -> &str {
+        "block_definitions_plugin"
+    }
+}
+
+fn load_block_definitions(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(BlockDefinitionsHandle(
+        asset_server.load(BLOCK_DEFINITIONS_PATH),
+    ));
+}
+
+/// Copy the definitions into the registry whenever the asset is (re)loaded.
+fn update_block_definitions(
+    handle: Option<Res<BlockDefinitionsHandle>>,
+    assets: Res<Assets<BlockDefinitionsAsset>>,
+    mut asset_events: EventReader<AssetEvent<BlockDefinitionsAsset>>,
+    mut definitions: ResMut<BlockDefinitions>,
+) {
+    let handle = match handle {
+        Some(handle) => handle,
+        None => return,
+    };
+
+    for event in asset_events.iter() {
+        match event {
+            AssetEvent::Created { handle: changed } | AssetEvent::Modified { handle: changed }
+                if *changed == handle.0 =>
+            {
+                let asset = match assets.get(changed) {
+                    Some(asset) => asset,
+                    None => {
+                        warn!("Block definitions are missing right after loading");
+                        continue;
+                    }
+                };
+                definitions.definitions = asset
+                    .blocks
+                    .iter()
+                    .map(|definition| (definition.id, definition.clone()))
+                    .collect();
+                info!("Loaded {} block definitions", definitions.definitions.len());
+            }
+            _ => {}
+        }
+    }
+}
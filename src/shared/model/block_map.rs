@@ -1,85 +1,333 @@
-use std::f32::consts::PI;
-
-use bevy::prelude::{Component, Entity, EulerRot, Quat, Transform, Vec3};
-use bevy::utils::hashbrown::hash_map::Iter;
-use bevy::utils::HashMap;
+use bevy::prelude::{Component, Entity, Mat3, Quat, Transform, Vec3};
+use bevy::utils::{HashMap, HashSet};
 use serde::{Deserialize, Serialize};
 
-use super::block::BlockType;
+use super::{
+    block::BlockType,
+    chunk::{BlockState, Chunk, ChunkPosition},
+};
 
-#[derive(Hash, PartialEq, Eq, Serialize, Deserialize, Debug, Clone, Copy)]
+/// A block that was replaced or removed, with the entity it was spawned as if it was.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub struct BlockMapEntry {
     pub block_type: BlockType,
     pub block_rotation: BlockRotation,
-    pub entity: Entity,
+    pub entity: Option<Entity>,
 }
 
-#[derive(Component, Serialize, Deserialize, Debug, Clone)]
-pub struct BlockMap {
-    map: HashMap<BlockPosition, BlockMapEntry>,
-    pub block_count: i32,
+/// Smallest box holding every block of a map, both corners included.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct BlockBounds {
+    pub min: BlockPosition,
+    pub max: BlockPosition,
 }
 
-impl BlockMap {
-    pub fn new() -> Self {
+impl BlockBounds {
+    pub fn point(position: BlockPosition) -> Self {
         Self {
-            map: HashMap::new(),
-            block_count: 0,
+            min: position,
+            max: position,
         }
     }
 
-    pub fn get(&self, position: &BlockPosition) -> Option<Entity> {
-        match self.map.get(position) {
-            Some(entry) => Some(entry.entity),
-            None => None,
+    pub fn including(self, position: BlockPosition) -> Self {
+        Self {
+            min: BlockPosition::new(
+                self.min.x.min(position.x),
+                self.min.y.min(position.y),
+                self.min.z.min(position.z),
+            ),
+            max: BlockPosition::new(
+                self.max.x.max(position.x),
+                self.max.y.max(position.y),
+                self.max.z.max(position.z),
+            ),
         }
     }
 
-    pub fn get_entry(&self, position: &BlockPosition) -> Option<&BlockMapEntry> {
-        self.map.get(position)
+    /// Whether `position` is on one of the sides of the box, so the box may shrink without it.
+    fn is_on_side(&self, position: BlockPosition) -> bool {
+        position.x == self.min.x
+            || position.y == self.min.y
+            || position.z == self.min.z
+            || position.x == self.max.x
+            || position.y == self.max.y
+            || position.z == self.max.z
     }
 
-    pub fn get_type(&self, position: &BlockPosition) -> Option<BlockType> {
-        match self.map.get(position) {
-            Some(entry) => Some(entry.block_type),
-            None => None,
-        }
+    pub fn contains(&self, position: BlockPosition) -> bool {
+        (self.min.x..=self.max.x).contains(&position.x)
+            && (self.min.y..=self.max.y).contains(&position.y)
+            && (self.min.z..=self.max.z).contains(&position.z)
+    }
+
+    /// Whether the two boxes overlap once grown by `margin` blocks on every side.
+    pub fn is_within(&self, other: &BlockBounds, margin: i32) -> bool {
+        self.min.x <= other.max.x + margin
+            && self.max.x >= other.min.x - margin
+            && self.min.y <= other.max.y + margin
+            && self.max.y >= other.min.y - margin
+            && self.min.z <= other.max.z + margin
+            && self.max.z >= other.min.z - margin
+    }
+}
+
+/// The blocks of a ship, stored in chunks of 16x16x16 blocks.
+///
+/// Only the blocks themselves are sent over the network, the entities they were spawned as stay
+/// with the side that spawned them. Chunks that changed are marked dirty until they are taken
+/// with [`BlockMap::take_dirty_chunks`], so meshes and colliders can be rebuilt per chunk.
+#[derive(Component, Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(from = "SerializedBlockMap", into = "SerializedBlockMap")]
+pub struct BlockMap {
+    chunks: HashMap<ChunkPosition, Chunk>,
+    entities: HashMap<BlockPosition, Entity>,
+    len: usize,
+    bounds: Option<BlockBounds>,
+    dirty: HashSet<ChunkPosition>,
+}
+
+impl BlockMap {
+    pub fn new() -> Self {
+        Self::default()
     }
 
+    /// How many blocks there are.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, position: &BlockPosition) -> Option<BlockState> {
+        self.chunks
+            .get(&ChunkPosition::of(*position))?
+            .get(ChunkPosition::local(*position))
+    }
+
+    pub fn contains(&self, position: &BlockPosition) -> bool {
+        self.get(position).is_some()
+    }
+
+    /// The entity the block at `position` was spawned as.
+    pub fn entity(&self, position: &BlockPosition) -> Option<Entity> {
+        self.entities.get(position).copied()
+    }
+
+    /// Place a block, returning the block it replaced. Placing empty space removes the block.
     pub fn set(
         &mut self,
-        entity: Entity,
-        block_type: BlockType,
         position: BlockPosition,
+        block_type: BlockType,
         block_rotation: BlockRotation,
     ) -> Option<BlockMapEntry> {
-        let opt_old_block_entry = self.map.insert(
-            position,
-            BlockMapEntry {
-                block_type,
-                block_rotation,
-                entity,
-            },
-        );
-        if opt_old_block_entry.is_none() {
-            self.block_count += 1;
+        if block_type == BlockType::EMPTY {
+            return self.remove(&position);
         }
-        opt_old_block_entry
+
+        let old = self
+            .chunks
+            .entry(ChunkPosition::of(position))
+            .or_default()
+            .set(
+                ChunkPosition::local(position),
+                Some(BlockState {
+                    block_type,
+                    block_rotation,
+                }),
+            );
+        if old.is_none() {
+            self.len += 1;
+        }
+        self.bounds = Some(match self.bounds {
+            Some(bounds) => bounds.including(position),
+            None => BlockBounds::point(position),
+        });
+        self.mark_dirty(position);
+
+        let entity = self.entities.remove(&position);
+        old.map(|old| BlockMapEntry {
+            block_type: old.block_type,
+            block_rotation: old.block_rotation,
+            entity,
+        })
+    }
+
+    /// Remember the entity the block at `position` was spawned as.
+    pub fn set_entity(&mut self, position: BlockPosition, entity: Entity) {
+        if self.contains(&position) {
+            self.entities.insert(position, entity);
+        }
+    }
+
+    /// Forget the entity the block at `position` was spawned as, keeping the block.
+    pub fn take_entity(&mut self, position: &BlockPosition) -> Option<Entity> {
+        self.entities.remove(position)
+    }
+
+    pub fn remove(&mut self, position: &BlockPosition) -> Option<BlockMapEntry> {
+        let chunk_position = ChunkPosition::of(*position);
+        let chunk = self.chunks.get_mut(&chunk_position)?;
+        let old = chunk.set(ChunkPosition::local(*position), None)?;
+        if chunk.is_empty() {
+            self.chunks.remove(&chunk_position);
+        }
+
+        self.len -= 1;
+        if self
+            .bounds
+            .map_or(false, |bounds| bounds.is_on_side(*position))
+        {
+            self.bounds = self.compute_bounds();
+        }
+        self.mark_dirty(*position);
+
+        Some(BlockMapEntry {
+            block_type: old.block_type,
+            block_rotation: old.block_rotation,
+            entity: self.entities.remove(position),
+        })
+    }
+
+    /// Every block, chunk by chunk.
+    pub fn entries(&self) -> impl Iterator<Item = (BlockPosition, BlockState)> + '_ {
+        self.chunks.iter().flat_map(|(chunk_position, chunk)| {
+            let origin = chunk_position.origin();
+            chunk.blocks().map(move |(local, block)| {
+                (
+                    BlockPosition::new(origin.x + local.x, origin.y + local.y, origin.z + local.z),
+                    block,
+                )
+            })
+        })
+    }
+
+    /// Every block that was spawned as an entity.
+    pub fn entities(&self) -> impl Iterator<Item = (BlockPosition, Entity)> + '_ {
+        self.entities
+            .iter()
+            .map(|(position, entity)| (*position, *entity))
+    }
+
+    /// The blocks touching the block at `position`, by the face of that block they touch.
+    pub fn neighbours(
+        &self,
+        position: BlockPosition,
+    ) -> impl Iterator<Item = (BlockFace, BlockState)> + '_ {
+        BlockFace::ALL
+            .into_iter()
+            .filter_map(move |face| Some((face, self.get(&position.neighbour(face))?)))
+    }
+
+    pub fn chunk(&self, position: &ChunkPosition) -> Option<&Chunk> {
+        self.chunks.get(position)
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = (ChunkPosition, &Chunk)> + '_ {
+        self.chunks
+            .iter()
+            .map(|(position, chunk)| (*position, chunk))
+    }
+
+    /// Smallest box holding every block, `None` if there are no blocks.
+    pub fn bounds(&self) -> Option<BlockBounds> {
+        self.bounds
+    }
+
+    /// Chunks that changed since they were last taken, including chunks that are now empty.
+    pub fn dirty_chunks(&self) -> impl Iterator<Item = ChunkPosition> + '_ {
+        self.dirty.iter().copied()
+    }
+
+    pub fn take_dirty_chunks(&mut self) -> HashSet<ChunkPosition> {
+        std::mem::take(&mut self.dirty)
+    }
+
+    /// Mark the chunk of `position` as changed, and the chunks next to it if the block is on
+    /// their border, as their faces touching it may have to change too.
+    fn mark_dirty(&mut self, position: BlockPosition) {
+        let chunk_position = ChunkPosition::of(position);
+        self.dirty.insert(chunk_position);
+        for face in BlockFace::ALL {
+            let neighbour = ChunkPosition::of(position.neighbour(face));
+            if neighbour != chunk_position {
+                self.dirty.insert(neighbour);
+            }
+        }
+    }
+
+    fn compute_bounds(&self) -> Option<BlockBounds> {
+        self.entries()
+            .map(|(position, _)| position)
+            .fold(None, |bounds, position| {
+                Some(match bounds {
+                    Some(bounds) => bounds.including(position),
+                    None => BlockBounds::point(position),
+                })
+            })
     }
 
-    pub fn remove(&mut self, position: &BlockPosition) -> Option<Entity> {
-        let opt_old_block_entry = self.map.remove(position);
-        if opt_old_block_entry.is_some() {
-            self.block_count -= 1;
+    /// The groups of blocks that hold together through their faces, biggest first.
+    pub fn connected_parts(&self) -> Vec<Vec<BlockPosition>> {
+        let mut visited = HashSet::new();
+        let mut parts = Vec::new();
+
+        for (start, _) in self.entries() {
+            if !visited.insert(start) {
+                continue;
+            }
+
+            // Flood fill from any block not in a part yet
+            let mut part = Vec::new();
+            let mut stack = vec![start];
+            while let Some(position) = stack.pop() {
+                part.push(position);
+                for (face, _) in self.neighbours(position) {
+                    let neighbour = position.neighbour(face);
+                    if visited.insert(neighbour) {
+                        stack.push(neighbour);
+                    }
+                }
+            }
+            parts.push(part);
         }
-        match opt_old_block_entry {
-            Some(old_block_entry) => Some(old_block_entry.entity),
-            None => None,
+
+        parts.sort_by_key(|part| std::cmp::Reverse(part.len()));
+        parts
+    }
+}
+
+/// A block map as it is sent, just the chunks that have blocks in them.
+#[derive(Serialize, Deserialize)]
+struct SerializedBlockMap {
+    chunks: Vec<(ChunkPosition, Chunk)>,
+}
+
+impl From<BlockMap> for SerializedBlockMap {
+    fn from(block_map: BlockMap) -> Self {
+        Self {
+            chunks: block_map.chunks.into_iter().collect(),
         }
     }
+}
 
-    pub fn entries(&self) -> Iter<'_, BlockPosition, BlockMapEntry> {
-        self.map.iter()
+impl From<SerializedBlockMap> for BlockMap {
+    fn from(serialized: SerializedBlockMap) -> Self {
+        let mut block_map = BlockMap {
+            chunks: serialized
+                .chunks
+                .into_iter()
+                .filter(|(_, chunk)| !chunk.is_empty())
+                .collect(),
+            ..Default::default()
+        };
+        block_map.len = block_map.chunks.values().map(Chunk::len).sum();
+        block_map.bounds = block_map.compute_bounds();
+        // Everything is new to whoever receives it
+        block_map.dirty = block_map.chunks.keys().copied().collect();
+        block_map
     }
 }
 
@@ -105,6 +353,12 @@ impl BlockPosition {
         Self { x: v, y: v, z: v }
     }
 
+    /// The position of the block touching this one on `face`.
+    pub fn neighbour(self, face: BlockFace) -> Self {
+        let normal = face.normal();
+        Self::new(self.x + normal.x, self.y + normal.y, self.z + normal.z)
+    }
+
     pub fn rounded(v: Vec3) -> Self {
         Self {
             x: v.x.round() as i32,
@@ -124,12 +378,12 @@ impl From<Vec3> for BlockPosition {
     }
 }
 
-impl Into<Vec3> for BlockPosition {
-    fn into(self) -> Vec3 {
+impl From<BlockPosition> for Vec3 {
+    fn from(position: BlockPosition) -> Self {
         Vec3 {
-            x: self.x as f32,
-            y: self.y as f32,
-            z: self.z as f32,
+            x: position.x as f32,
+            y: position.y as f32,
+            z: position.z as f32,
         }
     }
 }
@@ -140,52 +394,209 @@ impl Into<Transform> for BlockPosition {
     }
 }
 
+/// One of the six faces of a block, which is also the direction it faces.
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Serialize, Deserialize, Debug)]
+pub enum BlockFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+impl BlockFace {
+    pub const ALL: [BlockFace; 6] = [
+        BlockFace::PositiveX,
+        BlockFace::NegativeX,
+        BlockFace::PositiveY,
+        BlockFace::NegativeY,
+        BlockFace::PositiveZ,
+        BlockFace::NegativeZ,
+    ];
+
+    /// The offset to the neighbouring block on this side.
+    pub fn normal(self) -> BlockPosition {
+        match self {
+            BlockFace::PositiveX => BlockPosition::new(1, 0, 0),
+            BlockFace::NegativeX => BlockPosition::new(-1, 0, 0),
+            BlockFace::PositiveY => BlockPosition::new(0, 1, 0),
+            BlockFace::NegativeY => BlockPosition::new(0, -1, 0),
+            BlockFace::PositiveZ => BlockPosition::new(0, 0, 1),
+            BlockFace::NegativeZ => BlockPosition::new(0, 0, -1),
+        }
+    }
+
+    pub fn from_normal(normal: BlockPosition) -> Option<Self> {
+        Self::ALL.into_iter().find(|face| face.normal() == normal)
+    }
+
+    /// The face pointing closest to `direction`.
+    pub fn closest(direction: Vec3) -> Self {
+        Self::ALL
+            .into_iter()
+            .max_by(|a, b| a.dot(direction).total_cmp(&b.dot(direction)))
+            .unwrap()
+    }
+
+    pub fn opposite(self) -> Self {
+        match self {
+            BlockFace::PositiveX => BlockFace::NegativeX,
+            BlockFace::NegativeX => BlockFace::PositiveX,
+            BlockFace::PositiveY => BlockFace::NegativeY,
+            BlockFace::NegativeY => BlockFace::PositiveY,
+            BlockFace::PositiveZ => BlockFace::NegativeZ,
+            BlockFace::NegativeZ => BlockFace::PositiveZ,
+        }
+    }
+
+    /// The four faces next to this one, in the order of [`BlockFace::ALL`].
+    pub fn perpendicular(self) -> [BlockFace; 4] {
+        let mut faces = [self; 4];
+        let others = Self::ALL
+            .into_iter()
+            .filter(|face| *face != self && *face != self.opposite());
+        for (slot, face) in faces.iter_mut().zip(others) {
+            *slot = face;
+        }
+        faces
+    }
+
+    /// Where this face is in [`BlockFace::ALL`].
+    pub fn index(self) -> u8 {
+        Self::ALL.iter().position(|face| *face == self).unwrap() as u8
+    }
+
+    fn dot(self, direction: Vec3) -> f32 {
+        let normal: Vec3 = self.normal().into();
+        normal.dot(direction)
+    }
+}
+
+/// One of the 24 rotations that keep a block aligned to the grid.
+///
+/// It is stored as an index: four times the index of the face +X is turned towards, plus which of
+/// the faces perpendicular to that +Y is turned towards. That makes 0 the identity.
 #[derive(Component, Eq, PartialEq, Hash, Clone, Copy, Serialize, Deserialize, Debug)]
+#[serde(try_from = "u8", into = "u8")]
 pub struct BlockRotation(u8);
 
 impl BlockRotation {
-    fn round_quat_range(v: f32) -> u8 {
-        let u = (((v + PI) * 2.) / PI).round() as u8;
-        if u == 4 {
-            0
-        } else {
-            u
-        }
+    pub const IDENTITY: BlockRotation = BlockRotation(0);
+    pub const COUNT: u8 = 24;
+
+    pub fn all() -> impl Iterator<Item = BlockRotation> {
+        (0..Self::COUNT).map(BlockRotation)
+    }
+
+    pub fn index(self) -> u8 {
+        self.0
+    }
+
+    /// The rotation turning +X towards `x` and +Y towards `y`, if those are perpendicular.
+    pub fn from_axes(x: BlockFace, y: BlockFace) -> Option<Self> {
+        let turn = x.perpendicular().iter().position(|face| *face == y)?;
+        Some(Self(x.index() * 4 + turn as u8))
+    }
+
+    /// The face +X is turned towards.
+    pub fn x_axis(self) -> BlockFace {
+        BlockFace::ALL[(self.0 / 4) as usize]
+    }
+
+    /// The face +Y is turned towards.
+    pub fn y_axis(self) -> BlockFace {
+        self.x_axis().perpendicular()[(self.0 % 4) as usize]
+    }
+
+    /// The face +Z is turned towards, which follows from the other two as rotations keep the
+    /// axes right-handed.
+    pub fn z_axis(self) -> BlockFace {
+        let x = self.x_axis().normal();
+        let y = self.y_axis().normal();
+        BlockFace::from_normal(BlockPosition::new(
+            x.y * y.z - x.z * y.y,
+            x.z * y.x - x.x * y.z,
+            x.x * y.y - x.y * y.x,
+        ))
+        .unwrap()
     }
 
-    fn round_quat_tuple(tupl: (f32, f32, f32)) -> u8 {
-        Self::round_quat_range(tupl.0)
-            | (Self::round_quat_range(tupl.1) >> 2)
-            | (Self::round_quat_range(tupl.2) >> 4)
+    pub fn rotate_position(self, position: BlockPosition) -> BlockPosition {
+        let x = self.x_axis().normal();
+        let y = self.y_axis().normal();
+        let z = self.z_axis().normal();
+        BlockPosition::new(
+            x.x * position.x + y.x * position.y + z.x * position.z,
+            x.y * position.x + y.y * position.y + z.y * position.z,
+            x.z * position.x + y.z * position.y + z.z * position.z,
+        )
     }
 
-    fn round_to_angle(u: u8) -> f32 {
-        ((u as f32) - 2.) * PI / 2.
+    pub fn rotate_face(self, face: BlockFace) -> BlockFace {
+        BlockFace::from_normal(self.rotate_position(face.normal())).unwrap()
+    }
+
+    /// The rotation that does `other` first and then `self`, like multiplying quaternions.
+    pub fn compose(self, other: BlockRotation) -> BlockRotation {
+        Self::from_axes(
+            self.rotate_face(other.x_axis()),
+            self.rotate_face(other.y_axis()),
+        )
+        .unwrap()
+    }
+
+    pub fn inverse(self) -> BlockRotation {
+        Self::all()
+            .find(|rotation| rotation.compose(self) == Self::IDENTITY)
+            .unwrap()
     }
 }
 
 impl Default for BlockRotation {
     fn default() -> Self {
-        Quat::default().into()
+        Self::IDENTITY
+    }
+}
+
+impl TryFrom<u8> for BlockRotation {
+    type Error = &'static str;
+
+    fn try_from(index: u8) -> Result<Self, Self::Error> {
+        if index < Self::COUNT {
+            Ok(Self(index))
+        } else {
+            Err("block rotations go from 0 to 23")
+        }
+    }
+}
+
+impl From<BlockRotation> for u8 {
+    fn from(rotation: BlockRotation) -> Self {
+        rotation.0
     }
 }
 
 impl From<Quat> for BlockRotation {
-    fn from(v: Quat) -> Self {
-        Self(Self::round_quat_tuple(v.to_euler(EulerRot::XYZ)))
+    /// Snap to the closest of the 24 rotations.
+    fn from(quat: Quat) -> Self {
+        let x = BlockFace::closest(quat * Vec3::X);
+        let y_direction = quat * Vec3::Y;
+        let y = x
+            .perpendicular()
+            .into_iter()
+            .max_by(|a, b| a.dot(y_direction).total_cmp(&b.dot(y_direction)))
+            .unwrap();
+        Self::from_axes(x, y).unwrap()
     }
 }
 
 impl From<BlockRotation> for Quat {
-    fn from(r: BlockRotation) -> Self {
-        let x = r.0 & 0b11000000;
-        let y = (r.0 & 0b00110000) << 2;
-        let z = (r.0 & 0b00001100) << 4;
-        Quat::from_euler(
-            EulerRot::XYZ,
-            BlockRotation::round_to_angle(x),
-            BlockRotation::round_to_angle(y),
-            BlockRotation::round_to_angle(z),
-        )
+    fn from(rotation: BlockRotation) -> Self {
+        Quat::from_mat3(&Mat3::from_cols(
+            rotation.x_axis().normal().into(),
+            rotation.y_axis().normal().into(),
+            rotation.z_axis().normal().into(),
+        ))
     }
 }
@@ -0,0 +1,195 @@
+use std::{fmt::Display, fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::shared::migration::{load_versioned, unsupported_version, MigrationError, Versioned};
+
+use super::{
+    block::BlockType,
+    block_map::{BlockMap, BlockPosition, BlockRotation},
+};
+
+/// Version of the blueprint format, to be bumped whenever it changes.
+pub const BLUEPRINT_VERSION: u32 = 3;
+
+/// A ship design that can be spawned any number of times.
+///
+/// Unlike a [`BlockMap`] it only describes the blocks, without the entities that make them up.
+/// Blueprint files also carry a `version`, see [`BLUEPRINT_VERSION`].
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Blueprint {
+    pub name: String,
+    pub blocks: Vec<BlueprintBlock>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct BlueprintBlock {
+    pub block_type: BlockType,
+    pub position: BlockPosition,
+    #[serde(default)]
+    pub rotation: BlockRotation,
+}
+
+#[derive(Debug)]
+pub enum BlueprintError {
+    Io(io::Error),
+    Load(MigrationError),
+    /// Blueprint names may not contain path separators or dots.
+    InvalidName,
+}
+
+impl Display for BlueprintError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlueprintError::Io(e) => write!(f, "{}", e),
+            BlueprintError::Load(e) => write!(f, "{}", e),
+            BlueprintError::InvalidName => write!(f, "invalid blueprint name"),
+        }
+    }
+}
+
+impl std::error::Error for BlueprintError {}
+
+impl Blueprint {
+    pub fn from_block_map(name: String, block_map: &BlockMap) -> Self {
+        Self {
+            name,
+            blocks: block_map
+                .entries()
+                .map(|(position, entry)| BlueprintBlock {
+                    block_type: entry.block_type,
+                    position,
+                    rotation: entry.block_rotation,
+                })
+                .collect(),
+        }
+    }
+
+    /// Load the blueprint called `name` from the `directory` blueprints are kept in.
+    pub fn load(directory: &Path, name: &str) -> Result<Self, BlueprintError> {
+        let valid_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid_name {
+            return Err(BlueprintError::InvalidName);
+        }
+
+        let path = directory.join(name).with_extension("ron");
+        let contents = fs::read_to_string(path).map_err(BlueprintError::Io)?;
+        load_versioned(&contents).map_err(BlueprintError::Load)
+    }
+}
+
+impl Versioned for Blueprint {
+    const VERSION: u32 = BLUEPRINT_VERSION;
+
+    fn migrate(version: u32, contents: &str) -> Result<Self, MigrationError> {
+        match version {
+            // Blueprints from before versioning only lack the version field
+            0 | 1 => Ok(ron::from_str::<v2::Blueprint<v1::BlueprintBlock>>(contents)?.into()),
+            2 => Ok(ron::from_str::<v2::Blueprint>(contents)?.into()),
+            _ => Err(unsupported_version::<Self>(version)),
+        }
+    }
+}
+
+/// Version 1, where block types were the variants of an enum rather than ids.
+pub mod v1 {
+    use serde::Deserialize;
+
+    use crate::shared::model::{block::BlockType, block_map::BlockPosition};
+
+    use super::v2::PackedRotation;
+
+    #[derive(Deserialize)]
+    pub struct BlueprintBlock {
+        pub block_type: LegacyBlockType,
+        pub position: BlockPosition,
+        #[serde(default)]
+        pub rotation: PackedRotation,
+    }
+
+    #[derive(Deserialize)]
+    pub enum LegacyBlockType {
+        Hull,
+    }
+
+    impl From<LegacyBlockType> for BlockType {
+        fn from(block_type: LegacyBlockType) -> Self {
+            match block_type {
+                LegacyBlockType::Hull => BlockType::HULL,
+            }
+        }
+    }
+
+    impl From<BlueprintBlock> for super::v2::BlueprintBlock {
+        fn from(block: BlueprintBlock) -> Self {
+            Self {
+                block_type: block.block_type.into(),
+                position: block.position,
+                rotation: block.rotation,
+            }
+        }
+    }
+
+    impl From<BlueprintBlock> for super::BlueprintBlock {
+        fn from(block: BlueprintBlock) -> Self {
+            super::v2::BlueprintBlock::from(block).into()
+        }
+    }
+}
+
+/// Version 2, where rotations were packed euler angles rather than one of the 24 rotations.
+pub mod v2 {
+    use serde::Deserialize;
+
+    use crate::shared::model::{
+        block::BlockType,
+        block_map::{BlockPosition, BlockRotation},
+    };
+
+    #[derive(Deserialize)]
+    pub struct Blueprint<B = BlueprintBlock> {
+        pub name: String,
+        pub blocks: Vec<B>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct BlueprintBlock {
+        pub block_type: BlockType,
+        pub position: BlockPosition,
+        #[serde(default)]
+        pub rotation: PackedRotation,
+    }
+
+    #[derive(Deserialize, Default)]
+    pub struct PackedRotation(pub u8);
+
+    impl From<PackedRotation> for BlockRotation {
+        /// The packing lost all but the x angle, and unpacking ignored even that, so every block
+        /// was spawned unrotated. Keep them that way.
+        fn from(_: PackedRotation) -> Self {
+            BlockRotation::IDENTITY
+        }
+    }
+
+    impl From<BlueprintBlock> for super::BlueprintBlock {
+        fn from(block: BlueprintBlock) -> Self {
+            Self {
+                block_type: block.block_type,
+                position: block.position,
+                rotation: block.rotation.into(),
+            }
+        }
+    }
+
+    impl<B: Into<super::BlueprintBlock>> From<Blueprint<B>> for super::Blueprint {
+        fn from(blueprint: Blueprint<B>) -> Self {
+            Self {
+                name: blueprint.name,
+                blocks: blueprint.blocks.into_iter().map(Into::into).collect(),
+            }
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::fmt::Debug;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    block::BlockType,
+    block_map::{BlockPosition, BlockRotation},
+};
+
+/// Width of a chunk in blocks, along each axis.
+pub const CHUNK_SIZE: i32 = 16;
+
+const CHUNK_VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+/// Packed value of a spot without a block, [`BlockType::EMPTY`] in any rotation.
+const EMPTY: u32 = 0;
+
+/// Position of a chunk, counted in chunks. Chunk (0, 0, 0) holds blocks 0 to 15 along each axis.
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Serialize, Deserialize, Debug)]
+pub struct ChunkPosition {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl ChunkPosition {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    /// The chunk `position` is in.
+    pub fn of(position: BlockPosition) -> Self {
+        Self {
+            x: position.x.div_euclid(CHUNK_SIZE),
+            y: position.y.div_euclid(CHUNK_SIZE),
+            z: position.z.div_euclid(CHUNK_SIZE),
+        }
+    }
+
+    /// The block of the chunk with the lowest coordinates.
+    pub fn origin(self) -> BlockPosition {
+        BlockPosition::new(
+            self.x * CHUNK_SIZE,
+            self.y * CHUNK_SIZE,
+            self.z * CHUNK_SIZE,
+        )
+    }
+
+    /// Where `position` is inside its chunk, from 0 to 15 along each axis.
+    pub fn local(position: BlockPosition) -> BlockPosition {
+        BlockPosition::new(
+            position.x.rem_euclid(CHUNK_SIZE),
+            position.y.rem_euclid(CHUNK_SIZE),
+            position.z.rem_euclid(CHUNK_SIZE),
+        )
+    }
+}
+
+/// A block as it is stored, without the entity it may have been spawned as.
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Serialize, Deserialize, Debug)]
+pub struct BlockState {
+    pub block_type: BlockType,
+    pub block_rotation: BlockRotation,
+}
+
+impl BlockState {
+    fn pack(self) -> u32 {
+        (self.block_type.0 as u32) << 8 | self.block_rotation.index() as u32
+    }
+
+    fn unpack(packed: u32) -> Option<Self> {
+        let block_type = BlockType((packed >> 8) as u16);
+        if block_type == BlockType::EMPTY {
+            return None;
+        }
+        Some(Self {
+            block_type,
+            block_rotation: BlockRotation::try_from(packed as u8).ok()?,
+        })
+    }
+}
+
+/// A cube of [`CHUNK_SIZE`]³ blocks, each packed into the type and rotation of the block.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "PackedChunk", into = "PackedChunk")]
+pub struct Chunk {
+    blocks: Vec<u32>,
+    len: usize,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Self {
+            blocks: vec![EMPTY; CHUNK_VOLUME],
+            len: 0,
+        }
+    }
+
+    fn index(local: BlockPosition) -> usize {
+        (local.x + local.y * CHUNK_SIZE + local.z * CHUNK_SIZE * CHUNK_SIZE) as usize
+    }
+
+    fn local(index: usize) -> BlockPosition {
+        let index = index as i32;
+        BlockPosition::new(
+            index % CHUNK_SIZE,
+            index / CHUNK_SIZE % CHUNK_SIZE,
+            index / (CHUNK_SIZE * CHUNK_SIZE),
+        )
+    }
+
+    /// The block at `local`, which goes from 0 to 15 along each axis.
+    pub fn get(&self, local: BlockPosition) -> Option<BlockState> {
+        BlockState::unpack(self.blocks[Self::index(local)])
+    }
+
+    /// Put `block` at `local`, or clear it if it is `None`, returning what was there before.
+    pub fn set(&mut self, local: BlockPosition, block: Option<BlockState>) -> Option<BlockState> {
+        let block = block.filter(|block| block.block_type != BlockType::EMPTY);
+        let slot = &mut self.blocks[Self::index(local)];
+        let old = BlockState::unpack(*slot);
+        *slot = block.map_or(EMPTY, BlockState::pack);
+
+        match (old.is_some(), block.is_some()) {
+            (false, true) => self.len += 1,
+            (true, false) => self.len -= 1,
+            _ => {}
+        }
+        old
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Every block in the chunk, by its position inside the chunk.
+    pub fn blocks(&self) -> impl Iterator<Item = (BlockPosition, BlockState)> + '_ {
+        self.blocks
+            .iter()
+            .enumerate()
+            .filter_map(|(index, packed)| Some((Self::local(index), BlockState::unpack(*packed)?)))
+    }
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for Chunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Chunk").field("len", &self.len).finish()
+    }
+}
+
+/// A chunk as it is sent, run-length encoded as how many times each packed block repeats.
+#[derive(Serialize, Deserialize)]
+struct PackedChunk(Vec<(u16, u32)>);
+
+impl From<Chunk> for PackedChunk {
+    fn from(chunk: Chunk) -> Self {
+        let mut runs: Vec<(u16, u32)> = Vec::new();
+        for packed in chunk.blocks {
+            match runs.last_mut() {
+                Some((count, last)) if *last == packed => *count += 1,
+                _ => runs.push((1, packed)),
+            }
+        }
+        Self(runs)
+    }
+}
+
+impl TryFrom<PackedChunk> for Chunk {
+    type Error = &'static str;
+
+    fn try_from(packed: PackedChunk) -> Result<Self, Self::Error> {
+        let mut blocks = Vec::with_capacity(CHUNK_VOLUME);
+        let mut len = 0;
+        for (count, block) in packed.0 {
+            if blocks.len() + count as usize > CHUNK_VOLUME {
+                return Err("chunk has more than 16x16x16 blocks");
+            }
+            if block != EMPTY {
+                BlockState::unpack(block).ok_or("chunk has an invalid block")?;
+                len += count as usize;
+            }
+            blocks.extend(std::iter::repeat(block).take(count as usize));
+        }
+
+        if blocks.len() != CHUNK_VOLUME {
+            return Err("chunk does not have 16x16x16 blocks");
+        }
+        Ok(Self { blocks, len })
+    }
+}
@@ -0,0 +1,51 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// How a faction stands towards another one.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Relation {
+    Allied,
+    Neutral,
+    Hostile,
+}
+
+impl Default for Relation {
+    fn default() -> Self {
+        Relation::Neutral
+    }
+}
+
+impl Display for Relation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Relation::Allied => write!(f, "allied"),
+            Relation::Neutral => write!(f, "neutral"),
+            Relation::Hostile => write!(f, "hostile"),
+        }
+    }
+}
+
+impl FromStr for Relation {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allied" => Ok(Relation::Allied),
+            "neutral" => Ok(Relation::Neutral),
+            "hostile" => Ok(Relation::Hostile),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A faction as clients get to see it, with members by player name.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FactionInfo {
+    pub name: String,
+    pub leader: String,
+    pub members: Vec<String>,
+    /// The stance of this faction towards others, leaving out the neutral ones.
+    pub relations: Vec<(String, Relation)>,
+}
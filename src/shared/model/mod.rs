@@ -1,3 +1,7 @@
 pub mod block;
+pub mod block_definitions;
 pub mod block_map;
+pub mod blueprint;
+pub mod chunk;
+pub mod faction;
 pub mod ship;
@@ -1,3 +1,5 @@
+use std::fmt::Display;
+
 use bevy::prelude::{Bundle, Component, ComputedVisibility, Entity, Visibility};
 use bevy::transform::TransformBundle;
 use bevy_rapier3d::dynamics::Velocity;
@@ -7,28 +9,6 @@ use spacegame_core::message::ClientId;
 
 use crate::model::block_map::BlockMap;
 
-#[derive(Component)]
-pub struct Thrust {
-    pub t: f32,
-}
-
-impl Default for Thrust {
-    fn default() -> Self {
-        Self { t: 1. }
-    }
-}
-
-#[derive(Component)]
-pub struct Gimbal {
-    pub t: f32,
-}
-
-impl Default for Gimbal {
-    fn default() -> Self {
-        Self { t: 0.25 }
-    }
-}
-
 #[derive(Component)]
 pub struct ShipName {
     pub name: String,
@@ -45,6 +25,33 @@ impl Default for ShipName {
 #[derive(Component)]
 pub struct Ship;
 
+/// The name of whoever owns a ship.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum OwnerName {
+    Player(String),
+    Faction(String),
+}
+
+impl Display for OwnerName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OwnerName::Player(name) => write!(f, "{}", name),
+            OwnerName::Faction(name) => write!(f, "faction {}", name),
+        }
+    }
+}
+
+/// Who owns a ship and who it is shared with, by player name.
+///
+/// The server keeps track of owners by identity, this is only what clients get to see.
+#[derive(Component, Serialize, Deserialize, Clone, Default, Debug)]
+pub struct ShipOwnership {
+    /// `None` if the ship belongs to nobody, so everyone can use it.
+    pub owner: Option<OwnerName>,
+    pub builders: Vec<String>,
+    pub pilots: Vec<String>,
+}
+
 #[derive(Component)]
 pub enum Pilot {
     Pilot(ClientId),
@@ -64,8 +71,6 @@ pub struct ShipBundle {
     pub sleeping: Sleeping,
     pub ccd: Ccd,
     pub ship_name: ShipName,
-    pub thrust: Thrust,
-    pub gimbal: Gimbal,
     pub damping: Damping,
     pub impulse: ExternalImpulse,
     pub force: ExternalForce,
@@ -85,8 +90,6 @@ impl Default for ShipBundle {
             sleeping: Sleeping::disabled(),
             ccd: Ccd::enabled(),
             ship_name: ShipName::default(),
-            thrust: Thrust { t: 1000. },
-            gimbal: Gimbal { t: 10. },
             damping: Damping {
                 linear_damping: 1.,
                 angular_damping: 1.,
@@ -0,0 +1,192 @@
+use std::{
+    fmt::Display,
+    fs, io,
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    path::Path,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use bevy::prelude::{info, warn};
+use bevy_renet::renet::{ConnectToken, NETCODE_KEY_BYTES, NETCODE_USER_DATA_BYTES};
+
+use super::user_data::{validate_player_name, ConnectUserData};
+
+/// Seconds a connection without traffic stays open, written into every token.
+const TOKEN_TIMEOUT_SECONDS: i32 = 15;
+
+/// Key shared between the server and the token issuer, used to encrypt connect tokens.
+#[derive(Clone, Copy)]
+pub struct PrivateKey(pub [u8; NETCODE_KEY_BYTES]);
+
+impl PrivateKey {
+    pub fn random() -> Self {
+        Self(rand::random())
+    }
+
+    /// Read the key stored as hex at `path`, generating and storing a new one if there is none yet.
+    pub fn load_or_create(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Self::from_hex(contents.trim())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid private key")),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let key = Self::random();
+                fs::write(path, key.to_hex())?;
+                Ok(key)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn from_hex(hex: &str) -> Option<Self> {
+        if hex.len() != NETCODE_KEY_BYTES * 2 || !hex.is_ascii() {
+            return None;
+        }
+        let mut key = [0u8; NETCODE_KEY_BYTES];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+        }
+        Some(Self(key))
+    }
+
+    fn to_hex(self) -> String {
+        self.0.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    Io(io::Error),
+    InvalidUserData,
+    Token(String),
+}
+
+impl Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthError::Io(e) => write!(f, "{}", e),
+            AuthError::InvalidUserData => write!(f, "invalid identity or name"),
+            AuthError::Token(e) => write!(f, "could not generate connect token: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// Creates connect tokens for the servers sharing its private key.
+///
+/// This stands in for a proper authentication backend: it trusts whatever identity it is asked
+/// for, but it keeps clients from picking their own client id and lets the server run in secure
+/// mode.
+#[derive(Clone)]
+pub struct TokenIssuer {
+    pub private_key: PrivateKey,
+    pub protocol_id: u64,
+    /// Servers the tokens are valid for.
+    pub server_addresses: Vec<SocketAddr>,
+    /// Seconds a token can be used to connect after it was issued.
+    pub expire_seconds: u64,
+}
+
+impl TokenIssuer {
+    pub fn issue(&self, user_data: &ConnectUserData) -> Result<ConnectToken, AuthError> {
+        let current_time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap();
+        let user_data = user_data
+            .to_bytes()
+            .map_err(|_| AuthError::InvalidUserData)?;
+
+        ConnectToken::generate(
+            current_time,
+            self.protocol_id,
+            self.expire_seconds,
+            fastrand::u64(1..u64::MAX),
+            TOKEN_TIMEOUT_SECONDS,
+            self.server_addresses.clone(),
+            Some(&user_data),
+            &self.private_key.0,
+        )
+        .map_err(|e| AuthError::Token(e.to_string()))
+    }
+
+    /// Hand out tokens over TCP on a background thread.
+    ///
+    /// A client sends its [`ConnectUserData`] and gets a connect token back, or the connection is
+    /// closed if the data is invalid.
+    pub fn spawn_service(self, listen_addr: SocketAddr) -> io::Result<()> {
+        let listener = TcpListener::bind(listen_addr)?;
+        info!("Issuing connect tokens on {}", listen_addr);
+
+        thread::Builder::new()
+            .name(String::from("token_issuer"))
+            .spawn(move || self.serve(listener))?;
+        Ok(())
+    }
+
+    pub fn serve(&self, listener: TcpListener) {
+        for stream in listener.incoming() {
+            let result = stream
+                .map_err(AuthError::Io)
+                .and_then(|mut stream| self.handle(&mut stream));
+            if let Err(e) = result {
+                warn!("Could not issue connect token: {}", e);
+            }
+        }
+    }
+
+    fn handle(&self, stream: &mut TcpStream) -> Result<(), AuthError> {
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .map_err(AuthError::Io)?;
+
+        let mut bytes = [0u8; NETCODE_USER_DATA_BYTES];
+        stream.read_exact(&mut bytes).map_err(AuthError::Io)?;
+        let user_data =
+            ConnectUserData::from_bytes(&bytes).map_err(|_| AuthError::InvalidUserData)?;
+        validate_player_name(&user_data.name).map_err(|_| AuthError::InvalidUserData)?;
+
+        let token = self.issue(&user_data)?;
+        info!(
+            "Issued connect token to {} ({})",
+            user_data.name, user_data.identity
+        );
+        token.write(stream).map_err(AuthError::Io)
+    }
+}
+
+/// Ask the token service at `service_addr` for a connect token.
+pub fn request_token(
+    service_addr: impl ToSocketAddrs,
+    user_data: &ConnectUserData,
+    timeout: Duration,
+) -> Result<ConnectToken, AuthError> {
+    let service_addr = service_addr
+        .to_socket_addrs()
+        .map_err(AuthError::Io)?
+        .next()
+        .ok_or_else(|| AuthError::Io(io::ErrorKind::NotFound.into()))?;
+    let mut stream = TcpStream::connect_timeout(&service_addr, timeout).map_err(AuthError::Io)?;
+    stream
+        .set_read_timeout(Some(timeout))
+        .map_err(AuthError::Io)?;
+
+    let bytes = user_data
+        .to_bytes()
+        .map_err(|_| AuthError::InvalidUserData)?;
+    stream.write_all(&bytes).map_err(AuthError::Io)?;
+
+    ConnectToken::read(&mut stream).map_err(AuthError::Io)
+}
+
+/// Read a connect token written by [`write_token`].
+pub fn load_token(path: &Path) -> io::Result<ConnectToken> {
+    let mut file = fs::File::open(path)?;
+    ConnectToken::read(&mut file)
+}
+
+pub fn write_token(path: &Path, token: &ConnectToken) -> io::Result<()> {
+    let mut file = fs::File::create(path)?;
+    token.write(&mut file)
+}
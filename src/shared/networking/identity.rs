@@ -0,0 +1,62 @@
+use std::{fmt::Display, fs, io, path::Path, str::FromStr};
+
+use bevy::prelude::Component;
+use serde::{Deserialize, Serialize};
+
+/// Identifies a player across connections, unlike the client id which is only valid for a
+/// single session.
+#[derive(Component, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct PlayerIdentity(pub u128);
+
+impl PlayerIdentity {
+    pub fn random() -> Self {
+        Self(fastrand::u128(..))
+    }
+
+    /// Read the identity stored at `path`, generating and storing a new one if there is none yet.
+    pub fn load_or_create(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(contents) => contents
+                .parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let identity = Self::random();
+                fs::write(path, identity.to_string())?;
+                Ok(identity)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Display for PlayerIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+impl FromStr for PlayerIdentity {
+    type Err = ParseIdentityError;
+
+    /// Parse an identity from its hex form, as written by [`Display`]: exactly 32 hex digits.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.len() != 32 || !s.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(ParseIdentityError);
+        }
+        u128::from_str_radix(s, 16)
+            .map(Self)
+            .map_err(|_| ParseIdentityError)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseIdentityError;
+
+impl Display for ParseIdentityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "identity must be exactly 32 hex digits")
+    }
+}
+
+impl std::error::Error for ParseIdentityError {}
@@ -1,2 +1,5 @@
+pub mod auth;
+pub mod identity;
 pub mod player_id;
 pub mod plugin;
+pub mod user_data;
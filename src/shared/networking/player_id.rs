@@ -25,4 +25,10 @@ impl PlayerIdMap {
         self.map.insert(client_id, entity);
         self.reverse_map.insert(entity, client_id);
     }
+
+    pub fn remove_client(&mut self, client_id: u64) -> Option<Entity> {
+        let entity = self.map.remove(&client_id)?;
+        self.reverse_map.remove(&entity);
+        Some(entity)
+    }
 }
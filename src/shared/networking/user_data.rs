@@ -0,0 +1,96 @@
+use std::fmt::Display;
+
+use bevy_renet::renet::NETCODE_USER_DATA_BYTES;
+use serde::{Deserialize, Serialize};
+
+use super::identity::PlayerIdentity;
+
+pub const MIN_NAME_LENGTH: usize = 3;
+pub const MAX_NAME_LENGTH: usize = 16;
+/// Long enough for any sensible password while leaving room for the rest of the user data.
+pub const MAX_PASSWORD_LENGTH: usize = 64;
+
+/// Data sent by the client when connecting, packed into the renet user data.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ConnectUserData {
+    /// The [`crate::PROTOCOL_VERSION`] of the client, kept first so that it can still be read
+    /// when the rest of the layout changes.
+    pub version: u32,
+    pub identity: PlayerIdentity,
+    pub name: String,
+    /// Password for servers that require one.
+    pub password: Option<String>,
+}
+
+impl ConnectUserData {
+    pub fn to_bytes(&self) -> Result<[u8; NETCODE_USER_DATA_BYTES], UserDataError> {
+        let data = bincode::serialize(self).map_err(|_| UserDataError::Malformed)?;
+        if data.len() > NETCODE_USER_DATA_BYTES {
+            return Err(UserDataError::TooLarge);
+        }
+
+        let mut bytes = [0u8; NETCODE_USER_DATA_BYTES];
+        bytes[..data.len()].copy_from_slice(&data);
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8; NETCODE_USER_DATA_BYTES]) -> Result<Self, UserDataError> {
+        bincode::deserialize(bytes).map_err(|_| UserDataError::Malformed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserDataError {
+    Malformed,
+    TooLarge,
+}
+
+impl Display for UserDataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UserDataError::Malformed => write!(f, "malformed user data"),
+            UserDataError::TooLarge => write!(f, "user data does not fit in a connect packet"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameError {
+    TooShort,
+    TooLong,
+    InvalidCharacter(char),
+    Taken,
+}
+
+impl Display for NameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NameError::TooShort => {
+                write!(f, "name must be at least {} characters", MIN_NAME_LENGTH)
+            }
+            NameError::TooLong => write!(f, "name must be at most {} characters", MAX_NAME_LENGTH),
+            NameError::InvalidCharacter(c) => write!(f, "name contains invalid character {:?}", c),
+            NameError::Taken => write!(f, "name is already taken"),
+        }
+    }
+}
+
+/// Check that a player name has a valid length and only contains letters, digits, `_` and `-`.
+///
+/// Uniqueness is checked by the server.
+pub fn validate_player_name(name: &str) -> Result<(), NameError> {
+    let length = name.chars().count();
+    if length < MIN_NAME_LENGTH {
+        return Err(NameError::TooShort);
+    }
+    if length > MAX_NAME_LENGTH {
+        return Err(NameError::TooLong);
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || *c == '_' || *c == '-'))
+    {
+        return Err(NameError::InvalidCharacter(c));
+    }
+    Ok(())
+}
@@ -1,12 +1,15 @@
 use bevy::asset::Handle;
-use bevy::prelude::{Mesh, StandardMaterial};
+use bevy::prelude::{Image, Mesh, StandardMaterial};
 use bevy::utils::HashMap;
 
 use crate::model::block::BlockType;
 
+/// Meshes, materials and textures of the block types, registered on the client from the block
+/// definitions.
 pub struct BlockRegistry {
     material_map: HashMap<BlockType, Handle<StandardMaterial>>,
     mesh_map: HashMap<BlockType, Handle<Mesh>>,
+    texture_map: HashMap<BlockType, Handle<Image>>,
 }
 
 impl BlockRegistry {
@@ -14,6 +17,7 @@ impl BlockRegistry {
         Self {
             material_map: HashMap::new(),
             mesh_map: HashMap::new(),
+            texture_map: HashMap::new(),
         }
     }
 
@@ -29,11 +33,20 @@ impl BlockRegistry {
         self.mesh_map.insert(block_type, mesh_handle);
     }
 
-    pub fn get_material(&self, block_type: BlockType) -> Handle<StandardMaterial> {
-        self.material_map.get(&block_type).cloned().unwrap()
+    pub fn register_texture(&mut self, block_type: BlockType, texture_handle: Handle<Image>) {
+        self.texture_map.insert(block_type, texture_handle);
     }
 
-    pub fn get_mesh(&self, block_type: BlockType) -> Handle<Mesh> {
-        self.mesh_map.get(&block_type).cloned().unwrap()
+    pub fn get_material(&self, block_type: BlockType) -> Option<Handle<StandardMaterial>> {
+        self.material_map.get(&block_type).cloned()
+    }
+
+    pub fn get_mesh(&self, block_type: BlockType) -> Option<Handle<Mesh>> {
+        self.mesh_map.get(&block_type).cloned()
+    }
+
+    /// Whether `texture` is the texture of any block type.
+    pub fn is_texture(&self, texture: &Handle<Image>) -> bool {
+        self.texture_map.values().any(|handle| handle == texture)
     }
 }
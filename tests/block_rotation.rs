@@ -0,0 +1,204 @@
+use bevy::prelude::{Quat, Vec3};
+use spacegame::model::block_map::{BlockFace, BlockPosition, BlockRotation};
+
+const EPSILON: f32 = 1e-6;
+
+/// Positions that tell every rotation apart, as no two of their coordinates are the same.
+const POSITIONS: [(i32, i32, i32); 4] = [(1, 2, 3), (-4, 5, -6), (7, -8, 0), (0, 0, 0)];
+
+fn quat(rotation: BlockRotation) -> Quat {
+    rotation.into()
+}
+
+fn same_rotation(a: Quat, b: Quat) -> bool {
+    // q and -q are the same rotation
+    a.dot(b).abs() > 1. - EPSILON
+}
+
+fn vec(position: BlockPosition) -> Vec3 {
+    position.into()
+}
+
+#[test]
+fn there_are_24_distinct_rotations() {
+    let rotations: Vec<BlockRotation> = BlockRotation::all().collect();
+    assert_eq!(rotations.len(), 24);
+
+    for (i, a) in rotations.iter().enumerate() {
+        for b in &rotations[i + 1..] {
+            assert!(!same_rotation(quat(*a), quat(*b)), "{:?} == {:?}", a, b);
+        }
+    }
+}
+
+#[test]
+fn identity_is_the_default() {
+    assert_eq!(BlockRotation::default(), BlockRotation::IDENTITY);
+    assert_eq!(BlockRotation::IDENTITY.index(), 0);
+    assert!(same_rotation(quat(BlockRotation::IDENTITY), Quat::IDENTITY));
+    assert_eq!(BlockRotation::from(Quat::IDENTITY), BlockRotation::IDENTITY);
+}
+
+#[test]
+fn quats_are_exact() {
+    for rotation in BlockRotation::all() {
+        let q = quat(rotation);
+        assert!(q.is_normalized(), "{:?}", rotation);
+        for face in BlockFace::ALL {
+            let rotated = q * vec(face.normal());
+            let expected = vec(rotation.rotate_face(face).normal());
+            assert!(
+                rotated.abs_diff_eq(expected, EPSILON),
+                "{:?} turns {:?} to {} instead of {}",
+                rotation,
+                face,
+                rotated,
+                expected
+            );
+        }
+    }
+}
+
+#[test]
+fn quat_round_trips() {
+    for rotation in BlockRotation::all() {
+        assert_eq!(BlockRotation::from(quat(rotation)), rotation);
+        assert_eq!(BlockRotation::from(-quat(rotation)), rotation);
+    }
+}
+
+#[test]
+fn quats_snap_to_the_closest_rotation() {
+    let nudge = Quat::from_euler(bevy::prelude::EulerRot::XYZ, 0.2, -0.3, 0.1);
+    for rotation in BlockRotation::all() {
+        assert_eq!(BlockRotation::from(quat(rotation) * nudge), rotation);
+        assert_eq!(BlockRotation::from(nudge * quat(rotation)), rotation);
+    }
+}
+
+#[test]
+fn rotating_positions_matches_quats() {
+    for rotation in BlockRotation::all() {
+        for (x, y, z) in POSITIONS {
+            let position = BlockPosition::new(x, y, z);
+            let rotated = rotation.rotate_position(position);
+            assert!(
+                vec(rotated).abs_diff_eq(quat(rotation) * vec(position), EPSILON),
+                "{:?} rotated {:?} to {:?}",
+                rotation,
+                position,
+                rotated
+            );
+        }
+    }
+}
+
+#[test]
+fn rotating_faces_matches_positions() {
+    for rotation in BlockRotation::all() {
+        for face in BlockFace::ALL {
+            assert_eq!(
+                rotation.rotate_face(face).normal(),
+                rotation.rotate_position(face.normal())
+            );
+            assert_eq!(
+                rotation.rotate_face(face.opposite()),
+                rotation.rotate_face(face).opposite()
+            );
+        }
+    }
+}
+
+#[test]
+fn axes_define_the_rotation() {
+    for rotation in BlockRotation::all() {
+        assert_eq!(
+            rotation.x_axis(),
+            rotation.rotate_face(BlockFace::PositiveX)
+        );
+        assert_eq!(
+            rotation.y_axis(),
+            rotation.rotate_face(BlockFace::PositiveY)
+        );
+        assert_eq!(
+            rotation.z_axis(),
+            rotation.rotate_face(BlockFace::PositiveZ)
+        );
+        assert_eq!(
+            BlockRotation::from_axes(rotation.x_axis(), rotation.y_axis()),
+            Some(rotation)
+        );
+    }
+
+    for face in BlockFace::ALL {
+        assert_eq!(BlockRotation::from_axes(face, face), None);
+        assert_eq!(BlockRotation::from_axes(face, face.opposite()), None);
+    }
+}
+
+#[test]
+fn compose_matches_quats() {
+    for a in BlockRotation::all() {
+        for b in BlockRotation::all() {
+            let composed = a.compose(b);
+            assert!(
+                same_rotation(quat(composed), quat(a) * quat(b)),
+                "{:?} after {:?} is not {:?}",
+                a,
+                b,
+                composed
+            );
+            for (x, y, z) in POSITIONS {
+                let position = BlockPosition::new(x, y, z);
+                assert_eq!(
+                    composed.rotate_position(position),
+                    a.rotate_position(b.rotate_position(position))
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn compose_is_associative_with_identity() {
+    for a in BlockRotation::all() {
+        assert_eq!(a.compose(BlockRotation::IDENTITY), a);
+        assert_eq!(BlockRotation::IDENTITY.compose(a), a);
+        for b in BlockRotation::all() {
+            for c in BlockRotation::all() {
+                assert_eq!(a.compose(b).compose(c), a.compose(b.compose(c)));
+            }
+        }
+    }
+}
+
+#[test]
+fn inverse_undoes_the_rotation() {
+    for rotation in BlockRotation::all() {
+        let inverse = rotation.inverse();
+        assert_eq!(rotation.compose(inverse), BlockRotation::IDENTITY);
+        assert_eq!(inverse.compose(rotation), BlockRotation::IDENTITY);
+        assert_eq!(inverse.inverse(), rotation);
+        assert!(same_rotation(quat(inverse), quat(rotation).inverse()));
+        for (x, y, z) in POSITIONS {
+            let position = BlockPosition::new(x, y, z);
+            assert_eq!(
+                inverse.rotate_position(rotation.rotate_position(position)),
+                position
+            );
+        }
+    }
+}
+
+#[test]
+fn serializes_as_index() {
+    for rotation in BlockRotation::all() {
+        let serialized = ron::to_string(&rotation).unwrap();
+        assert_eq!(serialized, rotation.index().to_string());
+        assert_eq!(
+            ron::from_str::<BlockRotation>(&serialized).unwrap(),
+            rotation
+        );
+    }
+    assert!(ron::from_str::<BlockRotation>("24").is_err());
+}
@@ -0,0 +1,182 @@
+use bevy::prelude::Vec3;
+use spacegame::{
+    client::chunk_mesh::{mesh_chunk, ChunkMesh},
+    model::{
+        block::BlockType,
+        block_map::{BlockMap, BlockPosition, BlockRotation},
+        chunk::ChunkPosition,
+    },
+};
+
+const ENGINE: BlockType = BlockType(2);
+/// A block type that doesn't fill its cell, so it is neither meshed nor hides anything.
+const BALL: BlockType = BlockType(5);
+
+fn is_full_cube(block_type: BlockType) -> bool {
+    block_type != BALL
+}
+
+fn block_map(blocks: &[(i32, i32, i32, BlockType)]) -> BlockMap {
+    let mut block_map = BlockMap::new();
+    for &(x, y, z, block_type) in blocks {
+        block_map.set(
+            BlockPosition::new(x, y, z),
+            block_type,
+            BlockRotation::IDENTITY,
+        );
+    }
+    block_map
+}
+
+fn mesh(block_map: &BlockMap, chunk: ChunkPosition, block_type: BlockType) -> ChunkMesh {
+    mesh_chunk(block_map, chunk, is_full_cube)
+        .remove(&block_type)
+        .unwrap_or_default()
+}
+
+fn origin() -> ChunkPosition {
+    ChunkPosition::new(0, 0, 0)
+}
+
+fn assert_counts(mesh: &ChunkMesh, faces: usize) {
+    assert_eq!(mesh.face_count(), faces);
+    assert_eq!(mesh.vertex_count(), faces * 4);
+    assert_eq!(mesh.indices.len(), faces * 6);
+    assert_eq!(mesh.normals.len(), mesh.vertex_count());
+    assert_eq!(mesh.uvs.len(), mesh.vertex_count());
+}
+
+#[test]
+fn single_block_has_six_faces() {
+    let block_map = block_map(&[(3, 4, 5, BlockType::HULL)]);
+    assert_counts(&mesh(&block_map, origin(), BlockType::HULL), 6);
+}
+
+#[test]
+fn empty_chunk_has_no_meshes() {
+    let block_map = block_map(&[(3, 4, 5, BlockType::HULL)]);
+    assert!(mesh_chunk(&block_map, ChunkPosition::new(1, 0, 0), is_full_cube).is_empty());
+}
+
+#[test]
+fn row_of_blocks_is_merged() {
+    let block_map = block_map(&[
+        (0, 0, 0, BlockType::HULL),
+        (1, 0, 0, BlockType::HULL),
+        (2, 0, 0, BlockType::HULL),
+    ]);
+    assert_counts(&mesh(&block_map, origin(), BlockType::HULL), 6);
+}
+
+#[test]
+fn full_chunk_is_a_box() {
+    let mut blocks = Vec::new();
+    for x in 0..16 {
+        for y in 0..16 {
+            for z in 0..16 {
+                blocks.push((x, y, z, BlockType::HULL));
+            }
+        }
+    }
+    let block_map = block_map(&blocks);
+    assert_counts(&mesh(&block_map, origin(), BlockType::HULL), 6);
+}
+
+#[test]
+fn l_shape_needs_more_faces() {
+    // The L shaped sides need two rectangles each, as do the sides facing +X and +Y, which are
+    // split over two layers
+    let block_map = block_map(&[
+        (0, 0, 0, BlockType::HULL),
+        (1, 0, 0, BlockType::HULL),
+        (0, 1, 0, BlockType::HULL),
+    ]);
+    assert_counts(&mesh(&block_map, origin(), BlockType::HULL), 10);
+}
+
+#[test]
+fn different_types_are_not_merged_but_hide_each_other() {
+    let block_map = block_map(&[(0, 0, 0, BlockType::HULL), (1, 0, 0, ENGINE)]);
+    let meshes = mesh_chunk(&block_map, origin(), is_full_cube);
+    assert_eq!(meshes.len(), 2);
+    assert_counts(&meshes[&BlockType::HULL], 5);
+    assert_counts(&meshes[&ENGINE], 5);
+}
+
+#[test]
+fn diagonal_blocks_hide_nothing() {
+    let block_map = block_map(&[(0, 0, 0, BlockType::HULL), (1, 1, 0, BlockType::HULL)]);
+    assert_counts(&mesh(&block_map, origin(), BlockType::HULL), 12);
+}
+
+#[test]
+fn faces_are_hidden_across_chunks() {
+    let block_map = block_map(&[(15, 0, 0, BlockType::HULL), (16, 0, 0, BlockType::HULL)]);
+    assert_counts(&mesh(&block_map, origin(), BlockType::HULL), 5);
+    assert_counts(
+        &mesh(&block_map, ChunkPosition::new(1, 0, 0), BlockType::HULL),
+        5,
+    );
+}
+
+#[test]
+fn negative_chunks_are_meshed() {
+    let block_map = block_map(&[(-1, -1, -1, BlockType::HULL), (-2, -1, -1, BlockType::HULL)]);
+    let chunk = ChunkPosition::new(-1, -1, -1);
+    assert_counts(&mesh(&block_map, chunk, BlockType::HULL), 6);
+}
+
+#[test]
+fn partial_blocks_are_skipped_and_hide_nothing() {
+    let block_map = block_map(&[(0, 0, 0, BlockType::HULL), (1, 0, 0, BALL)]);
+    let meshes = mesh_chunk(&block_map, origin(), is_full_cube);
+    assert!(!meshes.contains_key(&BALL));
+    assert_counts(&meshes[&BlockType::HULL], 6);
+}
+
+#[test]
+fn faces_point_outwards() {
+    let block_map = block_map(&[
+        (0, 0, 0, BlockType::HULL),
+        (1, 0, 0, BlockType::HULL),
+        (0, 0, 1, BlockType::HULL),
+    ]);
+    let mesh = mesh(&block_map, origin(), BlockType::HULL);
+    let center = Vec3::new(1. / 3., 0., 1. / 3.);
+
+    for triangle in mesh.indices.chunks(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| Vec3::from(mesh.positions[triangle[i] as usize]));
+        let normal = Vec3::from(mesh.normals[triangle[0] as usize]);
+        let winding = (b - a).cross(c - a).normalize();
+
+        assert!(
+            winding.abs_diff_eq(normal, 1e-6),
+            "{} != {}",
+            winding,
+            normal
+        );
+        assert!((a - center).dot(normal) > 0.);
+    }
+}
+
+#[test]
+fn faces_cover_the_whole_surface() {
+    let block_map = block_map(&[
+        (0, 0, 0, BlockType::HULL),
+        (1, 0, 0, BlockType::HULL),
+        (2, 0, 0, BlockType::HULL),
+        (0, 1, 0, BlockType::HULL),
+    ]);
+    let mesh = mesh(&block_map, origin(), BlockType::HULL);
+
+    let area: f32 = mesh
+        .indices
+        .chunks(3)
+        .map(|triangle| {
+            let [a, b, c] = [0, 1, 2].map(|i| Vec3::from(mesh.positions[triangle[i] as usize]));
+            (b - a).cross(c - a).length() / 2.
+        })
+        .sum();
+    // 4 blocks with 6 sides each, minus the 3 pairs of sides touching each other
+    assert!((area - 18.).abs() < 1e-4, "{}", area);
+}
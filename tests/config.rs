@@ -0,0 +1,38 @@
+use std::net::SocketAddr;
+
+use spacegame::{client::config::ClientSettings, server::config::ServerSettings};
+
+#[test]
+fn default_client_gets_tokens_from_default_server() {
+    let server = ServerSettings::default();
+    let client = ClientSettings::default();
+
+    // Neither side skips authentication, so a token is needed
+    assert!(!server.unsecure && !client.unsecure);
+    assert!(client.token_file.is_none());
+    assert!(!server.external_token_service);
+
+    let token_service = server
+        .token_service
+        .expect("the default server runs no token service");
+    let asked: SocketAddr = client
+        .token_service_addr(server.public_addr())
+        .parse()
+        .unwrap();
+    assert_eq!(asked.port(), token_service.port());
+    assert!(token_service.ip().is_unspecified() || token_service.ip() == asked.ip());
+}
+
+#[test]
+fn backoff_doubles_after_the_first_attempt() {
+    let client = ClientSettings {
+        retry_backoff: 1.,
+        max_retry_backoff: 5.,
+        ..Default::default()
+    };
+
+    let waits: Vec<f32> = (0..5)
+        .map(|attempt| client.backoff(attempt).as_secs_f32())
+        .collect();
+    assert_eq!(waits, vec![0., 1., 2., 4., 5.]);
+}
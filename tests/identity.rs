@@ -0,0 +1,15 @@
+use spacegame::networking::identity::PlayerIdentity;
+
+#[test]
+fn identity_round_trips() {
+    let identity = PlayerIdentity(0x2a);
+    assert_eq!(identity.to_string().parse(), Ok(identity));
+}
+
+#[test]
+fn identity_needs_32_hex_digits() {
+    assert!("2a".parse::<PlayerIdentity>().is_err());
+    assert!(format!("{:033x}", 0x2a).parse::<PlayerIdentity>().is_err());
+    assert!(format!("+{:031x}", 0x2a).parse::<PlayerIdentity>().is_err());
+    assert!("g".repeat(32).parse::<PlayerIdentity>().is_err());
+}
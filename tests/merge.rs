@@ -0,0 +1,33 @@
+use bevy::prelude::{Quat, Transform};
+use spacegame::{
+    model::block_map::{BlockBounds, BlockPosition},
+    server::merge::GridAlignment,
+};
+
+fn bounds(min: (i32, i32, i32), max: (i32, i32, i32)) -> BlockBounds {
+    BlockBounds {
+        min: BlockPosition::new(min.0, min.1, min.2),
+        max: BlockPosition::new(max.0, max.1, max.2),
+    }
+}
+
+#[test]
+fn bounds_next_to_each_other_are_within_one_block() {
+    let a = bounds((0, 0, 0), (2, 2, 2));
+    assert!(a.is_within(&bounds((3, 0, 0), (5, 1, 1)), 1));
+    assert!(a.is_within(&bounds((1, 1, 1), (1, 1, 1)), 0));
+    assert!(!a.is_within(&bounds((4, 0, 0), (5, 1, 1)), 1));
+    assert!(!a.is_within(&bounds((0, -3, 0), (0, -2, 0)), 1));
+}
+
+#[test]
+fn rotated_bounds_stay_a_box() {
+    let target = Transform::default();
+    let other = Transform::from_xyz(10., 0., 0.)
+        .with_rotation(Quat::from_rotation_z(std::f32::consts::FRAC_PI_2));
+    let alignment = GridAlignment::between(&target, &other).unwrap();
+
+    // A quarter turn around z takes x to y and y to -x
+    let moved = alignment.bounds(bounds((0, 0, 0), (3, 1, 0)));
+    assert_eq!(moved, bounds((9, 0, 0), (10, 3, 0)));
+}
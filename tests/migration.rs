@@ -0,0 +1,197 @@
+use std::path::{Path, PathBuf};
+
+use spacegame::{
+    migration::{load_versioned, MigrationError},
+    model::{
+        block::BlockType,
+        block_map::{BlockPosition, BlockRotation},
+        blueprint::{Blueprint, BlueprintError, BLUEPRINT_VERSION},
+        faction::Relation,
+    },
+    networking::identity::PlayerIdentity,
+    server::world::{SavedIdentity, SavedOwner, WorldSave, WORLD_VERSION},
+};
+
+fn fixtures() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+fn assert_corvette(blueprint: &Blueprint) {
+    assert_eq!(blueprint.name, "Corvette");
+    assert_eq!(blueprint.blocks.len(), 3);
+    assert!(blueprint
+        .blocks
+        .iter()
+        .all(|block| block.block_type == BlockType::HULL));
+    assert_eq!(blueprint.blocks[1].position, BlockPosition::new(1, 0, 0));
+}
+
+/// Rotations from before version 3 never had an effect, so they all become the identity.
+fn assert_unrotated(blueprint: &Blueprint) {
+    assert!(blueprint
+        .blocks
+        .iter()
+        .all(|block| block.rotation == BlockRotation::IDENTITY));
+}
+
+#[test]
+fn blueprint_v0_is_migrated() {
+    let blueprint = Blueprint::load(&fixtures(), "blueprint_v0").unwrap();
+    assert_corvette(&blueprint);
+    assert_unrotated(&blueprint);
+}
+
+#[test]
+fn blueprint_v1_is_migrated() {
+    let blueprint = Blueprint::load(&fixtures(), "blueprint_v1").unwrap();
+    assert_corvette(&blueprint);
+    assert_unrotated(&blueprint);
+}
+
+#[test]
+fn blueprint_v2_is_migrated() {
+    let blueprint = Blueprint::load(&fixtures(), "blueprint_v2").unwrap();
+    assert_corvette(&blueprint);
+    assert_unrotated(&blueprint);
+}
+
+#[test]
+fn blueprint_v3_loads() {
+    assert_eq!(BLUEPRINT_VERSION, 3);
+    let blueprint = Blueprint::load(&fixtures(), "blueprint_v3").unwrap();
+    assert_corvette(&blueprint);
+    assert_eq!(blueprint.blocks[0].rotation, BlockRotation::IDENTITY);
+    assert_eq!(blueprint.blocks[2].rotation.index(), 5);
+}
+
+#[test]
+fn invalid_rotation_is_rejected() {
+    let contents = format!(
+        r#"(version: {}, name: "Corvette", blocks: [(block_type: 1, position: (x: 0, y: 0, z: 0), rotation: 24)])"#,
+        BLUEPRINT_VERSION
+    );
+    assert!(matches!(
+        load_versioned::<Blueprint>(&contents),
+        Err(MigrationError::Parse(_))
+    ));
+}
+
+#[test]
+fn blueprint_from_the_future_is_rejected() {
+    let contents = format!(
+        r#"(version: {}, name: "Corvette", blocks: [])"#,
+        BLUEPRINT_VERSION + 1
+    );
+    let result = load_versioned::<Blueprint>(&contents);
+    assert!(matches!(
+        result,
+        Err(MigrationError::UnsupportedVersion { found, supported })
+            if found == BLUEPRINT_VERSION + 1 && supported == BLUEPRINT_VERSION
+    ));
+}
+
+#[test]
+fn missing_blueprint_is_an_io_error() {
+    let result = Blueprint::load(&fixtures(), "does_not_exist");
+    assert!(matches!(result, Err(BlueprintError::Io(_))));
+}
+
+fn assert_world(world: &WorldSave) {
+    let alice = SavedIdentity(PlayerIdentity(0x2a));
+
+    assert_eq!(world.version, WORLD_VERSION);
+    assert_eq!(world.ships.len(), 2);
+
+    let corvette = &world.ships[0];
+    assert_eq!(corvette.name, "Corvette");
+    assert_eq!(corvette.blocks.len(), 2);
+    assert!(corvette
+        .blocks
+        .iter()
+        .all(|block| block.block_type == BlockType::HULL));
+    assert_eq!(corvette.transform.translation.x, 10.);
+    assert_eq!(corvette.velocity.linvel.z, 1.5);
+    assert_eq!(corvette.owner, SavedOwner::Player(alice));
+    assert_eq!(corvette.builders, vec![SavedIdentity(PlayerIdentity(0xff))]);
+    assert_eq!(corvette.pilot, Some(alice));
+
+    // Shared rights and the pilot are optional
+    let derelict = &world.ships[1];
+    assert_eq!(derelict.owner, SavedOwner::None);
+    assert!(derelict.builders.is_empty());
+    assert_eq!(derelict.pilot, None);
+
+    assert_eq!(world.players.len(), 1);
+    assert_eq!(world.players[0].identity, alice);
+    assert_eq!(world.players[0].name, "alice");
+}
+
+fn assert_world_unrotated(world: &WorldSave) {
+    assert!(world
+        .ships
+        .iter()
+        .flat_map(|ship| &ship.blocks)
+        .all(|block| block.rotation == BlockRotation::IDENTITY));
+}
+
+#[test]
+fn world_v1_is_migrated() {
+    let world = WorldSave::load(&fixtures().join("world_v1.ron"))
+        .unwrap()
+        .unwrap();
+    assert_world(&world);
+    assert_world_unrotated(&world);
+}
+
+#[test]
+fn world_v2_is_migrated() {
+    let world = WorldSave::load(&fixtures().join("world_v2.ron"))
+        .unwrap()
+        .unwrap();
+    assert_world(&world);
+    assert_world_unrotated(&world);
+}
+
+#[test]
+fn world_v3_is_migrated() {
+    let world = WorldSave::load(&fixtures().join("world_v3.ron"))
+        .unwrap()
+        .unwrap();
+    assert_world(&world);
+    assert_eq!(world.ships[0].blocks[0].rotation, BlockRotation::IDENTITY);
+    assert_eq!(world.ships[0].blocks[1].rotation.index(), 7);
+    // Factions were kept in their own file back then
+    assert!(world.factions.is_empty());
+}
+
+#[test]
+fn world_v4_loads() {
+    assert_eq!(WORLD_VERSION, 4);
+    let world = WorldSave::load(&fixtures().join("world_v4.ron"))
+        .unwrap()
+        .unwrap();
+    assert_world(&world);
+    assert_eq!(world.ships[0].blocks[1].rotation.index(), 7);
+
+    let traders = &world.factions["Traders"];
+    assert_eq!(traders.leader, SavedIdentity(PlayerIdentity(0x2a)));
+    assert_eq!(traders.members.len(), 2);
+    assert!(traders.invites.is_empty());
+    assert_eq!(traders.relations["Pirates"], Relation::Hostile);
+}
+
+#[test]
+fn missing_world_is_a_new_world() {
+    let world = WorldSave::load(&fixtures().join("does_not_exist.ron")).unwrap();
+    assert!(world.is_none());
+}
+
+#[test]
+fn world_from_the_future_is_rejected() {
+    let contents = format!("(version: {}, ships: [], players: [])", WORLD_VERSION + 1);
+    let result = load_versioned::<WorldSave>(&contents);
+    assert!(matches!(
+        result,
+        Err(MigrationError::UnsupportedVersion { .. })
+    ));
+}
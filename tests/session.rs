@@ -0,0 +1,70 @@
+use bevy::prelude::Entity;
+use spacegame::{
+    entities::player::PlayerClientId,
+    networking::identity::PlayerIdentity,
+    server::session::{previous_session, DisconnectedPlayers, PreviousSession},
+};
+
+const ALICE: PlayerIdentity = PlayerIdentity(0x2a);
+const BOB: PlayerIdentity = PlayerIdentity(0xff);
+
+fn players() -> Vec<(Entity, PlayerIdentity, PlayerClientId)> {
+    vec![
+        (Entity::from_raw(1), ALICE, PlayerClientId(10)),
+        (Entity::from_raw(2), BOB, PlayerClientId(20)),
+    ]
+}
+
+fn find(
+    disconnected_players: &mut DisconnectedPlayers,
+    players: &[(Entity, PlayerIdentity, PlayerClientId)],
+    identity: &PlayerIdentity,
+) -> Option<PreviousSession> {
+    previous_session(
+        disconnected_players,
+        players
+            .iter()
+            .map(|(entity, identity, client_id)| (*entity, identity, client_id)),
+        identity,
+    )
+}
+
+#[test]
+fn new_identity_has_no_session() {
+    let mut disconnected_players = DisconnectedPlayers::new();
+    let identity = PlayerIdentity(0x7);
+    assert_eq!(find(&mut disconnected_players, &players(), &identity), None);
+}
+
+#[test]
+fn disconnected_player_is_resumed() {
+    let mut disconnected_players = DisconnectedPlayers::new();
+    disconnected_players.insert(BOB, Entity::from_raw(2));
+
+    let session = find(&mut disconnected_players, &players(), &BOB);
+    assert_eq!(
+        session,
+        Some(PreviousSession {
+            player_entity: Entity::from_raw(2),
+            old_client_id: 20,
+            still_connected: false,
+        })
+    );
+    assert_eq!(disconnected_players.take(&BOB), None);
+}
+
+#[test]
+fn online_player_is_taken_over() {
+    // The old client crashed, but the server has not noticed yet
+    let mut disconnected_players = DisconnectedPlayers::new();
+
+    let session = find(&mut disconnected_players, &players(), &ALICE);
+    assert_eq!(
+        session,
+        Some(PreviousSession {
+            player_entity: Entity::from_raw(1),
+            old_client_id: 10,
+            still_connected: true,
+        })
+    );
+}